@@ -1,6 +1,18 @@
+use std::path::PathBuf;
+
 use clap::{Parser, Subcommand};
 
-use crate::{consul_remote::ConsulCommandConfig, etcd_remote::EtcdCommandConfig};
+use crate::{
+    consul_remote::ConsulCommandConfig,
+    etcd_remote::EtcdCommandConfig,
+    kv_commons::ValueEncoding,
+    kv_diff::DiffSide,
+    kv_dump::DumpFormat,
+    kv_uri::KVUri,
+    output::OutputFormat,
+    utils::scope_path,
+    vault_remote::{VaultCommandConfig, VaultSubs},
+};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -10,6 +22,14 @@ pub struct Cli {
     /// Set application log level
     pub log_level: String,
 
+    #[arg(long = "output", global = true, value_enum, default_value_t = OutputFormat::Raw)]
+    /// Format of command results and errors
+    pub output: OutputFormat,
+
+    #[arg(long = "profile", env = "KIVI_PROFILE", global = true)]
+    /// Connection profile from ~/.config/kivi/config.toml. Flags given on the command line override its values
+    pub profile: Option<String>,
+
     #[command(subcommand)]
     pub command: Option<Subs>,
 }
@@ -19,6 +39,10 @@ pub struct Cli {
 pub enum Subs {
     Consul(ConsulCommandConfig),
     Etcd(EtcdCommandConfig),
+    Vault(VaultCommandConfig),
+    Copy(CopyCmdConfig),
+    Diff(DiffCmdConfig),
+    Render(RemoteRenderCmdConfig),
 }
 
 #[derive(Subcommand, Debug)]
@@ -27,20 +51,113 @@ pub enum KVSubs {
     Read(ReadCmdConfig),
     Write(WriteCmdConfig),
     List(ListCmdConfig),
+    Delete(DeleteCmdConfig),
+    Tree(TreeCmdConfig),
+    Export(ExportCmdConfig),
+    Import(ImportCmdConfig),
+    Apply(ApplyCmdConfig),
+    Watch(WatchCmdConfig),
+    Exec(ExecCmdConfig),
+    Render(RenderCmdConfig),
+    Browse(BrowseCmdConfig),
+    Shell(ShellCmdConfig),
+}
+
+impl Subs {
+    /// Command keeps reading from remote until interrupted, so reads must not time out.
+    pub fn is_streaming(&self) -> bool {
+        let kv_command = match self {
+            Subs::Consul(cfg) => cfg.kv_command.as_ref(),
+            Subs::Etcd(cfg) => cfg.kv_command.as_ref(),
+            Subs::Vault(cfg) => match &cfg.kv_command {
+                Some(VaultSubs::Kv(kv_command)) => Some(kv_command),
+                _ => None,
+            },
+            Subs::Render(render_cfg) => return render_cfg.render.watch,
+            _ => None,
+        };
+        return match kv_command {
+            Some(KVSubs::Watch(_)) => true,
+            Some(KVSubs::Exec(exec_cmd)) => exec_cmd.watch,
+            Some(KVSubs::Render(render_cmd)) => render_cmd.watch,
+            _ => false,
+        };
+    }
+}
+
+impl KVSubs {
+    /**
+    Resolve key paths and prefixes of the command against the prefix, see [`scope_path`].
+    Keys used by templates are taken as written.
+
+    Examples:
+
+    ```
+    use kivi_rs::cli_def::{KVSubs, ListCmdConfig};
+
+    let mut command = KVSubs::List(ListCmdConfig { prefix: "svc".to_owned() });
+    command.scope("team-a");
+    assert!(matches!(command, KVSubs::List(list_cmd) if list_cmd.prefix == "team-a/svc"));
+    ```
+    */
+    pub fn scope(&mut self, prefix: &str) {
+        if prefix.is_empty() {
+            return;
+        }
+        let scope = |path: &mut String| *path = scope_path(prefix, path);
+        match self {
+            KVSubs::Read(cmd) => scope(&mut cmd.path),
+            KVSubs::Write(cmd) => scope(&mut cmd.path),
+            KVSubs::List(cmd) => scope(&mut cmd.prefix),
+            KVSubs::Delete(cmd) => scope(&mut cmd.path),
+            KVSubs::Tree(cmd) => scope(&mut cmd.prefix),
+            KVSubs::Export(cmd) => scope(&mut cmd.prefix),
+            KVSubs::Import(cmd) => scope(cmd.prefix.get_or_insert_with(String::new)),
+            KVSubs::Apply(cmd) => scope(&mut cmd.prefix),
+            KVSubs::Watch(cmd) => scope(&mut cmd.prefix),
+            KVSubs::Exec(cmd) => scope(&mut cmd.prefix),
+            KVSubs::Render(_) => {}
+            KVSubs::Browse(cmd) => scope(&mut cmd.prefix),
+            KVSubs::Shell(cmd) => scope(&mut cmd.prefix),
+        }
+    }
 }
 
 #[derive(Parser, Clone, Debug)]
 /// Read value under storage path
 pub struct ReadCmdConfig {
-    #[arg(short = 'e', long = "encoded", action)]
+    #[arg(short = 'e', long = "encoded", action, conflicts_with = "is_hex")]
     /// encode value as base64 string
     pub is_encoded: bool,
 
+    #[arg(long = "hex", action)]
+    /// encode value as hex string
+    pub is_hex: bool,
+
+    #[arg(long = "out")]
+    /// write exact value bytes to the file instead of printing them
+    pub out_file: Option<String>,
+
+    #[arg(long = "version")]
+    /// value version to read: Vault KV v2 version or etcd revision. Consul does not support it
+    pub version: Option<u64>,
+
     #[arg()]
     /// value path
     pub path: String,
 }
 
+impl ReadCmdConfig {
+    /// Encoding requested for the read value.
+    pub fn encoding(&self) -> ValueEncoding {
+        return match (self.is_encoded, self.is_hex) {
+            (true, _) => ValueEncoding::Base64,
+            (_, true) => ValueEncoding::Hex,
+            _ => ValueEncoding::Utf8,
+        };
+    }
+}
+
 #[derive(Parser, Clone, Debug)]
 /// Write value under storage path
 pub struct WriteCmdConfig {
@@ -49,7 +166,7 @@ pub struct WriteCmdConfig {
     pub is_inline_edit: bool,
 
     #[arg(short = 'd', long = "data")]
-    /// File content to write. Ignored if 'inline' write
+    /// File content to write as is, binary files included. Ignored if 'inline' write
     pub data_file: Option<String>,
 
     #[arg()]
@@ -57,6 +174,23 @@ pub struct WriteCmdConfig {
     pub path: String,
 }
 
+#[derive(Parser, Clone, Debug)]
+/// Delete value under storage path. Asks for confirmation before anything is removed.
+/// Vault KV v2 secrets are soft deleted: their metadata is kept, so they are still listed
+pub struct DeleteCmdConfig {
+    #[arg(short = 'r', long = "recurse", action)]
+    /// delete every key under the prefix
+    pub is_recursive: bool,
+
+    #[arg(short = 'y', long = "yes", action)]
+    /// skip confirmation prompt
+    pub assume_yes: bool,
+
+    #[arg()]
+    /// value path or prefix
+    pub path: String,
+}
+
 #[derive(Parser, Clone, Debug)]
 /// List all prefix child nodes
 pub struct ListCmdConfig {
@@ -64,3 +198,207 @@ pub struct ListCmdConfig {
     /// target prefix
     pub prefix: String,
 }
+
+#[derive(Parser, Clone, Debug)]
+/// Show whole prefix subtree with value sizes
+pub struct TreeCmdConfig {
+    #[arg(short = 'd', long = "depth")]
+    /// number of levels to show. Deeper nodes are collapsed
+    pub depth: Option<usize>,
+
+    #[arg()]
+    /// target prefix
+    pub prefix: String,
+}
+
+#[derive(Parser, Clone, Debug)]
+/// Export whole prefix subtree as a JSON dump
+pub struct ExportCmdConfig {
+    #[arg(short = 'f', long = "format", value_enum, default_value_t = DumpFormat::Consul)]
+    /// dump format. 'consul' can be loaded with `consul kv import`, 'kivi' keeps backend metadata
+    pub format: DumpFormat,
+
+    #[arg(short = 'o', long = "out")]
+    /// file to write the dump to. Dump is printed when omitted
+    pub out_file: Option<String>,
+
+    #[arg()]
+    /// target prefix
+    pub prefix: String,
+}
+
+#[derive(Parser, Clone, Debug)]
+/// Import values from a JSON dump. Shows keys to create or change and asks for confirmation before writing
+pub struct ImportCmdConfig {
+    #[arg(long = "prefix")]
+    /// prefix prepended to every imported key
+    pub prefix: Option<String>,
+
+    #[arg(long = "dry-run", action)]
+    /// only show what would be written
+    pub dry_run: bool,
+
+    #[arg(short = 'y', long = "yes", action)]
+    /// skip confirmation prompt
+    pub assume_yes: bool,
+
+    #[arg()]
+    /// dump file in 'consul' or 'kivi' export format
+    pub file: String,
+}
+
+#[derive(Parser, Clone, Debug)]
+/// Make prefix mirror a local directory: every file is written under its relative path.
+/// Shows the plan and asks for confirmation before writing
+pub struct ApplyCmdConfig {
+    #[arg(long = "prefix", default_value_t = String::new())]
+    /// prefix the directory is mapped to, e.g. svc/meta
+    pub prefix: String,
+
+    #[arg(long = "prune", action)]
+    /// delete keys under the prefix that have no file
+    pub prune: bool,
+
+    #[arg(long = "dry-run", action)]
+    /// only show what would be written
+    pub dry_run: bool,
+
+    #[arg(short = 'y', long = "yes", action)]
+    /// skip confirmation prompt
+    pub assume_yes: bool,
+
+    #[arg()]
+    /// directory with one file per key, hidden files are skipped
+    pub dir: PathBuf,
+}
+
+#[derive(Parser, Clone, Debug)]
+/// Print every change under the prefix with old and new value until interrupted
+pub struct WatchCmdConfig {
+    #[arg(long = "exec")]
+    /// shell command to run after each change, e.g. to reload a sidecar.
+    /// Change is passed in KIVI_WATCH_PATH, KIVI_WATCH_KIND and KIVI_WATCH_INDEX
+    pub exec: Option<String>,
+
+    #[arg()]
+    /// path or prefix to watch
+    pub prefix: String,
+}
+
+#[derive(Parser, Clone, Debug)]
+/// Run a command with values under the prefix as environment variables,
+/// e.g. `kivi consul exec --prefix app/config -- ./server`
+pub struct ExecCmdConfig {
+    #[arg(long = "prefix")]
+    /// prefix to read values from. Variable names are key paths relative to it
+    pub prefix: String,
+
+    #[arg(long = "keep-prefix", action)]
+    /// keep the prefix in variable names
+    pub keep_prefix: bool,
+
+    #[arg(long = "no-sanitize", action)]
+    /// keep characters other than letters, digits and '_' in variable names
+    pub no_sanitize: bool,
+
+    #[arg(long = "no-upcase", action)]
+    /// keep variable names in the case of the keys
+    pub no_upcase: bool,
+
+    #[arg(long = "watch", action)]
+    /// restart the command when values change
+    pub watch: bool,
+
+    #[arg(required = true, last = true)]
+    /// command and its arguments, after '--'
+    pub command: Vec<String>,
+}
+
+#[derive(Parser, Clone, Debug)]
+/// Render a template with values from remote, e.g. `{{ key "svc/meta/top" }}`.
+/// See `kivi_rs::kv_template::Template` for the syntax
+pub struct RenderCmdConfig {
+    #[arg(short = 'o', long = "out")]
+    /// file to write the result to, instead of stdout
+    pub out_file: Option<PathBuf>,
+
+    #[arg(long = "watch", action)]
+    /// render again whenever keys used by the template change
+    pub watch: bool,
+
+    #[arg(long = "interval", default_value_t = 10)]
+    /// seconds between renders of a watched template whose keys can not be watched, e.g. on Vault
+    pub interval: u64,
+
+    #[arg()]
+    /// template file
+    pub template: PathBuf,
+}
+
+#[derive(Parser, Clone, Debug)]
+/// Render a template with values from a remote given as URI, e.g. `kivi render app.conf.tpl --remote consul://127.0.0.1:8500`.
+/// Credentials and TLS settings are taken from environment variables or `--profile`
+pub struct RemoteRenderCmdConfig {
+    #[arg(long = "remote")]
+    /// remote to read keys from, e.g. consul://127.0.0.1:8500. Template keys are full paths, URI prefix is not used
+    pub remote: KVUri,
+
+    #[command(flatten)]
+    pub render: RenderCmdConfig,
+}
+
+#[derive(Parser, Clone, Debug)]
+/// Browse key tree in a full-screen terminal UI with value preview, search and editing
+pub struct BrowseCmdConfig {
+    #[arg(default_value_t = String::new())]
+    /// prefix to start at
+    pub prefix: String,
+}
+
+#[derive(Parser, Clone, Debug)]
+/// Interactive prompt with a current prefix, key name completion and history
+pub struct ShellCmdConfig {
+    #[arg(default_value_t = String::new())]
+    /// prefix to start at
+    pub prefix: String,
+}
+
+#[derive(Parser, Clone, Debug)]
+/// Copy prefix subtree between remotes, e.g. from Consul to etcd. Values are copied byte to byte
+pub struct CopyCmdConfig {
+    #[arg(long = "from")]
+    /// source remote and prefix, e.g. consul://127.0.0.1:8500/svc/meta
+    pub from: KVUri,
+
+    #[arg(long = "to")]
+    /// target remote and prefix, e.g. etcd+https://etcd.local:2379/svc/meta
+    pub to: KVUri,
+
+    #[arg(long = "from-profile")]
+    /// profile with credentials and TLS settings of the source remote
+    pub from_profile: Option<String>,
+
+    #[arg(long = "to-profile")]
+    /// profile with credentials and TLS settings of the target remote
+    pub to_profile: Option<String>,
+}
+
+#[derive(Parser, Clone, Debug)]
+/// Show keys added, removed and changed from left to right side. Exits with 9 when sides differ
+pub struct DiffCmdConfig {
+    #[arg()]
+    /// remote and prefix, e.g. consul://127.0.0.1:8500/svc/meta, or local directory
+    pub left: DiffSide,
+
+    #[arg()]
+    /// remote and prefix or local directory to compare with
+    pub right: DiffSide,
+
+    #[arg(long = "left-profile")]
+    /// profile with credentials and TLS settings of the left remote
+    pub left_profile: Option<String>,
+
+    #[arg(long = "right-profile")]
+    /// profile with credentials and TLS settings of the right remote
+    pub right_profile: Option<String>,
+}
@@ -1,21 +1,44 @@
 use core::result::Result;
+use std::collections::BTreeMap;
 use std::fs;
+use std::path::PathBuf;
 
 use clap::Parser;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use ureq::{Agent, AgentBuilder, Error, Response};
 
-use crate::http_ext::TokenAuthHeaderMiddleware;
+use crate::http_ext::{remap_transport_error, TokenAuthHeaderMiddleware};
+use crate::kv_secret::{KeyringEntry, TokenSource};
+use crate::kv_tls::{TlsEnv, TlsOptions};
 use crate::{
-    cli_def::{KVSubs, ListCmdConfig, ReadCmdConfig, WriteCmdConfig},
-    kv_commons::{KVDisplayConfig, KVError, KVRemoteSource, KVValue},
+    cli_def::{DeleteCmdConfig, KVSubs, ListCmdConfig, ReadCmdConfig, WriteCmdConfig},
+    kv_commons::{
+        confirm_delete, offer_conflict_diff, KVError, KVRemoteSource, KVValue, UserPrompt,
+    },
+    kv_watch::{snapshot_events, WatchEvent},
     utils::*,
 };
 
 const KV_API_PATH: &str = "/v1/kv/";
 const FIRST_LEVEL_KEYS_PARAMS: &str = "?keys=true&separator=/";
+const ALL_KEYS_PARAMS: &str = "?keys=true";
+const RECURSE_PARAMS: &str = "?recurse=true";
+const CAS_PARAM: &str = "?cas=";
+const INDEX_HEADER: &str = "X-Consul-Index";
+/// Longest time Consul holds a blocking query
+const WATCH_WAIT: &str = "5m";
+
+/// Consul tools' TLS environment variables, used when TLS flags are not given
+const TLS_ENV: TlsEnv = TlsEnv {
+    ca_cert: "CONSUL_CACERT",
+    client_cert: "CONSUL_CLIENT_CERT",
+    client_key: "CONSUL_CLIENT_KEY",
+    server_name: Some("CONSUL_TLS_SERVER_NAME"),
+    skip_verify: None,
+};
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Default)]
 /// Subset of Consul specific commands
 pub struct ConsulCommandConfig {
     /// Consul token for authentication
@@ -27,6 +50,25 @@ pub struct ConsulCommandConfig {
     )]
     pub token: Option<String>,
 
+    /// Consul token file
+    #[arg(
+        long = "token-file",
+        env = "CONSUL_HTTP_TOKEN_FILE",
+        help = "File with Consul token, read with the first request that needs it"
+    )]
+    pub token_file: Option<PathBuf>,
+
+    /// Consul token command
+    #[arg(
+        long = "token-command",
+        help = "Shell command printing Consul token, e.g. 'pass show consul'"
+    )]
+    pub token_command: Option<String>,
+
+    /// OS secret store entry with Consul token. Set by profiles only
+    #[arg(skip)]
+    pub keyring: Option<KeyringEntry>,
+
     /// Consul url
     #[arg(
         short = 'u',
@@ -37,10 +79,20 @@ pub struct ConsulCommandConfig {
     )]
     pub url: String,
 
+    #[command(flatten)]
+    pub tls: TlsOptions,
+
     /// Consul command to execute
     #[command(subcommand)]
     pub kv_command: Option<KVSubs>,
 }
+
+impl ConsulCommandConfig {
+    /// Where token is read from when it is not given inline.
+    pub fn token_source(&self) -> Option<TokenSource> {
+        return TokenSource::pick(&self.token_file, &self.token_command, &self.keyring);
+    }
+}
 /// Represents Consul KV source
 pub struct ConsulRemote<'a> {
     pub config: &'a ConsulCommandConfig,
@@ -50,8 +102,10 @@ pub struct ConsulRemote<'a> {
 impl<'a> ConsulRemote<'a> {
     /// Ctor for [`ConsulRemote`]
     pub fn new(config: &'a ConsulCommandConfig, agent_builder: AgentBuilder) -> Self {
+        let agent_builder = config.tls.with_env(&TLS_ENV).configure(agent_builder);
         let authorizer =
-            TokenAuthHeaderMiddleware::new("X-CONSUL-TOKEN".to_owned(), config.token.to_owned());
+            TokenAuthHeaderMiddleware::new("X-CONSUL-TOKEN".to_owned(), config.token.to_owned())
+                .with_source(config.token_source());
         Self {
             config,
             agent: agent_builder.middleware(authorizer).build(),
@@ -63,32 +117,45 @@ impl<'a> ConsulRemote<'a> {
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 struct ConsulValue {
-    lock_index: u8,
+    lock_index: u64,
     key: String,
-    flags: u8,
+    flags: u64,
     value: Option<String>,
-    create_index: u32,
-    modify_index: u32,
+    create_index: u64,
+    modify_index: u64,
+    #[serde(default)]
+    session: Option<String>,
 }
 
-/// Converts internal [`ConsulValue`] to [`KVValue`].
-///
-/// * `display_cfg` - [`KVDisplayConfig`] that determines how to represent the display value (b64 or plain string).
-fn to_kv_value(display_cfg: KVDisplayConfig) -> impl Fn(ConsulValue) -> KVValue {
+/// Converts internal [`ConsulValue`] to [`KVValue`]. Value bytes are decoded as is.
+fn to_kv_value() -> impl Fn(ConsulValue) -> KVValue {
     return move |consul_val: ConsulValue| {
-        let extractor = match display_cfg.as_b64_encoded {
-            true => identity_str,
-            false => decodeb_64_safe,
-        };
-
         let extracted = match consul_val.value {
-            None => "".to_owned(),
-            Some(st) => extractor(&st),
+            None => vec![],
+            Some(st) => decode_b64_bytes(&st),
         };
 
+        let mut metadata = Map::from_iter([
+            ("flags".to_owned(), Value::from(consul_val.flags)),
+            ("lock_index".to_owned(), Value::from(consul_val.lock_index)),
+            (
+                "create_index".to_owned(),
+                Value::from(consul_val.create_index),
+            ),
+            (
+                "modify_index".to_owned(),
+                Value::from(consul_val.modify_index),
+            ),
+        ]);
+        if let Some(session) = consul_val.session {
+            metadata.insert("session".to_owned(), Value::from(session));
+        }
+
         return KVValue {
             path: consul_val.key.to_string(),
             value: extracted,
+            version: Some(consul_val.modify_index),
+            metadata,
         };
     };
 }
@@ -110,15 +177,43 @@ fn create_prefix_iter_linter(prefix: String) -> impl Fn(Vec<String>) -> Vec<Stri
     };
 }
 
+/// Index of Consul state the response was built from. Missing index is treated as `1`,
+/// so the next blocking query does not return immediately.
+fn consul_index(response: &Response) -> u64 {
+    return response
+        .header(INDEX_HEADER)
+        .and_then(|index| index.parse().ok())
+        .unwrap_or(1);
+}
+
+/**
+Index of the next blocking query, as Consul docs on blocking queries suggest. Index that went
+backwards, e.g. after Consul state was restored from a snapshot, is reset to `0`, so the next query
+returns the current state at once. Index `0` is never waited for, it is raised to `1`.
+
+Examples:
+
+```
+use kivi_rs::consul_remote::next_watch_index;
+
+assert_eq!(42, next_watch_index(Some(40), 42));
+assert_eq!(0, next_watch_index(Some(40), 12));
+assert_eq!(1, next_watch_index(None, 0));
+```
+*/
+pub fn next_watch_index(previous: Option<u64>, returned: u64) -> u64 {
+    if previous.is_some_and(|previous| returned < previous) {
+        return 0;
+    }
+    return returned.max(1);
+}
+
 // Consul Response is always a JSON array of items
-fn process_consul_response(
-    response: Response,
-    display_cfg: KVDisplayConfig,
-) -> Result<KVValue, KVError> {
+fn process_consul_response(response: Response) -> Result<KVValue, KVError> {
     let kv_value_mapper = |vec_consul_vals: Vec<ConsulValue>| -> Vec<KVValue> {
         return vec_consul_vals
             .into_iter()
-            .map(to_kv_value(display_cfg))
+            .map(to_kv_value())
             .collect::<Vec<KVValue>>();
     };
 
@@ -130,7 +225,7 @@ fn process_consul_response(
         Err(_) => Err(KVError::ValueFormatErr),
         Ok(items) => match items.first() {
             Some(item) => Ok(item.clone()),
-            None => Err(KVError::NoValueErr),
+            None => Err(KVError::NoValueErr("".to_owned())),
         },
     };
 }
@@ -149,7 +244,7 @@ impl<'a> ConsulRemote<'a> {
     ```
     use kivi_rs::consul_remote::{ConsulCommandConfig, ConsulRemote};
     use ureq::AgentBuilder;
-    let cmd_cfg = ConsulCommandConfig {url: "http://127.0.0.1:8500".to_owned(), token: None, kv_command: None};
+    let cmd_cfg = ConsulCommandConfig {url: "http://127.0.0.1:8500".to_owned(), token: None, kv_command: None, ..Default::default()};
     let me = ConsulRemote::new(&cmd_cfg, AgentBuilder::new());
 
     assert_eq!("http://127.0.0.1:8500/v1/kv/some/value/under/path", me.to_consul_url(&"some/value/under/path".to_owned()));
@@ -157,46 +252,31 @@ impl<'a> ConsulRemote<'a> {
     assert_eq!("http://127.0.0.1:8500/v1/kv/other/value/under/path", me.to_consul_url(&"/other/value/under/path".to_owned()));
     ````
      */
-    pub fn to_consul_url(&self, suffix: &String) -> String {
+    pub fn to_consul_url(&self, suffix: &str) -> String {
         return build_url(&self.config.url, KV_API_PATH, suffix);
     }
 
-    fn write_to_path(&self, write_cfg: WriteCmdConfig, content: String) -> Result<(), KVError> {
-        let consul_url = self.to_consul_url(&write_cfg.path);
-        let res_response = self.agent.put(&consul_url).send_bytes(content.as_bytes());
+    /// List full paths of all keys under the prefix, at any depth.
+    pub fn list_all_keys(&self, prefix: &str) -> Result<Vec<String>, KVError> {
+        let consul_url = self.to_consul_url(prefix) + ALL_KEYS_PARAMS;
+        let res_response = self.agent.get(&consul_url).call();
 
         return match res_response {
             Err(status) => remap_consul_errors(status),
-            Ok(_) => Ok(()),
+            Ok(response) => response
+                .into_json::<Vec<String>>()
+                .or(Err(KVError::ValueFormatErr)),
         };
     }
 }
 
 impl<'a> KVRemoteSource for ConsulRemote<'a> {
-    fn execute_kv_command(&self) {
-        match &self.config.kv_command {
-            Some(KVSubs::Read(read_cmd)) => {
-                let read_res = self.read_path(read_cmd.clone());
-                match read_res {
-                    Ok(kv_val) => print!("{}", kv_val),
-                    Err(err) => eprintln!("{err}"),
-                }
-            }
-            Some(KVSubs::List(list_cmd)) => {
-                let list_res = self.list(list_cmd.clone());
-                match list_res {
-                    Ok(keys) => println!("{}", keys.join("\n")),
-                    Err(err) => eprintln!("{err}"),
-                }
-            }
-            Some(KVSubs::Write(write_cmd)) => {
-                let write_res = self.write_path(write_cmd.clone());
-                if let Err(err) = write_res {
-                    eprintln!("{err}");
-                }
-            }
-            None => todo!(),
-        }
+    fn backend_name(&self) -> &'static str {
+        return "consul";
+    }
+
+    fn can_watch(&self) -> bool {
+        return true;
     }
 
     fn list(&self, list_cfg: ListCmdConfig) -> Result<Vec<String>, KVError> {
@@ -214,50 +294,193 @@ impl<'a> KVRemoteSource for ConsulRemote<'a> {
         };
     }
 
+    fn read_all(&self, list_cfg: ListCmdConfig) -> Result<Vec<KVValue>, KVError> {
+        let consul_url = self.to_consul_url(&list_cfg.prefix) + RECURSE_PARAMS;
+        let res_response = self.agent.get(&consul_url).call();
+
+        return match res_response {
+            Err(status) => remap_consul_errors(status),
+            Ok(response) => response
+                .into_json::<Vec<ConsulValue>>()
+                .map(|vals| vals.into_iter().map(to_kv_value()).collect())
+                .or(Err(KVError::ValueFormatErr)),
+        };
+    }
+
+    /// Consul keeps no value history, so only the current value can be read.
     fn read_path(&self, read_cfg: ReadCmdConfig) -> Result<KVValue, KVError> {
+        if read_cfg.version.is_some() {
+            return Err(KVError::UnsupportedErr);
+        }
         let consul_url = self.to_consul_url(&read_cfg.path);
         let request = self.agent.get(&consul_url);
 
         let res_response = request.call();
-        let kv_display_config = KVDisplayConfig {
-            as_b64_encoded: read_cfg.is_encoded,
+
+        return match res_response {
+            Err(status) => remap_consul_errors(status),
+            Ok(response) => process_consul_response(response),
         };
+    }
 
+    /// Write value. When `modify_index` is set, value is written only if key's `ModifyIndex` still matches it.
+    fn write_value(
+        &self,
+        path: &str,
+        content: Vec<u8>,
+        modify_index: Option<u64>,
+    ) -> Result<(), KVError> {
+        let cas_params = match modify_index {
+            Some(index) => format!("{CAS_PARAM}{index}"),
+            None => "".to_owned(),
+        };
+        let consul_url = self.to_consul_url(path) + &cas_params;
+        let res_response = self.agent.put(&consul_url).send_bytes(&content);
+
+        // Consul answers with plain `true` or `false` depending on whether value was written
         return match res_response {
             Err(status) => remap_consul_errors(status),
-            Ok(response) => process_consul_response(response, kv_display_config),
+            Ok(response) => match response.into_json::<bool>() {
+                Ok(false) => Err(KVError::ConflictErr),
+                _ => Ok(()),
+            },
         };
     }
 
-    fn write_path(&self, write_cfg: WriteCmdConfig) -> Result<(), KVError> {
-        let write_new_value = |content| self.write_to_path(write_cfg.clone(), content);
+    fn write_path(
+        &self,
+        write_cfg: WriteCmdConfig,
+        prompt: &dyn UserPrompt,
+    ) -> Result<(), KVError> {
+        let write_new_value = |content: Vec<u8>, version: Option<u64>| {
+            self.write_value(&write_cfg.path, content, version)
+        };
+        let read_cfg = ReadCmdConfig {
+            is_encoded: false,
+            is_hex: false,
+            out_file: None,
+            version: None,
+            path: write_cfg.path.to_owned(),
+        };
 
         return if write_cfg.is_inline_edit {
-            self.read_path(ReadCmdConfig {
-                is_encoded: false,
-                path: write_cfg.path.to_owned(),
-            })
-            .and_then(|kv_val| kv_val.inline_edit_value())
-            .and_then(write_new_value)
+            // edit is written only if remote value was not modified meanwhile
+            let kv_val = self.read_path(read_cfg.clone())?;
+            let edited = kv_val.inline_edit_value()?;
+            let write_res = write_new_value(edited.as_bytes().to_vec(), kv_val.version);
+            offer_conflict_diff(
+                write_res,
+                &edited,
+                &|| self.read_path(read_cfg.clone()),
+                prompt,
+            )
         } else {
             match write_cfg.data_file.to_owned() {
                 Some(file) => {
-                    return fs::read_to_string(file)
+                    return fs::read(file)
                         .or_else(KVError::wrap_as_write_err)
-                        .and_then(write_new_value);
+                        .and_then(|content| write_new_value(content, None));
                 }
                 None => Ok(()),
             }
         };
     }
+
+    fn delete_path(
+        &self,
+        delete_cfg: DeleteCmdConfig,
+        prompt: &dyn UserPrompt,
+    ) -> Result<(), KVError> {
+        let keys = match delete_cfg.is_recursive {
+            true => self.list_all_keys(&delete_cfg.path)?,
+            false => vec![
+                self.read_path(ReadCmdConfig {
+                    is_encoded: false,
+                    is_hex: false,
+                    out_file: None,
+                    version: None,
+                    path: delete_cfg.path.to_owned(),
+                })?
+                .path,
+            ],
+        };
+        confirm_delete(&keys, delete_cfg.assume_yes, prompt)?;
+
+        let delete_params = match delete_cfg.is_recursive {
+            true => RECURSE_PARAMS,
+            false => "",
+        };
+        let consul_url = self.to_consul_url(&delete_cfg.path) + delete_params;
+        let res_response = self.agent.delete(&consul_url).call();
+
+        return match res_response {
+            Err(status) => remap_consul_errors(status),
+            Ok(_) => Ok(()),
+        };
+    }
+
+    /// Watch with blocking queries. Every response holds the whole subtree, so it is compared
+    /// with the previous one to find changed keys.
+    fn watch(
+        &self,
+        prefix: &str,
+        on_event: &mut dyn FnMut(WatchEvent) -> Result<(), KVError>,
+    ) -> Result<(), KVError> {
+        let mut known = BTreeMap::new();
+        let mut index: Option<u64> = None;
+        loop {
+            let index_params = match index {
+                Some(index) => format!("&index={index}&wait={WATCH_WAIT}"),
+                None => "".to_owned(),
+            };
+            let consul_url = self.to_consul_url(prefix) + RECURSE_PARAMS + &index_params;
+            let (values, new_index) = match self.agent.get(&consul_url).call() {
+                // prefix is empty until a key is written
+                Err(Error::Status(404, response)) => (vec![], consul_index(&response)),
+                Err(status) => return remap_consul_errors(status),
+                Ok(response) => {
+                    let new_index = consul_index(&response);
+                    let values = response
+                        .into_json::<Vec<ConsulValue>>()
+                        .or(Err(KVError::ValueFormatErr))?;
+                    (values.into_iter().map(to_kv_value()).collect(), new_index)
+                }
+            };
+            let events = snapshot_events(&mut known, values, new_index);
+            // first response is the baseline
+            if index.is_some() {
+                events.into_iter().try_for_each(&mut *on_event)?;
+            }
+            index = Some(next_watch_index(index, new_index));
+        }
+    }
+
+    /// Delete key. When `modify_index` is set, key is deleted only if its `ModifyIndex` still matches it.
+    fn delete_value(&self, path: &str, modify_index: Option<u64>) -> Result<(), KVError> {
+        let cas_params = match modify_index {
+            Some(index) => format!("{CAS_PARAM}{index}"),
+            None => "".to_owned(),
+        };
+        let consul_url = self.to_consul_url(path) + &cas_params;
+        let res_response = self.agent.delete(&consul_url).call();
+
+        return match res_response {
+            Err(status) => remap_consul_errors(status),
+            Ok(response) => match response.into_json::<bool>() {
+                Ok(false) => Err(KVError::ConflictErr),
+                _ => Ok(()),
+            },
+        };
+    }
 }
 
+/// Consul reports errors as plain text body
 fn remap_consul_errors<T>(status: Error) -> Result<T, KVError> {
     match status {
-        Error::Status(403, _) => Err(KVError::PermissionErr),
-        Error::Status(401, _) => Err(KVError::AuthenticationErr),
-        Error::Status(404, _) => Err(KVError::NoValueErr),
-        Error::Transport(_) => Err(KVError::RemoteErr),
-        Error::Status(_, _) => Err(KVError::RemoteErr),
+        Error::Transport(transport) => Err(remap_transport_error(transport)),
+        Error::Status(status, response) => Err(KVError::from_status(
+            status,
+            response.into_string().unwrap_or_default().trim().to_owned(),
+        )),
     }
 }
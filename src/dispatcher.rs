@@ -0,0 +1,390 @@
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::Map;
+use ureq::AgentBuilder;
+
+use crate::cli_def::{DiffCmdConfig, KVSubs, ListCmdConfig, RenderCmdConfig, WatchCmdConfig};
+use crate::kv_browse::browse;
+use crate::kv_commons::{KVError, KVRemoteSource, KVValue, UserPrompt};
+use crate::kv_diff::KVDiff;
+use crate::kv_dir::read_dir_values;
+use crate::kv_dump::{export_dump, parse_dump};
+use crate::kv_exec::exec_with_env;
+use crate::kv_plan::KVPlan;
+use crate::kv_template::{RenderedTemplate, Template};
+use crate::kv_uri::KVUri;
+use crate::kv_watch::run_on_change;
+use crate::output::{to_json, CommandOutput};
+use crate::utils::{create_path_linter, join_path};
+
+/**
+Execute KV command against any remote and return its result. Nothing is printed here,
+use [`crate::output::Renderer`] to present the result. Questions, e.g. whether to delete keys,
+are asked with `prompt`.
+
+Examples:
+
+```no_run
+use kivi_rs::cli_def::{KVSubs, ListCmdConfig};
+use kivi_rs::consul_remote::{ConsulCommandConfig, ConsulRemote};
+use kivi_rs::dispatcher::dispatch;
+use kivi_rs::kv_commons::{KVRemoteSource, NoPrompt};
+use kivi_rs::output::{OutputFormat, Renderer};
+use ureq::AgentBuilder;
+
+let cmd_cfg = ConsulCommandConfig {url: "http://127.0.0.1:8500".to_owned(), token: None, kv_command: None, ..Default::default()};
+let consul = ConsulRemote::new(&cmd_cfg, AgentBuilder::new());
+let command = KVSubs::List(ListCmdConfig { prefix: "services/".to_owned() });
+
+let output = dispatch(&consul, &command, &NoPrompt).unwrap();
+print!("{}", Renderer::new(consul.backend_name(), OutputFormat::Json).render(&output));
+```
+*/
+pub fn dispatch(
+    remote: &dyn KVRemoteSource,
+    command: &KVSubs,
+    prompt: &dyn UserPrompt,
+) -> Result<CommandOutput, KVError> {
+    return match command {
+        KVSubs::Read(read_cmd) => {
+            let value = remote
+                .read_path(read_cmd.clone())?
+                .encode(read_cmd.encoding());
+            match &read_cmd.out_file {
+                Some(file) => fs::write(file, &value.value)
+                    .or_else(KVError::wrap_as_write_err)
+                    .map(|_| CommandOutput::Done {
+                        path: read_cmd.path.to_owned(),
+                    }),
+                None => Ok(CommandOutput::Value(value)),
+            }
+        }
+        KVSubs::List(list_cmd) => remote
+            .list(list_cmd.clone())
+            .map(|keys| CommandOutput::Keys {
+                prefix: list_cmd.prefix.to_owned(),
+                keys,
+            }),
+        KVSubs::Write(write_cmd) => {
+            remote
+                .write_path(write_cmd.clone(), prompt)
+                .map(|_| CommandOutput::Done {
+                    path: write_cmd.path.to_owned(),
+                })
+        }
+        KVSubs::Delete(delete_cmd) => {
+            remote
+                .delete_path(delete_cmd.clone(), prompt)
+                .map(|_| CommandOutput::Done {
+                    path: delete_cmd.path.to_owned(),
+                })
+        }
+        KVSubs::Tree(tree_cmd) => remote
+            .read_all(ListCmdConfig {
+                prefix: tree_cmd.prefix.to_owned(),
+            })
+            .map(|values| CommandOutput::Tree {
+                prefix: tree_cmd.prefix.to_owned(),
+                values,
+                depth: tree_cmd.depth,
+            }),
+        KVSubs::Export(export_cmd) => {
+            let values = remote.read_all(ListCmdConfig {
+                prefix: export_cmd.prefix.to_owned(),
+            })?;
+            let dump = export_dump(
+                export_cmd.format,
+                remote.backend_name(),
+                &export_cmd.prefix,
+                values,
+            );
+            match &export_cmd.out_file {
+                Some(file) => fs::write(file, to_json(&dump))
+                    .or_else(KVError::wrap_as_write_err)
+                    .map(|_| CommandOutput::Done {
+                        path: export_cmd.prefix.to_owned(),
+                    }),
+                None => Ok(CommandOutput::document(
+                    &export_cmd.prefix,
+                    &dump,
+                    to_json(&dump),
+                )),
+            }
+        }
+        KVSubs::Import(import_cmd) => {
+            let content = fs::read(&import_cmd.file).or_else(KVError::wrap_as_write_err)?;
+            let values = parse_dump(&content, import_cmd.prefix.as_deref())?;
+            let plan = KVPlan::build(remote, values)?;
+            execute_plan(
+                remote,
+                plan,
+                import_cmd.dry_run,
+                import_cmd.assume_yes,
+                prompt,
+            )
+        }
+        // watch never returns a single result, see [`watch`]
+        KVSubs::Watch(_) => Err(KVError::UnsupportedErr),
+        // watched child ends the process when it exits, see [`crate::kv_exec::exec_watched`]
+        KVSubs::Exec(exec_cmd) if exec_cmd.watch => Err(KVError::UnsupportedErr),
+        KVSubs::Exec(exec_cmd) => exec_with_env(remote, exec_cmd),
+        KVSubs::Browse(browse_cmd) => browse(remote, browse_cmd, prompt),
+        // shell prints results of many commands, see [`crate::kv_shell::run_shell`]
+        KVSubs::Shell(_) => Err(KVError::UnsupportedErr),
+        // watched template is rendered many times, see [`watch_template`]
+        KVSubs::Render(render_cmd) if render_cmd.watch => Err(KVError::UnsupportedErr),
+        KVSubs::Render(render_cmd) => {
+            let template = read_template(render_cmd)?;
+            let rendered = template.render(remote)?;
+            write_rendered(render_cmd, rendered.text)
+        }
+        KVSubs::Apply(apply_cmd) => {
+            let prefix = create_path_linter()(apply_cmd.prefix.to_owned());
+            let values = read_dir_values(&apply_cmd.dir, &prefix)?;
+            let mut plan = KVPlan::build(remote, values)?;
+            if apply_cmd.prune {
+                // trailing delimiter keeps sibling prefixes, e.g. `svc/metadata`, out of the plan
+                let remote_values = match remote.read_all(ListCmdConfig {
+                    prefix: join_path(&prefix, ""),
+                }) {
+                    // nothing to prune under a prefix that does not exist yet
+                    Err(KVError::NoValueErr(_)) => vec![],
+                    other => other?,
+                };
+                // Consul folder keys, paths ending with `/`, have no counterpart in the directory
+                plan.prune(
+                    remote_values
+                        .into_iter()
+                        .filter(|value| !value.path.ends_with('/'))
+                        .collect(),
+                );
+            }
+            execute_plan(
+                remote,
+                plan,
+                apply_cmd.dry_run,
+                apply_cmd.assume_yes,
+                prompt,
+            )
+        }
+    };
+}
+
+/// Pass every change under the watched prefix to `on_output` as [`CommandOutput::Event`]
+/// and run the configured command after it. Failures of the command are passed to `on_error`
+/// and do not stop the watch. Returns when remote closes the watch or `on_output` fails.
+pub fn watch(
+    remote: &dyn KVRemoteSource,
+    watch_cmd: &WatchCmdConfig,
+    on_output: &mut dyn FnMut(&CommandOutput) -> Result<(), KVError>,
+    on_error: &mut dyn FnMut(&KVError),
+) -> Result<CommandOutput, KVError> {
+    remote.watch(&watch_cmd.prefix, &mut |event| {
+        let output = CommandOutput::Event(event);
+        on_output(&output)?;
+        if let (Some(command), CommandOutput::Event(event)) = (&watch_cmd.exec, &output) {
+            if let Err(err) = run_on_change(command, event) {
+                on_error(&err);
+            }
+        }
+        return Ok(());
+    })?;
+    return Ok(CommandOutput::Done {
+        path: watch_cmd.prefix.to_owned(),
+    });
+}
+
+fn read_template(render_cmd: &RenderCmdConfig) -> Result<Template, KVError> {
+    let source = fs::read_to_string(&render_cmd.template).or_else(KVError::wrap_as_write_err)?;
+    return Template::parse(&source);
+}
+
+/// Write rendered template to the output file, if any, otherwise return it as a value.
+fn write_rendered(render_cmd: &RenderCmdConfig, text: String) -> Result<CommandOutput, KVError> {
+    return match &render_cmd.out_file {
+        Some(file) => fs::write(file, text)
+            .or_else(KVError::wrap_as_write_err)
+            .map(|_| CommandOutput::Done {
+                path: file.display().to_string(),
+            }),
+        None => Ok(CommandOutput::Value(KVValue {
+            path: render_cmd.template.display().to_string(),
+            value: text.into_bytes(),
+            version: None,
+            metadata: Map::new(),
+        })),
+    };
+}
+
+/// Render the template and render it again whenever keys it read change. Every result is passed
+/// to `on_output`, unchanged results are skipped. Returns when remote closes the watch or `on_output` fails.
+///
+/// Keys are watched with a single watch of their common prefix, remotes without watches render
+/// again every `interval` seconds instead. Keys without one, e.g. `svc/a` and `app/b`, would watch
+/// the whole keyspace and are refused with [`KVError::UnsupportedErr`].
+pub fn watch_template(
+    remote: &dyn KVRemoteSource,
+    render_cmd: &RenderCmdConfig,
+    on_output: &mut dyn FnMut(&CommandOutput) -> Result<(), KVError>,
+) -> Result<CommandOutput, KVError> {
+    let template = read_template(render_cmd)?;
+    let mut rendered = template.render(remote)?;
+    let watched_prefix = common_prefix(&rendered.inputs);
+    if watched_prefix.is_empty() {
+        return Err(KVError::UnsupportedErr);
+    }
+    on_output(&write_rendered(render_cmd, rendered.text.to_owned())?)?;
+    let mut render_again = |rendered: &mut RenderedTemplate| {
+        let fresh = template.render(remote)?;
+        if fresh.text != rendered.text {
+            on_output(&write_rendered(render_cmd, fresh.text.to_owned())?)?;
+        }
+        *rendered = fresh;
+        return Ok::<(), KVError>(());
+    };
+
+    if !remote.can_watch() {
+        loop {
+            thread::sleep(Duration::from_secs(render_cmd.interval));
+            render_again(&mut rendered)?;
+        }
+    }
+    remote.watch(&watched_prefix, &mut |event| {
+        if !rendered
+            .inputs
+            .iter()
+            .any(|input| event.path.starts_with(input.as_str()))
+        {
+            return Ok(());
+        }
+        return render_again(&mut rendered);
+    })?;
+    return Ok(CommandOutput::Done {
+        path: render_cmd.template.display().to_string(),
+    });
+}
+
+/// Longest common prefix of the paths, not necessarily ending at a path delimiter.
+fn common_prefix(paths: &[String]) -> String {
+    let Some(first) = paths.first() else {
+        return "".to_owned();
+    };
+    let len = paths.iter().fold(first.len(), |len, path| {
+        first
+            .char_indices()
+            .zip(path.chars())
+            .take_while(|((idx, left), right)| *idx < len && left == right)
+            .map(|((idx, left), _)| idx + left.len_utf8())
+            .last()
+            .unwrap_or(0)
+    });
+    return first[..len].to_owned();
+}
+
+/// Confirm and apply the plan with changes, unless it is a dry run. Plan is `applied` only
+/// when its writes were run, failed keys are listed in [`KVPlan::failed`].
+fn execute_plan(
+    remote: &dyn KVRemoteSource,
+    mut plan: KVPlan,
+    dry_run: bool,
+    assume_yes: bool,
+    prompt: &dyn UserPrompt,
+) -> Result<CommandOutput, KVError> {
+    let applied = !dry_run && plan.has_changes();
+    if applied {
+        plan.confirm(assume_yes, prompt)?;
+        plan.apply(remote);
+    }
+    return Ok(CommandOutput::Plan { plan, applied });
+}
+
+/// Key that could not be copied.
+#[derive(Debug, Serialize)]
+pub struct CopyFailure {
+    pub path: String,
+    pub error: String,
+}
+
+/// Outcome of [`copy_prefix`]. Paths are the target ones.
+#[derive(Debug, Serialize)]
+pub struct CopyReport {
+    pub from: String,
+    pub to: String,
+    pub copied: Vec<String>,
+    pub failed: Vec<CopyFailure>,
+}
+
+impl CopyReport {
+    pub fn summary(&self) -> String {
+        return format!(
+            "Copied {} of {} keys from {} to {}, {} failed",
+            self.copied.len(),
+            self.copied.len() + self.failed.len(),
+            self.from,
+            self.to,
+            self.failed.len()
+        );
+    }
+}
+
+/**
+Copy every value under `from` prefix of `source` to `target`, replacing `from` prefix with `to` one.
+
+Values are copied byte to byte. A failed key does not stop the copy, it is reported in [`CopyReport`].
+Consul folder keys, paths ending with `/`, are skipped, other backends have no folders.
+*/
+pub fn copy_prefix(
+    source: &dyn KVRemoteSource,
+    from: &KVUri,
+    target: &dyn KVRemoteSource,
+    to: &KVUri,
+) -> Result<CommandOutput, KVError> {
+    // `app/config/`, not `app/config`, so `app/configuration` stays out
+    let prefix = join_path(&from.prefix, "");
+    let values = source.read_all(ListCmdConfig {
+        prefix: prefix.to_owned(),
+    })?;
+    let mut report = CopyReport {
+        from: from.to_string(),
+        to: to.to_string(),
+        copied: vec![],
+        failed: vec![],
+    };
+    for value in values
+        .into_iter()
+        .filter(|value| !value.path.ends_with('/'))
+    {
+        let relative_path = value.path.strip_prefix(&prefix).unwrap_or(&value.path);
+        let path = join_path(&to.prefix, relative_path);
+        match target.write_value(&path, value.value, None) {
+            Ok(()) => report.copied.push(path),
+            Err(err) => report.failed.push(CopyFailure {
+                path,
+                error: err.to_string(),
+            }),
+        }
+    }
+    return Ok(CommandOutput::Copy(report));
+}
+
+/// Read both sides, each with its own client and profile, and compare them.
+pub fn diff_sides(
+    diff_cmd: &DiffCmdConfig,
+    build_client: impl Fn() -> AgentBuilder,
+) -> Result<CommandOutput, KVError> {
+    let left_values = diff_cmd
+        .left
+        .read_values(build_client(), diff_cmd.left_profile.as_deref())?;
+    let right_values = diff_cmd
+        .right
+        .read_values(build_client(), diff_cmd.right_profile.as_deref())?;
+    return Ok(CommandOutput::Diff(KVDiff::build(
+        &diff_cmd.left.to_string(),
+        left_values,
+        &diff_cmd.right.to_string(),
+        right_values,
+    )));
+}
@@ -1,17 +1,44 @@
+use core::result::Result;
+use std::fs;
+use std::path::PathBuf;
+
 use clap::Parser;
-use ureq::{Agent, AgentBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use ureq::{Agent, AgentBuilder, Error, Response};
 
 use crate::cli_def::*;
-use crate::http_ext::basic_auth;
+use crate::http_ext::{remap_transport_error, SharedToken};
 use crate::kv_commons::*;
+use crate::kv_secret::{KeyringEntry, TokenSource};
+use crate::kv_tls::{TlsEnv, TlsOptions};
+use crate::kv_watch::{WatchEvent, WatchEventKind};
+use crate::utils::*;
 use crate::{http_ext::TokenAuthHeaderMiddleware, kv_commons::KVRemoteSource};
 
 const AUTH_HEADER: &str = "Authorization";
+const API_PATH: &str = "/v3/";
+const KV_RANGE_ENDPOINT: &str = "kv/range";
+const KV_PUT_ENDPOINT: &str = "kv/put";
+const KV_DELETE_ENDPOINT: &str = "kv/deleterange";
+const KV_TXN_ENDPOINT: &str = "kv/txn";
+const WATCH_ENDPOINT: &str = "watch";
+const AUTH_ENDPOINT: &str = "auth/authenticate";
+const KEY_SEPARATOR: &str = "/";
+
+/// Etcd tools' TLS environment variables, used when TLS flags are not given
+const TLS_ENV: TlsEnv = TlsEnv {
+    ca_cert: "ETCDCTL_CACERT",
+    client_cert: "ETCDCTL_CERT",
+    client_key: "ETCDCTL_KEY",
+    server_name: None,
+    skip_verify: Some("ETCDCTL_INSECURE_SKIP_TLS_VERIFY"),
+};
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Default)]
 /// Subset of etcd specific commands
 pub struct EtcdCommandConfig {
-    /// Consul token for authentication
+    /// Etcd credentials for authentication
     #[arg(
         short = 'c',
         long = "creds",
@@ -20,7 +47,25 @@ pub struct EtcdCommandConfig {
     )]
     pub token: Option<String>,
 
-    /// Consul url
+    /// Etcd credentials file
+    #[arg(
+        long = "creds-file",
+        help = "File with Etcd credentials in '--creds' format, read with the first request that needs it"
+    )]
+    pub token_file: Option<PathBuf>,
+
+    /// Etcd credentials command
+    #[arg(
+        long = "creds-command",
+        help = "Shell command printing Etcd credentials in '--creds' format, e.g. 'pass show etcd'"
+    )]
+    pub token_command: Option<String>,
+
+    /// OS secret store entry with Etcd credentials. Set by profiles only
+    #[arg(skip)]
+    pub keyring: Option<KeyringEntry>,
+
+    /// Etcd url
     #[arg(
         short = 'u',
         long = "url",
@@ -30,6 +75,9 @@ pub struct EtcdCommandConfig {
     )]
     pub url: String,
 
+    #[command(flatten)]
+    pub tls: TlsOptions,
+
     // Key separator add here
     // pub key_separator: u8
     /// Etcd command to execute
@@ -37,39 +85,678 @@ pub struct EtcdCommandConfig {
     pub kv_command: Option<KVSubs>,
 }
 
+impl EtcdCommandConfig {
+    /// Where credentials is read from when it is not given inline.
+    pub fn token_source(&self) -> Option<TokenSource> {
+        return TokenSource::pick(&self.token_file, &self.token_command, &self.keyring);
+    }
+}
+
+/// Represents etcd KV source. Talks to etcd v3 JSON gRPC gateway.
 pub struct EtcdRemote<'a> {
     pub config: &'a EtcdCommandConfig,
     pub agent: Agent,
+    auth_token: SharedToken,
 }
 
 impl<'a> EtcdRemote<'a> {
     /// Ctor for [`EtcdRemote`]
+    ///
+    /// No login happens here. Auth token is acquired with the first request that needs it.
     pub fn new(config: &'a EtcdCommandConfig, agent_builder: AgentBuilder) -> Self {
-        let authorizer = TokenAuthHeaderMiddleware::new(
-            AUTH_HEADER.to_owned(),
-            config.token.as_ref().map(basic_auth),
-        );
+        let agent_builder = config.tls.with_env(&TLS_ENV).configure(agent_builder);
+        let auth_token = SharedToken::default();
+        let authorizer =
+            TokenAuthHeaderMiddleware::shared(AUTH_HEADER.to_owned(), auth_token.clone());
         Self {
             config,
             agent: agent_builder.middleware(authorizer).build(),
+            auth_token,
+        }
+    }
+}
+
+/// Body of etcd `/v3/auth/authenticate` request.
+#[derive(Debug, Serialize)]
+struct EtcdAuthRequest {
+    name: String,
+    password: String,
+}
+
+/// Response of etcd `/v3/auth/authenticate`.
+#[derive(Debug, Deserialize)]
+struct EtcdAuthResponse {
+    token: String,
+}
+
+/// Body of etcd `/v3/kv/range` request. Keys are base64 encoded.
+#[derive(Debug, Serialize)]
+struct EtcdRangeRequest {
+    key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    range_end: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    keys_only: bool,
+    /// Store revision to read at, latest one when omitted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    revision: Option<String>,
+}
+
+/// Body of etcd `/v3/kv/deleterange` request. Keys are base64 encoded.
+#[derive(Debug, Serialize)]
+struct EtcdDeleteRequest {
+    key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    range_end: Option<String>,
+}
+
+/// Body of etcd `/v3/kv/put` request. Key and value are base64 encoded.
+#[derive(Debug, Serialize)]
+struct EtcdPutRequest {
+    key: String,
+    value: String,
+}
+
+/// Response of etcd `/v3/kv/range`. Gateway omits empty fields, hence defaults.
+#[derive(Debug, Deserialize)]
+struct EtcdRangeResponse {
+    #[serde(default)]
+    kvs: Vec<EtcdValue>,
+}
+
+/// Represents stored/read etcd Value.
+#[derive(Debug, Deserialize)]
+struct EtcdValue {
+    key: String,
+    #[serde(default)]
+    value: Option<String>,
+    /// 64 bit integers are sent as strings by the gateway
+    #[serde(default)]
+    mod_revision: Option<String>,
+    #[serde(default)]
+    create_revision: Option<String>,
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    lease: Option<String>,
+}
+
+/// Body of etcd `/v3/kv/txn` request.
+#[derive(Debug, Serialize)]
+struct EtcdTxnRequest {
+    compare: Vec<EtcdCompare>,
+    success: Vec<EtcdRequestOp>,
+}
+
+/// Txn condition that holds while key's `mod_revision` equals the given one.
+#[derive(Debug, Serialize)]
+struct EtcdCompare {
+    key: String,
+    target: &'static str,
+    result: &'static str,
+    mod_revision: String,
+}
+
+/// Txn operation, serialized as `{"request_put": {...}}`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum EtcdRequestOp {
+    RequestPut(EtcdPutRequest),
+    RequestDeleteRange(EtcdDeleteRequest),
+}
+
+/// Response of etcd `/v3/kv/txn`. `succeeded` is omitted when compare failed.
+#[derive(Debug, Deserialize)]
+struct EtcdTxnResponse {
+    #[serde(default)]
+    succeeded: bool,
+}
+
+/// Body of etcd `/v3/watch` request. Previous values are requested to show what changed.
+#[derive(Debug, Serialize)]
+struct EtcdWatchRequest {
+    create_request: EtcdWatchCreateRequest,
+}
+
+#[derive(Debug, Serialize)]
+struct EtcdWatchCreateRequest {
+    key: String,
+    range_end: String,
+    prev_kv: bool,
+}
+
+/// Single message of etcd watch stream. Gateway reports stream failures in `error`.
+#[derive(Debug, Deserialize)]
+struct EtcdWatchMessage {
+    #[serde(default)]
+    result: Option<EtcdWatchResult>,
+    #[serde(default)]
+    error: Option<EtcdStreamError>,
+}
+
+/// Server cancels the watch with `canceled`, e.g. when watched revision was compacted.
+#[derive(Debug, Deserialize)]
+struct EtcdWatchResult {
+    #[serde(default)]
+    events: Vec<EtcdEvent>,
+    #[serde(default)]
+    canceled: bool,
+    #[serde(default)]
+    cancel_reason: String,
+    /// int64 is sent as a string by the gateway
+    #[serde(default)]
+    compact_revision: Option<String>,
+}
+
+impl EtcdWatchResult {
+    /// Reason of a canceled watch, the newest compacted revision is mentioned when known.
+    fn cancel_message(&self) -> String {
+        let reason = match self.cancel_reason.is_empty() {
+            true => "watch canceled by server".to_owned(),
+            false => format!("watch canceled: {}", self.cancel_reason),
+        };
+        return match &self.compact_revision {
+            Some(revision) if revision != "0" => {
+                format!("{reason}, revisions up to {revision} are compacted")
+            }
+            _ => reason,
+        };
+    }
+}
+
+/// Change of a key. `type` is omitted for puts, as default enum values are not sent.
+#[derive(Debug, Deserialize)]
+struct EtcdEvent {
+    #[serde(default, rename = "type")]
+    event_type: Option<String>,
+    kv: EtcdValue,
+    #[serde(default)]
+    prev_kv: Option<EtcdValue>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EtcdStreamError {
+    #[serde(default)]
+    http_code: Option<u16>,
+    #[serde(default)]
+    message: String,
+}
+
+impl From<EtcdEvent> for WatchEvent {
+    fn from(event: EtcdEvent) -> Self {
+        let kind = match event.event_type.as_deref() {
+            Some("DELETE") => WatchEventKind::Delete,
+            _ => WatchEventKind::Put,
+        };
+        let new = to_kv_value()(event.kv);
+        return WatchEvent {
+            path: new.path.to_owned(),
+            kind,
+            index: new.version.unwrap_or_default(),
+            old: event.prev_kv.map(to_kv_value()),
+            new: match kind {
+                WatchEventKind::Put => Some(new),
+                WatchEventKind::Delete => None,
+            },
+        };
+    }
+}
+
+/// Converts internal [`EtcdValue`] to [`KVValue`]. Value bytes are decoded as is.
+fn to_kv_value() -> impl Fn(EtcdValue) -> KVValue {
+    return move |etcd_val: EtcdValue| {
+        let extracted = match etcd_val.value {
+            None => vec![],
+            Some(st) => decode_b64_bytes(&st),
+        };
+
+        let metadata = [
+            ("create_revision", &etcd_val.create_revision),
+            ("mod_revision", &etcd_val.mod_revision),
+            ("version", &etcd_val.version),
+            ("lease", &etcd_val.lease),
+        ]
+        .into_iter()
+        .filter_map(|(name, field)| {
+            let number = field.as_ref()?.parse::<u64>().ok()?;
+            return Some((name.to_owned(), Value::from(number)));
+        })
+        .collect::<Map<String, Value>>();
+
+        return KVValue {
+            path: decodeb_64_safe(&etcd_val.key),
+            value: extracted,
+            version: etcd_val.mod_revision.and_then(|rev| rev.parse().ok()),
+            metadata,
+        };
+    };
+}
+
+/// Linter of etcd keys. Used to reduce a full prefix range to immediate child node names.
+///
+/// etcd has no notion of directories, so prefix is removed from every key and the remainder is cut
+/// right after the first [`KEY_SEPARATOR`]. This aligns the output with Consul `?separator=/` listing.
+///
+/// See [`create_str_linter()`]
+pub fn create_children_linter(prefix: String) -> impl Fn(Vec<String>) -> Vec<String> {
+    let keys_linter = create_str_linter(Some(prefix), None, false);
+    return move |keys: Vec<String>| -> Vec<String> {
+        let mut children: Vec<String> = vec![];
+        for key in keys.into_iter().map(&keys_linter) {
+            let child = match key.find(KEY_SEPARATOR) {
+                Some(idx) => key[..idx + KEY_SEPARATOR.len()].to_owned(),
+                None => key,
+            };
+            if !child.is_empty() && !children.contains(&child) {
+                children.push(child);
+            }
         }
+        return children;
+    };
+}
+
+/// Base64 encoded start and end of the range covering every key under the prefix, root one included.
+fn encode_prefix_range(prefix: &str) -> (String, String) {
+    return (
+        encode_b64(&prefix_range_start(prefix.as_bytes())),
+        encode_b64(&prefix_range_end(prefix.as_bytes())),
+    );
+}
+
+/// Split base64 encoded `user:password` credentials into [`EtcdAuthRequest`].
+///
+/// Surrounding whitespace is ignored, so `echo "user:pass" | base64` output works as is.
+fn to_auth_request(credentials: &str) -> Result<EtcdAuthRequest, KVError> {
+    let decoded = decodeb_64_safe(credentials.trim());
+    return match decoded.trim().split_once(':') {
+        Some((name, password)) if !name.is_empty() => Ok(EtcdAuthRequest {
+            name: name.to_owned(),
+            password: password.to_owned(),
+        }),
+        _ => Err(KVError::AuthenticationErr(
+            "credentials must be a base64 encoded 'user:password' string".to_owned(),
+        )),
+    };
+}
+
+// etcd range response holds zero or more items, only first one matters for reads
+fn process_etcd_response(response: Response) -> Result<KVValue, KVError> {
+    let result_items = response.into_json::<EtcdRangeResponse>();
+
+    return match result_items {
+        Err(_) => Err(KVError::ValueFormatErr),
+        Ok(range) => match range.kvs.into_iter().next() {
+            Some(item) => Ok(to_kv_value()(item)),
+            None => Err(KVError::NoValueErr("".to_owned())),
+        },
+    };
+}
+
+impl<'a> EtcdRemote<'a> {
+    /**
+    Create properly formed etcd v3 gateway URL for the API endpoint.
+
+    See [build_url()]
+
+    Examples:
+
+    ```
+    use kivi_rs::etcd_remote::{EtcdCommandConfig, EtcdRemote};
+    use ureq::AgentBuilder;
+    let cmd_cfg = EtcdCommandConfig {url: "http://127.0.0.1:2379/".to_owned(), token: None, kv_command: None, ..Default::default()};
+    let me = EtcdRemote::new(&cmd_cfg, AgentBuilder::new());
+
+    assert_eq!("http://127.0.0.1:2379/v3/kv/range", me.to_etcd_url("kv/range"));
+    ````
+     */
+    pub fn to_etcd_url(&self, endpoint: &str) -> String {
+        return build_url(&self.config.url, API_PATH, endpoint);
+    }
+
+    /// Exchange configured credentials for an auth token and cache it for subsequent requests.
+    ///
+    /// Does nothing when no credentials are configured.
+    fn authenticate(&self) -> Result<(), KVError> {
+        let auth_request = match (&self.config.token, self.config.token_source()) {
+            (Some(credentials), _) => to_auth_request(credentials)?,
+            (None, Some(source)) => {
+                to_auth_request(&source.resolve().map_err(KVError::CredentialsErr)?)?
+            }
+            (None, None) => return Ok(()),
+        };
+        // stale token must not be sent along with login request
+        *self.auth_token.write().unwrap() = None;
+        let res_response = self
+            .agent
+            .post(&self.to_etcd_url(AUTH_ENDPOINT))
+            .send_json(auth_request);
+
+        return match res_response {
+            Err(Error::Status(400, response)) => {
+                Err(KVError::AuthenticationErr(etcd_error_message(response)))
+            }
+            Err(status) => remap_etcd_errors(status),
+            Ok(response) => response
+                .into_json::<EtcdAuthResponse>()
+                .map(|auth| *self.auth_token.write().unwrap() = Some(auth.token))
+                .or(Err(KVError::ValueFormatErr)),
+        };
+    }
+
+    /// Send JSON request to the etcd gateway endpoint.
+    ///
+    /// Logs in before the first request and once again if remote rejects the cached token (e.g. it expired).
+    fn call_etcd(&self, endpoint: &str, body: &impl Serialize) -> Result<Response, KVError> {
+        let has_credentials = self.config.token.is_some() || self.config.token_source().is_some();
+        if has_credentials && self.auth_token.read().unwrap().is_none() {
+            self.authenticate()?;
+        }
+        let etcd_url = self.to_etcd_url(endpoint);
+        let res_response = self.agent.post(&etcd_url).send_json(body);
+
+        return match res_response {
+            Err(Error::Status(401, _)) if has_credentials => self.authenticate().and_then(|_| {
+                self.agent
+                    .post(&etcd_url)
+                    .send_json(body)
+                    .or_else(remap_etcd_errors)
+            }),
+            Err(status) => remap_etcd_errors(status),
+            Ok(response) => Ok(response),
+        };
+    }
+
+    /// Run single operation in a txn that succeeds only if key's `mod_revision` still equals `revision`.
+    fn call_guarded(&self, key: String, revision: u64, op: EtcdRequestOp) -> Result<(), KVError> {
+        let txn_request = EtcdTxnRequest {
+            compare: vec![EtcdCompare {
+                key,
+                target: "MOD",
+                result: "EQUAL",
+                mod_revision: revision.to_string(),
+            }],
+            success: vec![op],
+        };
+
+        return self
+            .call_etcd(KV_TXN_ENDPOINT, &txn_request)
+            .and_then(|response| {
+                response
+                    .into_json::<EtcdTxnResponse>()
+                    .or(Err(KVError::ValueFormatErr))
+            })
+            .and_then(|txn| match txn.succeeded {
+                true => Ok(()),
+                false => Err(KVError::ConflictErr),
+            });
+    }
+
+    /// List full paths of all keys under the prefix, at any depth.
+    pub fn list_all_keys(&self, prefix: &str) -> Result<Vec<String>, KVError> {
+        let (key, range_end) = encode_prefix_range(&create_path_linter()(prefix.to_owned()));
+        let range_request = EtcdRangeRequest {
+            key,
+            range_end: Some(range_end),
+            keys_only: true,
+            revision: None,
+        };
+        let res_response = self.call_etcd(KV_RANGE_ENDPOINT, &range_request);
+
+        return res_response.and_then(|response| {
+            response
+                .into_json::<EtcdRangeResponse>()
+                .map(|range| {
+                    range
+                        .kvs
+                        .into_iter()
+                        .map(|kv| decodeb_64_safe(&kv.key))
+                        .collect()
+                })
+                .or(Err(KVError::ValueFormatErr))
+        });
     }
 }
 
 impl<'a> KVRemoteSource for EtcdRemote<'a> {
-    fn execute_kv_command(&self) {
-        todo!()
+    fn backend_name(&self) -> &'static str {
+        return "etcd";
+    }
+
+    fn can_watch(&self) -> bool {
+        return true;
     }
 
     fn list(&self, list_cfg: ListCmdConfig) -> Result<Vec<String>, KVError> {
-        todo!()
+        let prefix = create_path_linter()(list_cfg.prefix);
+
+        return self
+            .list_all_keys(&prefix)
+            .map(create_children_linter(prefix));
     }
 
+    fn read_all(&self, list_cfg: ListCmdConfig) -> Result<Vec<KVValue>, KVError> {
+        let (key, range_end) = encode_prefix_range(&create_path_linter()(list_cfg.prefix));
+        let range_request = EtcdRangeRequest {
+            key,
+            range_end: Some(range_end),
+            keys_only: false,
+            revision: None,
+        };
+        let res_response = self.call_etcd(KV_RANGE_ENDPOINT, &range_request);
+
+        return res_response.and_then(|response| {
+            response
+                .into_json::<EtcdRangeResponse>()
+                .map(|range| range.kvs.into_iter().map(to_kv_value()).collect())
+                .or(Err(KVError::ValueFormatErr))
+        });
+    }
+
+    /// Version is a store revision, e.g. `mod_revision` of an earlier value.
+    /// Revisions removed by compaction can not be read.
     fn read_path(&self, read_cfg: ReadCmdConfig) -> Result<KVValue, KVError> {
-        todo!()
+        let range_request = EtcdRangeRequest {
+            key: encode_b64(create_path_linter()(read_cfg.path).as_bytes()),
+            range_end: None,
+            keys_only: false,
+            revision: read_cfg.version.map(|revision| revision.to_string()),
+        };
+        let res_response = self.call_etcd(KV_RANGE_ENDPOINT, &range_request);
+
+        return res_response.and_then(process_etcd_response);
     }
 
-    fn write_path(&self, write_cfg: WriteCmdConfig) -> Result<(), KVError> {
-        todo!()
+    /// Write value. When `mod_revision` is set, value is written in a txn
+    /// that succeeds only if key was not modified after that revision.
+    fn write_value(
+        &self,
+        path: &str,
+        content: Vec<u8>,
+        mod_revision: Option<u64>,
+    ) -> Result<(), KVError> {
+        let key = encode_b64(create_path_linter()(path.to_owned()).as_bytes());
+        let put_request = EtcdPutRequest {
+            key: key.to_owned(),
+            value: encode_b64(&content),
+        };
+        return match mod_revision {
+            Some(revision) => {
+                self.call_guarded(key, revision, EtcdRequestOp::RequestPut(put_request))
+            }
+            None => self.call_etcd(KV_PUT_ENDPOINT, &put_request).map(|_| ()),
+        };
+    }
+
+    fn write_path(
+        &self,
+        write_cfg: WriteCmdConfig,
+        prompt: &dyn UserPrompt,
+    ) -> Result<(), KVError> {
+        let write_new_value = |content: Vec<u8>, version: Option<u64>| {
+            self.write_value(&write_cfg.path, content, version)
+        };
+        let read_cfg = ReadCmdConfig {
+            is_encoded: false,
+            is_hex: false,
+            out_file: None,
+            version: None,
+            path: write_cfg.path.to_owned(),
+        };
+
+        return if write_cfg.is_inline_edit {
+            // edit is written only if remote value was not modified meanwhile
+            let kv_val = self.read_path(read_cfg.clone())?;
+            let edited = kv_val.inline_edit_value()?;
+            let write_res = write_new_value(edited.as_bytes().to_vec(), kv_val.version);
+            offer_conflict_diff(
+                write_res,
+                &edited,
+                &|| self.read_path(read_cfg.clone()),
+                prompt,
+            )
+        } else {
+            match write_cfg.data_file.to_owned() {
+                Some(file) => {
+                    return fs::read(file)
+                        .or_else(KVError::wrap_as_write_err)
+                        .and_then(|content| write_new_value(content, None));
+                }
+                None => Ok(()),
+            }
+        };
+    }
+
+    fn delete_path(
+        &self,
+        delete_cfg: DeleteCmdConfig,
+        prompt: &dyn UserPrompt,
+    ) -> Result<(), KVError> {
+        let path = create_path_linter()(delete_cfg.path);
+        let keys = match delete_cfg.is_recursive {
+            true => self.list_all_keys(&path)?,
+            false => vec![
+                self.read_path(ReadCmdConfig {
+                    is_encoded: false,
+                    is_hex: false,
+                    out_file: None,
+                    version: None,
+                    path: path.to_owned(),
+                })?
+                .path,
+            ],
+        };
+        confirm_delete(&keys, delete_cfg.assume_yes, prompt)?;
+
+        let delete_request = match delete_cfg.is_recursive {
+            true => {
+                let (key, range_end) = encode_prefix_range(&path);
+                EtcdDeleteRequest {
+                    key,
+                    range_end: Some(range_end),
+                }
+            }
+            false => EtcdDeleteRequest {
+                key: encode_b64(path.as_bytes()),
+                range_end: None,
+            },
+        };
+
+        return self
+            .call_etcd(KV_DELETE_ENDPOINT, &delete_request)
+            .map(|_| ());
+    }
+
+    /// Watch with a single `/v3/watch` stream, events are passed on as they arrive.
+    /// Watch canceled by the server, e.g. after compaction, is a [`KVError::RemoteErr`].
+    fn watch(
+        &self,
+        prefix: &str,
+        on_event: &mut dyn FnMut(WatchEvent) -> Result<(), KVError>,
+    ) -> Result<(), KVError> {
+        let (key, range_end) = encode_prefix_range(&create_path_linter()(prefix.to_owned()));
+        let watch_request = EtcdWatchRequest {
+            create_request: EtcdWatchCreateRequest {
+                key,
+                range_end,
+                prev_kv: true,
+            },
+        };
+        let reader = self
+            .call_etcd(WATCH_ENDPOINT, &watch_request)?
+            .into_reader();
+
+        for message in serde_json::Deserializer::from_reader(reader).into_iter::<EtcdWatchMessage>()
+        {
+            let message = message.map_err(|err| KVError::NetworkErr(err.to_string()))?;
+            if let Some(err) = message.error {
+                return Err(KVError::RemoteErr {
+                    status: err.http_code.unwrap_or(500),
+                    message: err.message,
+                });
+            }
+            let Some(result) = message.result else {
+                continue;
+            };
+            if result.canceled {
+                return Err(KVError::RemoteErr {
+                    status: 500,
+                    message: result.cancel_message(),
+                });
+            }
+            result
+                .events
+                .into_iter()
+                .map(WatchEvent::from)
+                .try_for_each(&mut *on_event)?;
+        }
+        return Ok(());
+    }
+
+    /// Delete key. When `mod_revision` is set, key is deleted in a txn
+    /// that succeeds only if key was not modified after that revision.
+    fn delete_value(&self, path: &str, mod_revision: Option<u64>) -> Result<(), KVError> {
+        let key = encode_b64(create_path_linter()(path.to_owned()).as_bytes());
+        let delete_request = EtcdDeleteRequest {
+            key: key.to_owned(),
+            range_end: None,
+        };
+        return match mod_revision {
+            Some(revision) => self.call_guarded(
+                key,
+                revision,
+                EtcdRequestOp::RequestDeleteRange(delete_request),
+            ),
+            None => self
+                .call_etcd(KV_DELETE_ENDPOINT, &delete_request)
+                .map(|_| ()),
+        };
+    }
+}
+
+/// Error body of etcd gateway. `error` is kept by older gateway versions.
+#[derive(Debug, Deserialize)]
+struct EtcdErrorResponse {
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Extract gRPC error message from the gateway response, falling back to raw body.
+fn etcd_error_message(response: Response) -> String {
+    let body = response.into_string().unwrap_or_default();
+    return serde_json::from_str::<EtcdErrorResponse>(&body)
+        .ok()
+        .and_then(|err| err.message.or(err.error))
+        .unwrap_or_else(|| body.trim().to_owned());
+}
+
+/// etcd gateway translates gRPC status codes into HTTP ones
+fn remap_etcd_errors<T>(status: Error) -> Result<T, KVError> {
+    match status {
+        Error::Transport(transport) => Err(remap_transport_error(transport)),
+        Error::Status(status, response) => {
+            Err(KVError::from_status(status, etcd_error_message(response)))
+        }
     }
 }
@@ -1,29 +1,95 @@
-use ureq::{Error, Middleware, Request, Response};
+use std::error::Error as StdError;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::sync::{Arc, RwLock};
+
+use ureq::{Error, Middleware, Request, Response, Transport};
+
+use crate::kv_commons::KVError;
+use crate::kv_secret::TokenSource;
+use crate::kv_tls::TlsError;
+
+/// Token value shared between [`TokenAuthHeaderMiddleware`] and its owner,
+/// so that token can be (re)placed after a login.
+pub type SharedToken = Arc<RwLock<Option<String>>>;
 
 /// Token header authentication [`Middleware`] for [`Request`].
 pub struct TokenAuthHeaderMiddleware {
     header: String,
-    token: Option<String>,
+    token: SharedToken,
+    /// Where missing token is read from before a request
+    source: Option<TokenSource>,
 }
 
-/// Format a Basic auth string
-pub fn basic_auth(token: &String) -> String {
-    format!("Basic {}", token)
+/// Token could not be read from its [`TokenSource`]. Passed through `ureq` as a transport error.
+#[derive(Debug)]
+struct CredentialsError(String);
+
+impl Display for CredentialsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
 }
 
+impl StdError for CredentialsError {}
+
 impl TokenAuthHeaderMiddleware {
     pub fn new(header: String, token: Option<String>) -> Self {
-        Self { header, token }
+        Self::shared(header, Arc::new(RwLock::new(token)))
+    }
+
+    /// Ctor for middleware whose token is acquired or refreshed later on.
+    pub fn shared(header: String, token: SharedToken) -> Self {
+        Self {
+            header,
+            token,
+            source: None,
+        }
+    }
+
+    /// Read token from the source with the first request, and again whenever it is reset.
+    pub fn with_source(mut self, source: Option<TokenSource>) -> Self {
+        self.source = source;
+        self
     }
 }
 
 impl Middleware for TokenAuthHeaderMiddleware {
     /// [`Middleware`] implementation adds header only when token value exists.
     fn handle(&self, request: Request, next: ureq::MiddlewareNext) -> Result<Response, Error> {
-        let req: Request = match &self.token {
+        let is_missing = self.token.read().unwrap().is_none();
+        if let (true, Some(source)) = (is_missing, &self.source) {
+            let token = source
+                .resolve()
+                .map_err(|reason| io::Error::other(CredentialsError(reason)))?;
+            *self.token.write().unwrap() = Some(token);
+        }
+        let req: Request = match self.token.read().unwrap().as_ref() {
             Some(token) => request.set(self.header.as_str(), token.as_str()),
             _ => request,
         };
         next.handle(req)
     }
 }
+
+/// Error raised by a middleware or TLS connector and passed through `ureq` as I/O error.
+fn io_cause<E: StdError + 'static>(transport: &Transport) -> Option<&E> {
+    return transport
+        .source()
+        .and_then(|err| err.downcast_ref::<io::Error>())
+        .and_then(|err| err.get_ref())
+        .and_then(|err| err.downcast_ref::<E>());
+}
+
+/// Remap transport error of a request. Failed token reads of [`TokenAuthHeaderMiddleware`]
+/// are [`KVError::CredentialsErr`], unusable TLS settings are [`KVError::ConfigErr`],
+/// anything else is [`KVError::NetworkErr`].
+pub fn remap_transport_error(transport: Transport) -> KVError {
+    if let Some(err) = io_cause::<CredentialsError>(&transport) {
+        return KVError::CredentialsErr(err.0.to_owned());
+    }
+    if let Some(err) = io_cause::<TlsError>(&transport) {
+        return KVError::ConfigErr(format!("TLS: {err}"));
+    }
+    return KVError::NetworkErr(transport.to_string());
+}
@@ -0,0 +1,391 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, stdout, IsTerminal, Write};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::cli_def::{BrowseCmdConfig, ListCmdConfig, ReadCmdConfig, WriteCmdConfig};
+use crate::kv_commons::{KVError, KVRemoteSource, KVValue, UserPrompt};
+use crate::kv_tree::human_size;
+use crate::output::CommandOutput;
+use crate::utils::{create_path_linter, encode_b64};
+
+const PATH_DELIMITER: char = '/';
+const HELP: &str =
+    "↑↓ move  → expand  ← collapse  / search  n next  y copy path  e edit  r refresh  q quit";
+
+/// Visible line of the key tree.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    pub path: String,
+    /// Name relative to the parent folder, folders end with `/`
+    pub name: String,
+    pub depth: usize,
+    pub is_folder: bool,
+}
+
+/// Full-screen key tree browser. Children of a folder are listed when it is expanded for the first time,
+/// values are read when they are selected. See [`browse`].
+pub struct Browser<'a> {
+    remote: &'a dyn KVRemoteSource,
+    /// Asks what to do when an edit conflicts with remote changes
+    prompt: &'a dyn UserPrompt,
+    /// Prefix the tree starts at, empty or ending with `/`
+    root: String,
+    children: HashMap<String, Vec<String>>,
+    expanded: HashSet<String>,
+    /// Read values, or the error that happened on read
+    values: HashMap<String, Result<KVValue, String>>,
+    list_state: ListState,
+    /// Search query being typed
+    search: Option<String>,
+    last_query: String,
+    status: String,
+}
+
+impl<'a> Browser<'a> {
+    pub fn new(remote: &'a dyn KVRemoteSource, prompt: &'a dyn UserPrompt, prefix: &str) -> Self {
+        let root = match create_path_linter()(prefix.to_owned()) {
+            root if root.is_empty() || root.ends_with(PATH_DELIMITER) => root,
+            root => format!("{root}{PATH_DELIMITER}"),
+        };
+        let mut browser = Browser {
+            remote,
+            prompt,
+            root: root.to_owned(),
+            children: HashMap::new(),
+            expanded: HashSet::new(),
+            values: HashMap::new(),
+            list_state: ListState::default().with_selected(Some(0)),
+            search: None,
+            last_query: String::new(),
+            status: HELP.to_owned(),
+        };
+        browser.load_children(&root);
+        return browser;
+    }
+
+    fn load_children(&mut self, prefix: &str) {
+        if self.children.contains_key(prefix) {
+            return;
+        }
+        let children = match self.remote.list(ListCmdConfig {
+            prefix: prefix.to_owned(),
+        }) {
+            Ok(children) => children,
+            Err(KVError::NoValueErr(_)) => vec![],
+            Err(err) => {
+                self.status = format!("{prefix}: {err}");
+                vec![]
+            }
+        };
+        self.children.insert(prefix.to_owned(), children);
+    }
+
+    /// Rows of the tree in display order, children of expanded folders follow them.
+    pub fn rows(&self) -> Vec<Row> {
+        let mut rows = vec![];
+        self.collect_rows(&self.root, 0, &mut rows);
+        return rows;
+    }
+
+    fn collect_rows(&self, prefix: &str, depth: usize, rows: &mut Vec<Row>) {
+        for name in self.children.get(prefix).into_iter().flatten() {
+            let path = format!("{prefix}{name}");
+            let is_folder = name.ends_with(PATH_DELIMITER);
+            let is_expanded = self.expanded.contains(&path);
+            rows.push(Row {
+                path: path.to_owned(),
+                name: name.to_owned(),
+                depth,
+                is_folder,
+            });
+            if is_folder && is_expanded {
+                self.collect_rows(&path, depth + 1, rows);
+            }
+        }
+    }
+
+    pub fn selected_row(&self) -> Option<Row> {
+        return self.rows().into_iter().nth(self.list_state.selected()?);
+    }
+
+    pub fn select(&mut self, idx: usize) {
+        let last = self.rows().len().saturating_sub(1);
+        self.list_state.select(Some(idx.min(last)));
+    }
+
+    fn move_selection(&mut self, down: bool) {
+        let current = self.list_state.selected().unwrap_or_default();
+        match down {
+            true => self.select(current + 1),
+            false => self.select(current.saturating_sub(1)),
+        }
+    }
+
+    /// Expand selected folder, or collapse it when it is expanded.
+    pub fn expand(&mut self) {
+        let Some(row) = self.selected_row().filter(|row| row.is_folder) else {
+            return;
+        };
+        match self.expanded.contains(&row.path) {
+            true => self.expanded.remove(&row.path),
+            false => {
+                self.load_children(&row.path);
+                self.expanded.insert(row.path)
+            }
+        };
+    }
+
+    /// Collapse selected folder, or move to the parent one.
+    pub fn collapse(&mut self) {
+        let Some(row) = self.selected_row() else {
+            return;
+        };
+        if self.expanded.remove(&row.path) {
+            return;
+        }
+        let parent = row.path[..row.path.len() - row.name.len()].to_owned();
+        if let Some(idx) = self.rows().iter().position(|row| row.path == parent) {
+            self.select(idx);
+        }
+    }
+
+    /// Forget everything read, expanded folders are listed again.
+    fn refresh(&mut self) {
+        self.children.clear();
+        self.values.clear();
+        let mut prefixes: Vec<String> = self.expanded.iter().cloned().collect();
+        prefixes.push(self.root.to_owned());
+        prefixes.sort();
+        for prefix in prefixes {
+            self.load_children(&prefix);
+        }
+        self.select(self.list_state.selected().unwrap_or_default());
+        self.status = HELP.to_owned();
+    }
+
+    fn load_selected_value(&mut self) {
+        let Some(row) = self.selected_row().filter(|row| !row.is_folder) else {
+            return;
+        };
+        if self.values.contains_key(&row.path) {
+            return;
+        }
+        let read_res = self.remote.read_path(ReadCmdConfig {
+            is_encoded: false,
+            is_hex: false,
+            out_file: None,
+            version: None,
+            path: row.path.to_owned(),
+        });
+        self.values
+            .insert(row.path, read_res.map_err(|err| err.to_string()));
+    }
+
+    /// Search for `query` from the selected row on, see [`Browser::next_match`].
+    pub fn find(&mut self, query: &str) {
+        self.last_query = query.to_owned();
+        self.status = HELP.to_owned();
+        self.next_match();
+    }
+
+    /// Select next visible row after the selected one whose path contains the last query.
+    /// Search wraps around to the first row.
+    pub fn next_match(&mut self) {
+        let rows = self.rows();
+        let start = self
+            .list_state
+            .selected()
+            .map(|idx| idx + 1)
+            .unwrap_or_default();
+        let found = (0..rows.len())
+            .map(|offset| (start + offset) % rows.len())
+            .find(|idx| rows[*idx].path.contains(&self.last_query));
+        match found {
+            Some(idx) => self.select(idx),
+            None => self.status = format!("'{}' is not found in listed keys", self.last_query),
+        }
+    }
+
+    /// Put selected path to the terminal clipboard with an OSC 52 sequence.
+    fn copy_path(&mut self) {
+        let Some(row) = self.selected_row() else {
+            return;
+        };
+        let mut out = stdout();
+        let _ = write!(out, "\x1b]52;c;{}\x07", encode_b64(row.path.as_bytes()));
+        let _ = out.flush();
+        self.status = format!("Copied {}", row.path);
+    }
+
+    /// Edit selected value in $EDITOR with the same flow as `write --inline`. Terminal is handed over
+    /// to the editor meanwhile, failure is passed to [`UserPrompt::on_error`] before the tree is shown again.
+    fn edit(&mut self, terminal: &mut DefaultTerminal) {
+        let Some(row) = self.selected_row().filter(|row| !row.is_folder) else {
+            return;
+        };
+        ratatui::restore();
+        let write_res = self.remote.write_path(
+            WriteCmdConfig {
+                is_inline_edit: true,
+                data_file: None,
+                path: row.path.to_owned(),
+            },
+            self.prompt,
+        );
+        if let Err(err) = &write_res {
+            // keep conflict diff on screen until user has read it
+            self.prompt.on_error(err);
+        }
+        *terminal = ratatui::init();
+        self.values.remove(&row.path);
+        self.status = match write_res {
+            Ok(()) => format!("Saved {}", row.path),
+            Err(err) => format!("{}: {err}", row.path),
+        };
+    }
+
+    fn on_search_key(&mut self, code: KeyCode) {
+        let Some(query) = self.search.as_mut() else {
+            return;
+        };
+        match code {
+            KeyCode::Char(ch) => query.push(ch),
+            KeyCode::Backspace => {
+                query.pop();
+            }
+            KeyCode::Enter => {
+                let query = self.search.take().unwrap_or_default();
+                self.find(&query);
+            }
+            KeyCode::Esc => self.search = None,
+            _ => {}
+        }
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<(), KVError> {
+        loop {
+            self.load_selected_value();
+            terminal
+                .draw(|frame| self.draw(frame))
+                .or_else(KVError::wrap_as_write_err)?;
+            let Event::Key(key) = event::read().or_else(KVError::wrap_as_write_err)? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if self.search.is_some() {
+                self.on_search_key(key.code);
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(())
+                }
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Up | KeyCode::Char('k') => self.move_selection(false),
+                KeyCode::Down | KeyCode::Char('j') => self.move_selection(true),
+                KeyCode::Right | KeyCode::Enter | KeyCode::Char('l') => self.expand(),
+                KeyCode::Left | KeyCode::Char('h') => self.collapse(),
+                KeyCode::Char('/') => self.search = Some(String::new()),
+                KeyCode::Char('n') => self.next_match(),
+                KeyCode::Char('y') => self.copy_path(),
+                KeyCode::Char('e') => self.edit(terminal),
+                KeyCode::Char('r') => self.refresh(),
+                _ => {}
+            }
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main_area, status_area] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [tree_area, value_area] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(main_area);
+
+        let rows = self.rows();
+        let items: Vec<ListItem> = rows
+            .iter()
+            .map(|row| {
+                let marker = match (row.is_folder, self.expanded.contains(&row.path)) {
+                    (false, _) => "  ",
+                    (true, true) => "▾ ",
+                    (true, false) => "▸ ",
+                };
+                ListItem::new(format!("{}{marker}{}", "  ".repeat(row.depth), row.name))
+            })
+            .collect();
+        let tree_title = match self.root.is_empty() {
+            true => format!(" {} ", self.remote.backend_name()),
+            false => format!(" {}: {} ", self.remote.backend_name(), self.root),
+        };
+        let tree = List::new(items)
+            .block(Block::bordered().title(tree_title))
+            .highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(tree, tree_area, &mut self.list_state);
+
+        let selected = self.list_state.selected().and_then(|idx| rows.get(idx));
+        let (value_title, preview) = match selected {
+            None => ("".to_owned(), "".to_owned()),
+            Some(row) if row.is_folder => (row.path.to_owned(), "".to_owned()),
+            Some(row) => (row.path.to_owned(), self.preview(&row.path)),
+        };
+        let value = Paragraph::new(preview)
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title(format!(" {value_title} ")));
+        frame.render_widget(value, value_area);
+
+        let status = match &self.search {
+            Some(query) => format!("/{query}"),
+            None => self.status.to_owned(),
+        };
+        frame.render_widget(Paragraph::new(status), status_area);
+    }
+
+    /// Value text followed by its version and metadata.
+    fn preview(&self, path: &str) -> String {
+        let value = match self.values.get(path) {
+            None => return "".to_owned(),
+            Some(Err(err)) => return err.to_owned(),
+            Some(Ok(value)) => value,
+        };
+        let text = match value.as_text() {
+            Some(text) => text.to_owned(),
+            None => format!("<binary_value:{}>", human_size(value.value.len())),
+        };
+        let version = value
+            .version
+            .map(|version| format!("version: {version}\n"))
+            .unwrap_or_default();
+        let metadata: String = value
+            .metadata
+            .iter()
+            .map(|(name, field)| format!("{name}: {field}\n"))
+            .collect();
+        return format!("{text}\n\n{version}{metadata}");
+    }
+}
+
+/// Browse the key tree under the prefix in a full-screen terminal UI. Needs an interactive terminal.
+pub fn browse(
+    remote: &dyn KVRemoteSource,
+    browse_cmd: &BrowseCmdConfig,
+    prompt: &dyn UserPrompt,
+) -> Result<CommandOutput, KVError> {
+    if !io::stdout().is_terminal() {
+        return Err(KVError::UnsupportedErr);
+    }
+    let mut browser = Browser::new(remote, prompt, &browse_cmd.prefix);
+    let mut terminal = ratatui::init();
+    let browse_res = browser.run(&mut terminal);
+    ratatui::restore();
+    return browse_res.map(|_| CommandOutput::Done {
+        path: browse_cmd.prefix.to_owned(),
+    });
+}
@@ -1,75 +1,388 @@
-use std::borrow::ToOwned;
 use std::fmt::Formatter;
 use std::{error::Error, fmt::Display};
 
-use crate::cli_def::{ListCmdConfig, ReadCmdConfig, WriteCmdConfig};
-use edit;
+use crate::cli_def::{DeleteCmdConfig, ListCmdConfig, ReadCmdConfig, WriteCmdConfig};
+use crate::kv_watch::WatchEvent;
+use crate::utils::{decode_hex, encode_b64, encode_hex, try_decode_b64};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
+/// Value stored in remote. Holds exact bytes, which are not required to be valid UTF-8.
+///
+/// Serialized value is a plain string for UTF-8 text and Base 64 string with `encoding: base64` otherwise.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(into = "KVValueDocument", try_from = "KVValueDocument")]
 pub struct KVValue {
-    pub value: String,
     pub path: String,
+    pub value: Vec<u8>,
+    /// Remote specific version of the value, when remote keeps track of it
+    pub version: Option<u64>,
+    /// Remote specific details of the value, e.g. flags or indices
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub metadata: Map<String, Value>,
 }
 
 impl KVValue {
-    /// Send current value as buffer to system editor.
+    /// Value as text, if it is valid UTF-8.
+    pub fn as_text(&self) -> Option<&str> {
+        return std::str::from_utf8(&self.value).ok();
+    }
+
+    /**
+    Replace value with its text representation in given encoding. [`ValueEncoding::Utf8`] keeps value as is.
+
+    Examples:
+
+    ```
+    use kivi_rs::kv_commons::{KVValue, ValueEncoding};
+    let value = KVValue { path: "cert".to_owned(), value: vec![0x30, 0x82], version: None, metadata: Default::default() };
+
+    assert_eq!(b"MII=".to_vec(), value.clone().encode(ValueEncoding::Base64).value);
+    assert_eq!(b"3082".to_vec(), value.encode(ValueEncoding::Hex).value);
+    ```
+    */
+    pub fn encode(self, encoding: ValueEncoding) -> KVValue {
+        let value = match encoding {
+            ValueEncoding::Utf8 => self.value,
+            ValueEncoding::Base64 => encode_b64(&self.value).into_bytes(),
+            ValueEncoding::Hex => encode_hex(&self.value).into_bytes(),
+        };
+        return KVValue { value, ..self };
+    }
+
+    /// Send current value as buffer to system editor. Binary values can not be edited.
     ///
     /// Return new value after edit is complete.
     pub fn inline_edit_value(&self) -> Result<String, KVError> {
-        return edit::edit(self.value.to_owned()).or_else(|err| KVError::wrap_as_write_err(err));
+        let text = self.as_text().ok_or(KVError::ValueFormatErr)?;
+        return edit::edit(text).or_else(KVError::wrap_as_write_err);
     }
 }
 
 impl Display for KVValue {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", &self.value)
+        write!(f, "{}", String::from_utf8_lossy(&self.value))
     }
 }
 
-/// Configuration that dictates how to print [`KVValue`].
-#[derive(Debug, Clone, Copy)]
-pub struct KVDisplayConfig {
-    pub as_b64_encoded: bool,
+/// Text representation of value bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueEncoding {
+    /// bytes are valid UTF-8 text and are used as is
+    #[default]
+    Utf8,
+    Base64,
+    Hex,
+}
+
+impl ValueEncoding {
+    fn is_utf8(&self) -> bool {
+        return *self == ValueEncoding::Utf8;
+    }
+}
+
+/// Serialized form of [`KVValue`].
+#[derive(Serialize, Deserialize)]
+struct KVValueDocument {
+    path: String,
+    value: String,
+    #[serde(default, skip_serializing_if = "ValueEncoding::is_utf8")]
+    encoding: ValueEncoding,
+    version: Option<u64>,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    metadata: Map<String, Value>,
+}
+
+impl From<KVValue> for KVValueDocument {
+    fn from(kv_value: KVValue) -> Self {
+        let (value, encoding) = match String::from_utf8(kv_value.value) {
+            Ok(text) => (text, ValueEncoding::Utf8),
+            Err(err) => (encode_b64(err.as_bytes()), ValueEncoding::Base64),
+        };
+        return KVValueDocument {
+            path: kv_value.path,
+            value,
+            encoding,
+            version: kv_value.version,
+            metadata: kv_value.metadata,
+        };
+    }
+}
+
+impl TryFrom<KVValueDocument> for KVValue {
+    type Error = KVError;
+
+    fn try_from(document: KVValueDocument) -> Result<Self, Self::Error> {
+        let value = match document.encoding {
+            ValueEncoding::Utf8 => document.value.into_bytes(),
+            ValueEncoding::Base64 => {
+                try_decode_b64(&document.value).ok_or(KVError::ValueFormatErr)?
+            }
+            ValueEncoding::Hex => decode_hex(&document.value).ok_or(KVError::ValueFormatErr)?,
+        };
+        return Ok(KVValue {
+            path: document.path,
+            value,
+            version: document.version,
+            metadata: document.metadata,
+        });
+    }
 }
 
 /// Common errors that happen when one works with KV Storages.
 #[derive(Debug, Serialize, Deserialize)]
 pub enum KVError {
-    PermissionErr,
-    RemoteErr,
-    AuthenticationErr,
-    NoValueErr,
+    /// Remote refused the request. Carries the error reported by remote, if any
+    PermissionErr(String),
+    /// Remote responded with unexpected status. `message` is the error reported by remote, if any
+    RemoteErr {
+        status: u16,
+        message: String,
+    },
+    /// Remote could not be reached. Carries the transport error cause
+    NetworkErr(String),
+    /// Remote requires authentication or refused the credentials. Carries the reason, if any
+    AuthenticationErr(String),
+    /// Value does not exist. Carries the error reported by remote, if any
+    NoValueErr(String),
     ValueFormatErr,
     ValueWriteErr(String),
+    UnsupportedErr,
+    AbortedErr,
+    ConflictErr,
+    /// Template could not be parsed or rendered. Carries the reason
+    TemplateErr(String),
+    /// Config file or profile could not be used. Carries the reason
+    ConfigErr(String),
+    /// Token could not be read from its file, command or keyring. Carries the reason
+    CredentialsErr(String),
+    /// Command could not be run or failed, e.g. command run on watched change or unparsable shell line.
+    /// Carries the reason
+    CommandErr(String),
 }
 
 impl KVError {
     pub fn wrap_as_write_err<T>(err: impl Error) -> Result<T, KVError> {
         return Err(KVError::ValueWriteErr(format!("{}", err)));
     }
+
+    /**
+    Error for unsuccessful remote response status. `message` is the error reported by remote, if any.
+
+    Examples:
+
+    ```
+    use kivi_rs::kv_commons::KVError;
+
+    let err = KVError::from_status(403, "ACL not found".to_owned());
+    assert_eq!("Error: not enough permissions: ACL not found", err.to_string());
+    assert_eq!(1, KVError::from_status(500, "".to_owned()).exit_code());
+    ```
+    */
+    pub fn from_status(status: u16, message: String) -> KVError {
+        return match status {
+            401 => KVError::AuthenticationErr(message),
+            403 => KVError::PermissionErr(message),
+            404 => KVError::NoValueErr(message),
+            _ => KVError::RemoteErr { status, message },
+        };
+    }
+
+    /**
+    Process exit code for the error. Codes of commands that did not fail, e.g. copy with failed keys,
+    are set by [`crate::output::CommandOutput::exit_code`], both are listed below. Successful commands
+    exit with `0`, `exec` with the code of its command.
+
+    | Code | Meaning                                      | Errors                                                  |
+    |------|----------------------------------------------|---------------------------------------------------------|
+    | 1    | remote or command error, some keys failed    | [`KVError::RemoteErr`], [`KVError::CommandErr`], copy or apply with failed keys |
+    | 2    | bad input                                    | [`KVError::ValueFormatErr`], [`KVError::ValueWriteErr`], [`KVError::UnsupportedErr`], [`KVError::TemplateErr`], [`KVError::ConfigErr`] |
+    | 3    | not found                                    | [`KVError::NoValueErr`]                                 |
+    | 4    | authentication required or failed            | [`KVError::AuthenticationErr`], [`KVError::CredentialsErr`] |
+    | 5    | not enough permissions                       | [`KVError::PermissionErr`]                              |
+    | 6    | conflict, value changed remotely             | [`KVError::ConflictErr`]                                |
+    | 7    | network, remote is unreachable               | [`KVError::NetworkErr`]                                 |
+    | 8    | aborted by user                              | [`KVError::AbortedErr`]                                 |
+    | 9    | diff found differences                       | [`crate::output::DIFF_FOUND_EXIT_CODE`]                 |
+
+    Examples:
+
+    ```
+    use kivi_rs::kv_commons::KVError;
+
+    assert_eq!(3, KVError::NoValueErr("".to_owned()).exit_code());
+    assert_eq!(1, KVError::RemoteErr { status: 500, message: "".to_owned() }.exit_code());
+    ```
+    */
+    pub fn exit_code(&self) -> i32 {
+        return match self {
+            KVError::RemoteErr { .. } | KVError::CommandErr(_) => 1,
+            KVError::ValueFormatErr
+            | KVError::ValueWriteErr(_)
+            | KVError::UnsupportedErr
+            | KVError::TemplateErr(_)
+            | KVError::ConfigErr(_) => 2,
+            KVError::NoValueErr(_) => 3,
+            KVError::AuthenticationErr(_) | KVError::CredentialsErr(_) => 4,
+            KVError::PermissionErr(_) => 5,
+            KVError::ConflictErr => 6,
+            KVError::NetworkErr(_) => 7,
+            KVError::AbortedErr => 8,
+        };
+    }
 }
 
 impl Display for KVError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            KVError::PermissionErr => write!(f, "Error: not enough permissions"),
-            KVError::RemoteErr => write!(f, "Error: remote returned error"),
-            KVError::AuthenticationErr => write!(f, "Error: remote authentication required"),
-            KVError::NoValueErr => write!(f, "<unknown_value>"),
+            KVError::PermissionErr(message) if message.is_empty() => {
+                write!(f, "Error: not enough permissions")
+            }
+            KVError::PermissionErr(message) => {
+                write!(f, "Error: not enough permissions: {message}")
+            }
+            KVError::RemoteErr { status, message } if message.is_empty() => {
+                write!(f, "Error: remote returned error {status}")
+            }
+            KVError::RemoteErr { status, message } => {
+                write!(f, "Error: remote returned error {status}: {message}")
+            }
+            KVError::NetworkErr(cause) => write!(f, "Error: remote is unreachable: {cause}"),
+            KVError::AuthenticationErr(reason) if reason.is_empty() => {
+                write!(f, "Error: remote authentication required")
+            }
+            KVError::AuthenticationErr(reason) => {
+                write!(f, "Error: remote authentication required: {reason}")
+            }
+            KVError::NoValueErr(message) if message.is_empty() => write!(f, "<unknown_value>"),
+            KVError::NoValueErr(message) => write!(f, "<unknown_value>: {message}"),
             KVError::ValueFormatErr => write!(f, "<err_value>"),
             KVError::ValueWriteErr(msg) => write!(f, "<file_error:{}>", msg),
+            KVError::UnsupportedErr => write!(f, "Error: operation is not supported by remote"),
+            KVError::AbortedErr => write!(f, "Error: operation aborted"),
+            KVError::ConflictErr => {
+                write!(f, "Error: value was modified remotely after it was read")
+            }
+            KVError::TemplateErr(reason) => write!(f, "Error: template: {reason}"),
+            KVError::ConfigErr(reason) => write!(f, "Error: config: {reason}"),
+            KVError::CredentialsErr(reason) => write!(f, "Error: credentials: {reason}"),
+            KVError::CommandErr(reason) => write!(f, "Error: command: {reason}"),
         }
     }
 }
 
+/// Questions commands ask before remote is changed. Library code never reads from or prints
+/// to the terminal itself, the binary answers these with interactive prompts.
+pub trait UserPrompt {
+    /// Ask to proceed. `details` describe what is going to happen, e.g. list keys to delete.
+    fn confirm(&self, details: &str, question: &str) -> bool;
+
+    /// Edited value was not written, as remote value changed while it was edited.
+    /// `read_remote` reads current remote value, e.g. to show how it differs from `edited`.
+    fn on_conflict(&self, edited: &str, read_remote: &dyn Fn() -> Result<KVValue, KVError>);
+
+    /// Command failed while it had the terminal, e.g. an edit from the key browser.
+    /// The error should stay visible until user has read it.
+    fn on_error(&self, err: &KVError);
+}
+
+/// Answers every question with "no", for use without a terminal.
+/// Commands that change remote need their `assume_yes` flag then.
+pub struct NoPrompt;
+
+impl UserPrompt for NoPrompt {
+    fn confirm(&self, _details: &str, _question: &str) -> bool {
+        return false;
+    }
+
+    fn on_conflict(&self, _edited: &str, _read_remote: &dyn Fn() -> Result<KVValue, KVError>) {}
+
+    fn on_error(&self, _err: &KVError) {}
+}
+
+/// Ask user to confirm removal of listed keys. Nothing is asked when `assume_yes` is set.
+pub fn confirm_delete(
+    keys: &[String],
+    assume_yes: bool,
+    prompt: &dyn UserPrompt,
+) -> Result<(), KVError> {
+    if keys.is_empty() {
+        return Err(KVError::NoValueErr("".to_owned()));
+    }
+    if assume_yes {
+        return Ok(());
+    }
+    let details = keys.iter().fold(
+        "Following keys will be deleted:".to_owned(),
+        |details, key| format!("{details}\n  {key}"),
+    );
+
+    return match prompt.confirm(&details, "Proceed?") {
+        true => Ok(()),
+        false => Err(KVError::AbortedErr),
+    };
+}
+
+/// Pass `write_res` through. On [`KVError::ConflictErr`] user is offered to see
+/// how current remote value differs from the local edit.
+pub fn offer_conflict_diff(
+    write_res: Result<(), KVError>,
+    edited: &str,
+    read_remote: &dyn Fn() -> Result<KVValue, KVError>,
+    prompt: &dyn UserPrompt,
+) -> Result<(), KVError> {
+    if matches!(write_res, Err(KVError::ConflictErr)) {
+        prompt.on_conflict(edited, read_remote);
+    }
+    return write_res;
+}
+
 /// Abstract trait suitable _(hopefully)_ for any Key Value storage.
 pub trait KVRemoteSource {
-    fn execute_kv_command(&self);
+    /// Short remote name, e.g. `consul`. Shown in structured output.
+    fn backend_name(&self) -> &'static str;
 
     fn list(&self, list_cfg: ListCmdConfig) -> Result<Vec<String>, KVError>;
 
+    /// Read every value under the prefix, at any depth.
+    fn read_all(&self, list_cfg: ListCmdConfig) -> Result<Vec<KVValue>, KVError>;
+
     fn read_path(&self, read_cfg: ReadCmdConfig) -> Result<KVValue, KVError>;
 
-    fn write_path(&self, write_cfg: WriteCmdConfig) -> Result<(), KVError>;
+    /// Inline edit asks `prompt` what to do when remote value changed meanwhile.
+    fn write_path(&self, write_cfg: WriteCmdConfig, prompt: &dyn UserPrompt)
+        -> Result<(), KVError>;
+
+    /// Write exact bytes under the path. When `version` is set, value is written only
+    /// if remote version still matches it, otherwise [`KVError::ConflictErr`] is returned.
+    /// Version `0` writes the value only if the key does not exist.
+    fn write_value(
+        &self,
+        path: &str,
+        content: Vec<u8>,
+        version: Option<u64>,
+    ) -> Result<(), KVError>;
+
+    /// Keys are deleted once `prompt` confirms it, unless `assume_yes` is set.
+    fn delete_path(
+        &self,
+        delete_cfg: DeleteCmdConfig,
+        prompt: &dyn UserPrompt,
+    ) -> Result<(), KVError>;
+
+    /// Delete a single key without confirmation. `version` guards the delete the same way
+    /// it guards [`KVRemoteSource::write_value()`].
+    fn delete_value(&self, path: &str, version: Option<u64>) -> Result<(), KVError>;
+
+    /// Whether [`KVRemoteSource::watch()`] is supported at all.
+    fn can_watch(&self) -> bool;
+
+    /// Block and pass every change under the prefix to `on_event` until the remote closes
+    /// the watch or `on_event` fails. Changes made before the call are not reported.
+    fn watch(
+        &self,
+        prefix: &str,
+        on_event: &mut dyn FnMut(WatchEvent) -> Result<(), KVError>,
+    ) -> Result<(), KVError>;
 }
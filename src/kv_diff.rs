@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use serde::Serialize;
+use ureq::AgentBuilder;
+
+use crate::cli_def::ListCmdConfig;
+use crate::kv_commons::{KVError, KVRemoteSource, KVValue};
+use crate::kv_dir::read_dir_values;
+use crate::kv_profile::uri_remote_config;
+use crate::kv_uri::KVUri;
+use crate::utils::{join_path, unified_diff};
+
+const PATH_DELIMITER: char = '/';
+
+/// One side of a diff: remote prefix or local directory.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffSide {
+    Remote(KVUri),
+    Directory(PathBuf),
+}
+
+impl FromStr for DiffSide {
+    type Err = String;
+
+    /// Anything that looks like `<backend>://` is a remote, the rest is a directory path.
+    fn from_str(side: &str) -> Result<Self, Self::Err> {
+        if side.contains("://") {
+            return side.parse().map(DiffSide::Remote);
+        }
+        return Ok(DiffSide::Directory(PathBuf::from(side)));
+    }
+}
+
+impl Display for DiffSide {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            DiffSide::Remote(uri) => write!(f, "{uri}"),
+            DiffSide::Directory(dir) => write!(f, "{}", dir.display()),
+        };
+    }
+}
+
+impl DiffSide {
+    pub fn backend_name(&self) -> &'static str {
+        return match self {
+            DiffSide::Remote(uri) => uri.backend.name(),
+            DiffSide::Directory(_) => "directory",
+        };
+    }
+
+    /// Values of the side with keys relative to its prefix or directory. Remote is reached with
+    /// credentials of `profile`, if any. Consul folder keys, paths ending with `/`, are left out.
+    pub fn read_values(
+        &self,
+        agent_builder: AgentBuilder,
+        profile: Option<&str>,
+    ) -> Result<Vec<KVValue>, KVError> {
+        let uri = match self {
+            DiffSide::Directory(dir) => return read_dir_values(dir, ""),
+            DiffSide::Remote(uri) => uri,
+        };
+        let remote_cfg = uri_remote_config(uri, profile)?;
+        return relative_values(remote_cfg.build_remote(agent_builder).as_ref(), &uri.prefix);
+    }
+}
+
+/**
+Values under `prefix` with keys relative to it. Only keys under the `prefix/` folder are read,
+so `svc/metadata/top` is not a part of `svc/meta`. Consul folder keys, paths ending with `/`,
+are left out.
+
+Examples:
+
+```no_run
+use kivi_rs::kv_diff::relative_values;
+# use kivi_rs::kv_commons::KVRemoteSource;
+# fn example(remote: &dyn KVRemoteSource) {
+// svc/meta/top -> top
+let values = relative_values(remote, "svc/meta").unwrap();
+# }
+```
+*/
+pub fn relative_values(remote: &dyn KVRemoteSource, prefix: &str) -> Result<Vec<KVValue>, KVError> {
+    let prefix = join_path(prefix, "");
+    let values = remote.read_all(ListCmdConfig {
+        prefix: prefix.to_owned(),
+    })?;
+    return Ok(values
+        .into_iter()
+        .filter(|value| !value.path.ends_with(PATH_DELIMITER))
+        .map(|value| KVValue {
+            path: value
+                .path
+                .strip_prefix(&prefix)
+                .unwrap_or(&value.path)
+                .trim_start_matches(PATH_DELIMITER)
+                .to_owned(),
+            ..value
+        })
+        .collect());
+}
+
+/// Difference of a single key, from left side to right one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffAction {
+    /// Only right side has the key
+    Added,
+    /// Only left side has the key
+    Removed,
+    Changed,
+}
+
+impl DiffAction {
+    /// Single character marker used in plain text diffs.
+    pub fn marker(&self) -> char {
+        return match self {
+            DiffAction::Added => '+',
+            DiffAction::Removed => '-',
+            DiffAction::Changed => '~',
+        };
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffEntry {
+    pub path: String,
+    pub action: DiffAction,
+    /// Unified diff of changed text values. Binary values have none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+}
+
+/// Keys that differ between two sides.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KVDiff {
+    pub left: String,
+    pub right: String,
+    pub entries: Vec<DiffEntry>,
+    pub unchanged: usize,
+}
+
+impl KVDiff {
+    /**
+    Compare values of two sides by their relative keys.
+
+    Examples:
+
+    ```
+    use kivi_rs::kv_commons::KVValue;
+    use kivi_rs::kv_diff::{DiffAction, KVDiff};
+    let value = |path: &str, value: &str| KVValue { path: path.to_owned(), value: value.as_bytes().to_vec(), version: None, metadata: Default::default() };
+
+    let left = vec![value("a", "1\n"), value("b", "x\n"), value("c", "same")];
+    let right = vec![value("b", "y\n"), value("c", "same"), value("d", "new")];
+    let diff = KVDiff::build("staging", left, "prod", right);
+
+    assert_eq!("1 added, 1 removed, 1 changed, 1 unchanged", diff.summary());
+    assert_eq!(DiffAction::Removed, diff.entries[0].action);
+    assert_eq!("--- a/b\n+++ b/b\n@@ -1 +1 @@\n-x\n+y\n", diff.entries[1].diff.as_deref().unwrap());
+    ```
+    */
+    pub fn build(
+        left_name: &str,
+        left: Vec<KVValue>,
+        right_name: &str,
+        right: Vec<KVValue>,
+    ) -> KVDiff {
+        let mut left: BTreeMap<String, Vec<u8>> = left
+            .into_iter()
+            .map(|value| (value.path, value.value))
+            .collect();
+        let mut diff = KVDiff {
+            left: left_name.to_owned(),
+            right: right_name.to_owned(),
+            ..Default::default()
+        };
+        let mut entries = BTreeMap::new();
+        for value in right {
+            let entry = match left.remove(&value.path) {
+                None => DiffEntry {
+                    path: value.path.to_owned(),
+                    action: DiffAction::Added,
+                    diff: None,
+                },
+                Some(left_value) if left_value == value.value => {
+                    diff.unchanged += 1;
+                    continue;
+                }
+                Some(left_value) => DiffEntry {
+                    path: value.path.to_owned(),
+                    action: DiffAction::Changed,
+                    diff: text_diff(&value.path, &left_value, &value.value),
+                },
+            };
+            entries.insert(value.path, entry);
+        }
+        for path in left.into_keys() {
+            let entry = DiffEntry {
+                path: path.to_owned(),
+                action: DiffAction::Removed,
+                diff: None,
+            };
+            entries.insert(path, entry);
+        }
+        diff.entries = entries.into_values().collect();
+        return diff;
+    }
+
+    pub fn count(&self, action: DiffAction) -> usize {
+        return self
+            .entries
+            .iter()
+            .filter(|entry| entry.action == action)
+            .count();
+    }
+
+    pub fn has_changes(&self) -> bool {
+        return !self.entries.is_empty();
+    }
+
+    pub fn summary(&self) -> String {
+        return format!(
+            "{} added, {} removed, {} changed, {} unchanged",
+            self.count(DiffAction::Added),
+            self.count(DiffAction::Removed),
+            self.count(DiffAction::Changed),
+            self.unchanged
+        );
+    }
+
+    /// Plain text diff, one key per line prefixed with [`DiffAction::marker()`] and followed by its value diff.
+    pub fn render(&self) -> String {
+        return self
+            .entries
+            .iter()
+            .map(|entry| match &entry.diff {
+                Some(diff) => format!("{} {}\n{diff}", entry.action.marker(), entry.path),
+                None => format!("{} {}\n", entry.action.marker(), entry.path),
+            })
+            .collect();
+    }
+}
+
+/// Unified diff of values when both are text.
+fn text_diff(path: &str, left: &[u8], right: &[u8]) -> Option<String> {
+    let left = std::str::from_utf8(left).ok()?;
+    let right = std::str::from_utf8(right).ok()?;
+    return Some(unified_diff(
+        left,
+        right,
+        &format!("a/{path}"),
+        &format!("b/{path}"),
+    ));
+}
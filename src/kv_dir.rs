@@ -0,0 +1,52 @@
+use std::fs;
+use std::path::Path;
+
+use serde_json::Map;
+
+use crate::kv_commons::{KVError, KVValue};
+use crate::utils::join_path;
+
+/**
+Read every file under `root` as a value. Key is the file path relative to `root` joined with `/`
+and prepended with `prefix`. Hidden files and directories, e.g. `.git`, are skipped.
+
+Values are sorted by key.
+
+Examples:
+
+```no_run
+use std::path::Path;
+use kivi_rs::kv_dir::read_dir_values;
+
+// config/service-a/config -> svc/meta/service-a/config
+let values = read_dir_values(Path::new("config"), "svc/meta").unwrap();
+```
+*/
+pub fn read_dir_values(root: &Path, prefix: &str) -> Result<Vec<KVValue>, KVError> {
+    let mut values = vec![];
+    collect_files(root, prefix, &mut values)?;
+    values.sort_by(|left, right| left.path.cmp(&right.path));
+    return Ok(values);
+}
+
+fn collect_files(dir: &Path, prefix: &str, values: &mut Vec<KVValue>) -> Result<(), KVError> {
+    for entry in fs::read_dir(dir).or_else(KVError::wrap_as_write_err)? {
+        let entry = entry.or_else(KVError::wrap_as_write_err)?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = join_path(prefix, &name);
+        if entry.path().is_dir() {
+            collect_files(&entry.path(), &path, values)?;
+            continue;
+        }
+        values.push(KVValue {
+            path,
+            value: fs::read(entry.path()).or_else(KVError::wrap_as_write_err)?,
+            version: None,
+            metadata: Map::new(),
+        });
+    }
+    return Ok(());
+}
@@ -0,0 +1,148 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::kv_commons::{KVError, KVValue};
+use crate::utils::{encode_b64, join_path, try_decode_b64};
+
+/// Version of [`KiviDump`] layout, bumped on incompatible changes.
+pub const KIVI_DUMP_VERSION: u32 = 1;
+
+/// Layout of prefix dumps.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// `consul kv export` compatible: key, flags and base64 value
+    #[default]
+    Consul,
+    /// portable dump that keeps backend metadata of every value
+    Kivi,
+}
+
+/// Entry of `consul kv export` dump.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsulDumpEntry {
+    pub key: String,
+    #[serde(default)]
+    pub flags: u64,
+    /// Base 64 encoded value bytes
+    pub value: String,
+}
+
+impl From<&KVValue> for ConsulDumpEntry {
+    /// Consul flags are kept, values of other backends get `0`.
+    fn from(kv_value: &KVValue) -> Self {
+        let flags = kv_value
+            .metadata
+            .get("flags")
+            .and_then(Value::as_u64)
+            .unwrap_or_default();
+        return ConsulDumpEntry {
+            key: kv_value.path.to_owned(),
+            flags,
+            value: encode_b64(&kv_value.value),
+        };
+    }
+}
+
+impl TryFrom<ConsulDumpEntry> for KVValue {
+    type Error = KVError;
+
+    fn try_from(entry: ConsulDumpEntry) -> Result<Self, Self::Error> {
+        return Ok(KVValue {
+            path: entry.key,
+            value: try_decode_b64(&entry.value).ok_or(KVError::ValueFormatErr)?,
+            version: None,
+            metadata: Map::from_iter([("flags".to_owned(), Value::from(entry.flags))]),
+        });
+    }
+}
+
+/// Portable dump of a prefix subtree taken from any backend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KiviDump {
+    pub kivi_dump: u32,
+    /// Backend the dump was taken from
+    pub backend: String,
+    pub prefix: String,
+    pub values: Vec<KVValue>,
+}
+
+/**
+Build dump of values under the prefix in requested format.
+
+Examples:
+
+```
+use kivi_rs::kv_commons::KVValue;
+use kivi_rs::kv_dump::{export_dump, DumpFormat};
+let values = vec![KVValue { path: "svc/top".to_owned(), value: b"x".to_vec(), version: Some(3), metadata: Default::default() }];
+
+let consul_dump = export_dump(DumpFormat::Consul, "consul", "svc/", values.clone());
+assert_eq!(r#"[{"key":"svc/top","flags":0,"value":"eA=="}]"#, consul_dump.to_string());
+
+let kivi_dump = export_dump(DumpFormat::Kivi, "consul", "svc/", values);
+assert_eq!("x", kivi_dump["values"][0]["value"]);
+assert_eq!("consul", kivi_dump["backend"]);
+```
+*/
+pub fn export_dump(format: DumpFormat, backend: &str, prefix: &str, values: Vec<KVValue>) -> Value {
+    let dump = match format {
+        DumpFormat::Consul => serde_json::to_value(
+            values
+                .iter()
+                .map(ConsulDumpEntry::from)
+                .collect::<Vec<ConsulDumpEntry>>(),
+        ),
+        DumpFormat::Kivi => serde_json::to_value(KiviDump {
+            kivi_dump: KIVI_DUMP_VERSION,
+            backend: backend.to_owned(),
+            prefix: prefix.to_owned(),
+            values,
+        }),
+    };
+    return dump.unwrap_or_default();
+}
+
+/// Dump of any [`DumpFormat`], told apart by its layout.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AnyDump {
+    Consul(Vec<ConsulDumpEntry>),
+    Kivi(KiviDump),
+}
+
+/**
+Parse dump of any [`DumpFormat`] into values. `prefix` is prepended to every key.
+
+Examples:
+
+```
+use kivi_rs::kv_dump::parse_dump;
+let dump = br#"[{"key": "service-a/config", "flags": 0, "value": "eA=="}]"#;
+
+let values = parse_dump(dump, Some("svc/meta/")).unwrap();
+assert_eq!("svc/meta/service-a/config", values[0].path);
+assert_eq!(b"x".to_vec(), values[0].value);
+```
+*/
+pub fn parse_dump(content: &[u8], prefix: Option<&str>) -> Result<Vec<KVValue>, KVError> {
+    let values = match serde_json::from_slice::<AnyDump>(content) {
+        Err(_) => return Err(KVError::ValueFormatErr),
+        Ok(AnyDump::Kivi(dump)) if dump.kivi_dump > KIVI_DUMP_VERSION => {
+            return Err(KVError::UnsupportedErr)
+        }
+        Ok(AnyDump::Kivi(dump)) => dump.values,
+        Ok(AnyDump::Consul(entries)) => entries
+            .into_iter()
+            .map(KVValue::try_from)
+            .collect::<Result<Vec<KVValue>, KVError>>()?,
+    };
+
+    return Ok(values
+        .into_iter()
+        .map(|value| KVValue {
+            path: join_path(prefix.unwrap_or_default(), &value.path),
+            ..value
+        })
+        .collect());
+}
@@ -0,0 +1,272 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::process::{Child, Command};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cli_def::{ExecCmdConfig, ListCmdConfig};
+use crate::kv_commons::{KVError, KVRemoteSource};
+use crate::output::CommandOutput;
+use crate::utils::{create_path_linter, join_path};
+
+const PATH_DELIMITER: char = '/';
+/// How often watched child is checked for exit
+const CHILD_POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// Time a child is given to stop after `SIGTERM` before it is killed, same as envconsul default
+const KILL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Turns key paths into environment variable names.
+#[derive(Debug, Clone, Default)]
+pub struct EnvNaming {
+    /// Prefix removed from key paths, if any
+    pub strip_prefix: Option<String>,
+    /// Replace characters other than ASCII letters, digits and `_` with `_`
+    pub sanitize: bool,
+    pub upcase: bool,
+}
+
+impl From<&ExecCmdConfig> for EnvNaming {
+    fn from(exec_cmd: &ExecCmdConfig) -> Self {
+        return EnvNaming {
+            strip_prefix: match exec_cmd.keep_prefix {
+                true => None,
+                false => Some(create_path_linter()(exec_cmd.prefix.to_owned())),
+            },
+            sanitize: !exec_cmd.no_sanitize,
+            upcase: !exec_cmd.no_upcase,
+        };
+    }
+}
+
+impl EnvNaming {
+    /**
+    Environment variable name of the key.
+
+    Examples:
+
+    ```
+    use kivi_rs::kv_exec::EnvNaming;
+    let naming = EnvNaming { strip_prefix: Some("app/config".to_owned()), sanitize: true, upcase: true };
+
+    assert_eq!("DB_URL", naming.name("app/config/db-url"));
+    assert_eq!("CACHE_TTL", naming.name("app/config/cache/ttl"));
+    assert_eq!("app/config/db-url", EnvNaming::default().name("app/config/db-url"));
+    ```
+    */
+    pub fn name(&self, path: &str) -> String {
+        let name = match &self.strip_prefix {
+            Some(prefix) => path.strip_prefix(prefix.as_str()).unwrap_or(path),
+            None => path,
+        }
+        .trim_start_matches(PATH_DELIMITER);
+        let name: String = match self.sanitize {
+            true => name
+                .chars()
+                .map(|ch| match ch.is_ascii_alphanumeric() {
+                    true => ch,
+                    false => '_',
+                })
+                .collect(),
+            false => name.to_owned(),
+        };
+        return match self.upcase {
+            true => name.to_uppercase(),
+            false => name,
+        };
+    }
+}
+
+/// Read values under the prefix as environment variables. Folder keys are skipped,
+/// values that are not valid UTF-8 are passed lossy. Keys that turn into the same name,
+/// e.g. `db-url` and `db_url`, are refused with [`KVError::ConfigErr`].
+fn read_env(
+    remote: &dyn KVRemoteSource,
+    prefix: &str,
+    naming: &EnvNaming,
+) -> Result<BTreeMap<String, String>, KVError> {
+    let values = remote.read_all(ListCmdConfig {
+        prefix: prefix.to_owned(),
+    })?;
+    let mut env = BTreeMap::new();
+    let mut sources: BTreeMap<String, String> = BTreeMap::new();
+    for value in values
+        .into_iter()
+        .filter(|value| !value.path.ends_with(PATH_DELIMITER))
+    {
+        let name = naming.name(&value.path);
+        if name.is_empty() {
+            continue;
+        }
+        if let Some(other) = sources.insert(name.to_owned(), value.path.to_owned()) {
+            return Err(KVError::ConfigErr(format!(
+                "keys '{other}' and '{}' are both passed as {name}",
+                value.path
+            )));
+        }
+        env.insert(name, String::from_utf8_lossy(&value.value).into_owned());
+    }
+    return Ok(env);
+}
+
+fn start_failed(command: &[String], err: io::Error) -> KVError {
+    return KVError::ValueWriteErr(format!("'{}' failed to start: {err}", command[0]));
+}
+
+fn build_command(command: &[String], env: &BTreeMap<String, String>) -> Command {
+    let mut child = Command::new(&command[0]);
+    child.args(&command[1..]).envs(env);
+    return child;
+}
+
+/// Replace current process with the command. Returns only if it could not be started.
+#[cfg(unix)]
+fn exec_command(
+    command: &[String],
+    env: &BTreeMap<String, String>,
+) -> Result<CommandOutput, KVError> {
+    use std::os::unix::process::CommandExt;
+
+    return Err(start_failed(command, build_command(command, env).exec()));
+}
+
+/// Processes can not be replaced, so the command is run as a child and its exit code is passed on.
+#[cfg(not(unix))]
+fn exec_command(
+    command: &[String],
+    env: &BTreeMap<String, String>,
+) -> Result<CommandOutput, KVError> {
+    return match build_command(command, env).status() {
+        Ok(status) => Ok(CommandOutput::Exited {
+            code: status.code().unwrap_or(1),
+        }),
+        Err(err) => Err(start_failed(command, err)),
+    };
+}
+
+fn spawn_command(command: &[String], env: &BTreeMap<String, String>) -> Result<Child, KVError> {
+    return build_command(command, env)
+        .spawn()
+        .map_err(|err| start_failed(command, err));
+}
+
+/// Ask the child to stop with `SIGTERM` and kill it when it is still running after [`KILL_TIMEOUT`].
+#[cfg(unix)]
+fn stop_child(mut child: Child) {
+    // a reaped child's pid may already belong to another process
+    if let Ok(Some(_)) = child.try_wait() {
+        return;
+    }
+    // SAFETY: plain syscall, the child is not reaped, so the pid is still its own
+    let is_signaled = unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) } == 0;
+    let deadline = Instant::now() + KILL_TIMEOUT;
+    while is_signaled && Instant::now() < deadline {
+        if let Ok(Some(_)) = child.try_wait() {
+            return;
+        }
+        thread::sleep(CHILD_POLL_INTERVAL);
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Processes can not be signaled, so the child is killed right away.
+#[cfg(not(unix))]
+fn stop_child(mut child: Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// Pass the child's exit code to `on_exit` once the child stops on its own. Restarts hold
+/// the lock, so a stopped child is never seen here. Returns once the child is taken away.
+fn watch_child_exit(child: Arc<Mutex<Option<Child>>>, on_exit: fn(i32)) {
+    thread::spawn(move || loop {
+        thread::sleep(CHILD_POLL_INTERVAL);
+        let status = match child.lock().unwrap().as_mut() {
+            Some(running) => running.try_wait(),
+            None => return,
+        };
+        if let Ok(Some(status)) = status {
+            on_exit(status.code().unwrap_or(1));
+            return;
+        }
+    });
+}
+
+/// Prefix the environment is read from. Trailing delimiter keeps sibling prefixes,
+/// e.g. `app/configuration`, out of the environment.
+fn env_prefix(exec_cmd: &ExecCmdConfig) -> String {
+    return join_path(&create_path_linter()(exec_cmd.prefix.to_owned()), "");
+}
+
+/**
+Run the command with values under the prefix as environment variables, see [`EnvNaming`].
+
+Current process is replaced with the command, so this returns only when it could not be started.
+Where processes can not be replaced, the command runs as a child and its exit code is returned
+as [`CommandOutput::Exited`]. `--watch` is not handled here, see [`exec_watched`].
+*/
+pub fn exec_with_env(
+    remote: &dyn KVRemoteSource,
+    exec_cmd: &ExecCmdConfig,
+) -> Result<CommandOutput, KVError> {
+    let naming = EnvNaming::from(exec_cmd);
+    let env = read_env(remote, &env_prefix(exec_cmd), &naming)?;
+    return exec_command(&exec_cmd.command, &env);
+}
+
+/**
+Run the command as a child with values under the prefix as environment variables, see [`EnvNaming`].
+The child is stopped and started again with fresh values whenever they change. Child is stopped with
+`SIGTERM` first and killed only if it outlives [`KILL_TIMEOUT`].
+
+When the child stops on its own while the watch is running, its exit code is passed to `on_exit`
+from a background thread. The watch blocks on remote, so `on_exit` is expected to end the process.
+When remote closes the watch, the child keeps running without restarts and its exit code is returned
+as [`CommandOutput::Exited`]. When the watch fails, the child is stopped and the error returned.
+*/
+pub fn exec_watched(
+    remote: &dyn KVRemoteSource,
+    exec_cmd: &ExecCmdConfig,
+    on_exit: fn(i32),
+) -> Result<CommandOutput, KVError> {
+    if !remote.can_watch() {
+        return Err(KVError::UnsupportedErr);
+    }
+    let naming = EnvNaming::from(exec_cmd);
+    let prefix = env_prefix(exec_cmd);
+    let mut current_env = read_env(remote, &prefix, &naming)?;
+
+    let first_child = spawn_command(&exec_cmd.command, &current_env)?;
+    let child = Arc::new(Mutex::new(Some(first_child)));
+    watch_child_exit(Arc::clone(&child), on_exit);
+    let watch_res = remote.watch(&prefix, &mut |_event| {
+        // a single change may come as several events, the child is restarted once
+        let env = read_env(remote, &prefix, &naming)?;
+        if env == current_env {
+            return Ok(());
+        }
+        let mut running = child.lock().unwrap();
+        if let Some(stale) = running.take() {
+            stop_child(stale);
+        }
+        *running = Some(spawn_command(&exec_cmd.command, &env)?);
+        current_env = env;
+        return Ok(());
+    });
+    // taken child is not watched for exit anymore
+    let Some(mut running) = child.lock().unwrap().take() else {
+        // restart could not spawn the child and ended the watch with its error
+        return watch_res.map(|_| CommandOutput::Done { path: prefix });
+    };
+    if let Err(err) = watch_res {
+        stop_child(running);
+        return Err(err);
+    }
+
+    // remote closed the watch, child keeps running without restarts
+    let status = running.wait().or_else(KVError::wrap_as_write_err)?;
+    return Ok(CommandOutput::Exited {
+        code: status.code().unwrap_or(1),
+    });
+}
@@ -0,0 +1,192 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use crate::cli_def::ReadCmdConfig;
+use crate::kv_commons::{KVError, KVRemoteSource, KVValue, UserPrompt};
+
+/// Change planned for a single key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlanAction {
+    Create,
+    Change,
+    Delete,
+    Unchanged,
+}
+
+impl PlanAction {
+    /// Single character marker used in plain text plans.
+    pub fn marker(&self) -> char {
+        return match self {
+            PlanAction::Create => '+',
+            PlanAction::Change => '~',
+            PlanAction::Delete => '-',
+            PlanAction::Unchanged => '=',
+        };
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanEntry {
+    pub path: String,
+    pub action: PlanAction,
+    /// Value to write, empty for deletes
+    #[serde(skip)]
+    pub value: Vec<u8>,
+    /// Remote version the plan was built against, `0` for created keys. Guards the write or delete
+    /// with check-and-set
+    #[serde(skip)]
+    pub remote_version: Option<u64>,
+}
+
+/// Planned key that could not be written or deleted.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanFailure {
+    pub path: String,
+    pub error: String,
+}
+
+/// Set of writes that brings remote to the desired state.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KVPlan {
+    pub entries: Vec<PlanEntry>,
+    /// Keys that failed during [`KVPlan::apply()`]
+    pub failed: Vec<PlanFailure>,
+}
+
+impl KVPlan {
+    /// Compare desired values with remote ones. Every key is read separately.
+    pub fn build(remote: &dyn KVRemoteSource, desired: Vec<KVValue>) -> Result<KVPlan, KVError> {
+        let mut entries = vec![];
+        for value in desired {
+            let read_res = remote.read_path(ReadCmdConfig {
+                is_encoded: false,
+                is_hex: false,
+                out_file: None,
+                version: None,
+                path: value.path.to_owned(),
+            });
+            let (action, remote_version) = match read_res {
+                // version `0` creates the key only if nobody else did it meanwhile
+                Err(KVError::NoValueErr(_)) => (PlanAction::Create, Some(0)),
+                Err(err) => return Err(err),
+                Ok(current) if current.value == value.value => (PlanAction::Unchanged, None),
+                Ok(current) => (PlanAction::Change, current.version),
+            };
+            entries.push(PlanEntry {
+                path: value.path,
+                action,
+                value: value.value,
+                remote_version,
+            });
+        }
+        return Ok(KVPlan {
+            entries,
+            failed: vec![],
+        });
+    }
+
+    /// Delete remote values that are not part of the plan. `remote` are every value under the planned prefix.
+    pub fn prune(&mut self, remote: Vec<KVValue>) {
+        let planned: HashSet<String> = self
+            .entries
+            .iter()
+            .map(|entry| entry.path.to_owned())
+            .collect();
+        let deletes = remote
+            .into_iter()
+            .filter(|value| !planned.contains(&value.path))
+            .map(|value| PlanEntry {
+                path: value.path,
+                action: PlanAction::Delete,
+                value: vec![],
+                remote_version: value.version,
+            });
+        self.entries.extend(deletes);
+    }
+
+    pub fn count(&self, action: PlanAction) -> usize {
+        return self
+            .entries
+            .iter()
+            .filter(|entry| entry.action == action)
+            .count();
+    }
+
+    pub fn has_changes(&self) -> bool {
+        return self.count(PlanAction::Unchanged) < self.entries.len();
+    }
+
+    /**
+    Human readable counts of planned changes. Failed keys are counted only when there are some.
+
+    Examples:
+
+    ```
+    use kivi_rs::kv_plan::KVPlan;
+
+    assert_eq!("0 to create, 0 to change, 0 to delete, 0 unchanged", KVPlan::default().summary());
+    ```
+    */
+    pub fn summary(&self) -> String {
+        let counts = format!(
+            "{} to create, {} to change, {} to delete, {} unchanged",
+            self.count(PlanAction::Create),
+            self.count(PlanAction::Change),
+            self.count(PlanAction::Delete),
+            self.count(PlanAction::Unchanged)
+        );
+        return match self.failed.len() {
+            0 => counts,
+            failed => format!("{counts}, {failed} failed"),
+        };
+    }
+
+    /// Plain text plan, one key per line prefixed with [`PlanAction::marker()`].
+    pub fn render(&self) -> String {
+        return self
+            .entries
+            .iter()
+            .map(|entry| format!("{} {}", entry.action.marker(), entry.path))
+            .collect::<Vec<String>>()
+            .join("\n");
+    }
+
+    /// Show plan to user and ask to proceed, unless `assume_yes` is set.
+    pub fn confirm(&self, assume_yes: bool, prompt: &dyn UserPrompt) -> Result<(), KVError> {
+        if assume_yes {
+            return Ok(());
+        }
+        let details = format!("{}\n{}", self.render(), self.summary());
+        return match prompt.confirm(&details, "Apply?") {
+            true => Ok(()),
+            false => Err(KVError::AbortedErr),
+        };
+    }
+
+    /// Write created and changed values and delete pruned ones. Every value is touched only
+    /// if remote was not modified after the plan was built.
+    /// A failed key does not stop the apply, it is recorded in [`KVPlan::failed`].
+    pub fn apply(&mut self, remote: &dyn KVRemoteSource) {
+        self.failed = self
+            .entries
+            .iter()
+            .filter_map(|entry| {
+                let result = match entry.action {
+                    PlanAction::Unchanged => Ok(()),
+                    PlanAction::Delete => remote.delete_value(&entry.path, entry.remote_version),
+                    _ => remote.write_value(
+                        &entry.path,
+                        entry.value.to_owned(),
+                        entry.remote_version,
+                    ),
+                };
+                return result.err().map(|err| PlanFailure {
+                    path: entry.path.to_owned(),
+                    error: err.to_string(),
+                });
+            })
+            .collect();
+    }
+}
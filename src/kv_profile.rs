@@ -0,0 +1,270 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use serde::Deserialize;
+
+use crate::cli_def::{Cli, Subs};
+use crate::kv_commons::KVError;
+use crate::kv_secret::KeyringEntry;
+use crate::kv_tls::TlsOptions;
+use crate::kv_uri::{BackendKind, KVUri, RemoteConfig};
+
+/// Environment variable with config file location, overrides the default one
+const CONFIG_ENV: &str = "KIVI_CONFIG";
+const CONFIG_FILE: &str = "kivi/config.toml";
+
+/// Named connection settings, selected with `--profile`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Backend the profile is meant for, other backend commands refuse it
+    pub backend: BackendKind,
+    pub url: Option<String>,
+    /// Token, or etcd credentials
+    pub token: Option<String>,
+    pub token_file: Option<PathBuf>,
+    pub token_command: Option<String>,
+    /// OS secret store entry with the token
+    pub keyring: Option<KeyringEntry>,
+    pub tls: Option<TlsOptions>,
+    /// Prefix relative key paths are resolved against, see [`crate::cli_def::KVSubs::scope()`]
+    #[serde(default)]
+    pub prefix: String,
+}
+
+/**
+Content of the config file.
+
+Examples:
+
+```
+use kivi_rs::kv_profile::KiviConfig;
+use kivi_rs::kv_uri::BackendKind;
+
+let config = KiviConfig::parse(r#"
+[profiles.prod]
+backend = "consul"
+url = "https://consul.prod:8501"
+prefix = "team-a"
+keyring = { service = "kivi", user = "prod" }
+
+[profiles.prod.tls]
+ca_cert = "/etc/kivi/prod-ca.pem"
+"#).unwrap();
+
+let prod = config.profile("prod").unwrap();
+assert_eq!(BackendKind::Consul, prod.backend);
+assert_eq!(None, prod.token);
+assert_eq!("prod", prod.keyring.as_ref().unwrap().user);
+assert!(!prod.tls.as_ref().unwrap().insecure_skip_verify);
+assert!(config.profile("stage").is_err());
+assert!(KiviConfig::parse("[profiles.dev]\nbackend = \"zookeeper\"").is_err());
+```
+*/
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KiviConfig {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl KiviConfig {
+    pub fn parse(content: &str) -> Result<Self, KVError> {
+        return toml::from_str(content)
+            .map_err(|err| KVError::ConfigErr(err.to_string().trim_end().to_owned()));
+    }
+
+    pub fn load(path: &Path) -> Result<Self, KVError> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| KVError::ConfigErr(format!("{}: {err}", path.display())))?;
+        return KiviConfig::parse(&content);
+    }
+
+    pub fn profile(&self, name: &str) -> Result<&Profile, KVError> {
+        return self
+            .profiles
+            .get(name)
+            .ok_or(KVError::ConfigErr(format!("unknown profile '{name}'")));
+    }
+}
+
+/// Config file location: `$KIVI_CONFIG`, otherwise `kivi/config.toml` under `$XDG_CONFIG_HOME`
+/// or `~/.config`.
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os(CONFIG_ENV) {
+        return Some(PathBuf::from(path));
+    }
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    return Some(config_dir.join(CONFIG_FILE));
+}
+
+/// Connection settings shared by backend configs.
+struct Settings<'a> {
+    url: &'a mut String,
+    token: &'a mut Option<String>,
+    token_file: &'a mut Option<PathBuf>,
+    token_command: &'a mut Option<String>,
+    keyring: &'a mut Option<KeyringEntry>,
+    tls: &'a mut TlsOptions,
+}
+
+/// Replace connection settings with profile values, unless they are given on the command line.
+/// Token sources replace each other as a whole, so any of them given on the command line
+/// leaves all profile ones out.
+fn apply_settings(profile: &Profile, matches: Option<&ArgMatches>, settings: Settings) {
+    let is_explicit =
+        |id: &str| matches.and_then(|m| m.value_source(id)) == Some(ValueSource::CommandLine);
+    if let (false, Some(profile_url)) = (is_explicit("url"), &profile.url) {
+        *settings.url = profile_url.to_owned();
+    }
+    let has_explicit_token = ["token", "token_file", "token_command"]
+        .into_iter()
+        .any(is_explicit);
+    let has_profile_token = profile.token.is_some()
+        || profile.token_file.is_some()
+        || profile.token_command.is_some()
+        || profile.keyring.is_some();
+    if !has_explicit_token && has_profile_token {
+        *settings.token = profile.token.to_owned();
+        *settings.token_file = profile.token_file.to_owned();
+        *settings.token_command = profile.token_command.to_owned();
+        *settings.keyring = profile.keyring.to_owned();
+    }
+    // TLS settings do not exclude each other and are taken one by one
+    if let Some(profile_tls) = &profile.tls {
+        let tls = settings.tls;
+        pick(
+            is_explicit("ca_cert"),
+            &mut tls.ca_cert,
+            &profile_tls.ca_cert,
+        );
+        pick(
+            is_explicit("client_cert"),
+            &mut tls.client_cert,
+            &profile_tls.client_cert,
+        );
+        pick(
+            is_explicit("client_key"),
+            &mut tls.client_key,
+            &profile_tls.client_key,
+        );
+        pick(
+            is_explicit("tls_server_name"),
+            &mut tls.tls_server_name,
+            &profile_tls.tls_server_name,
+        );
+        tls.insecure_skip_verify |= profile_tls.insecure_skip_verify;
+    }
+}
+
+/// Take profile value of a setting, unless the setting is given on the command line.
+fn pick<T: Clone>(is_explicit: bool, value: &mut Option<T>, profile_value: &Option<T>) {
+    if !is_explicit && profile_value.is_some() {
+        value.clone_from(profile_value);
+    }
+}
+
+/// Connection settings of a backend config, see [`Settings`].
+macro_rules! settings_of {
+    ($cfg:expr) => {
+        Settings {
+            url: &mut $cfg.url,
+            token: &mut $cfg.token,
+            token_file: &mut $cfg.token_file,
+            token_command: &mut $cfg.token_command,
+            keyring: &mut $cfg.keyring,
+            tls: &mut $cfg.tls,
+        }
+    };
+}
+
+/// Profile `name` from the config file. Profiles of other backends are refused.
+fn load_profile(name: &str, backend: BackendKind) -> Result<Profile, KVError> {
+    let path = config_path().ok_or(KVError::ConfigErr(format!(
+        "no config location, set {CONFIG_ENV}"
+    )))?;
+    let config = KiviConfig::load(&path)?;
+    let profile = config.profile(name)?;
+    if profile.backend != backend {
+        return Err(KVError::ConfigErr(format!(
+            "profile '{name}' is for {}, not {}",
+            profile.backend.name(),
+            backend.name()
+        )));
+    }
+    return Ok(profile.to_owned());
+}
+
+/**
+Apply profile selected with `--profile` or `KIVI_PROFILE` to backend command.
+
+Flags given on the command line win over profile values, profile values win over environment
+variables and defaults. Key paths of the command are scoped to profile prefix. `copy`, `diff`
+and `render` address remotes with URIs and are left as is. `render` takes credentials of `--profile`,
+sides of `copy` and `diff` take their own profiles, see [`uri_remote_config()`].
+*/
+pub fn apply_profile(cli: &mut Cli, matches: &ArgMatches) -> Result<(), KVError> {
+    let Some(name) = &cli.profile else {
+        return Ok(());
+    };
+    let (backend, sub_matches) = match &cli.command {
+        Some(Subs::Consul(_)) => (BackendKind::Consul, matches.subcommand_matches("consul")),
+        Some(Subs::Etcd(_)) => (BackendKind::Etcd, matches.subcommand_matches("etcd")),
+        Some(Subs::Vault(_)) => (BackendKind::Vault, matches.subcommand_matches("vault")),
+        _ => return Ok(()),
+    };
+    let profile = load_profile(name, backend)?;
+
+    match &mut cli.command {
+        Some(Subs::Consul(cfg)) => {
+            apply_settings(&profile, sub_matches, settings_of!(cfg));
+            if let Some(kv_command) = &mut cfg.kv_command {
+                kv_command.scope(&profile.prefix);
+            }
+        }
+        Some(Subs::Etcd(cfg)) => {
+            apply_settings(&profile, sub_matches, settings_of!(cfg));
+            if let Some(kv_command) = &mut cfg.kv_command {
+                kv_command.scope(&profile.prefix);
+            }
+        }
+        Some(Subs::Vault(cfg)) => {
+            apply_settings(&profile, sub_matches, settings_of!(cfg));
+            if let Some(vault_command) = &mut cfg.kv_command {
+                vault_command.scope(&profile.prefix);
+            }
+        }
+        _ => {}
+    }
+    return Ok(());
+}
+
+/**
+Config of the remote `uri` points to, with credentials and TLS settings of profile `profile_name`.
+
+Address and prefix always come from the URI. Profile values win over environment variables, so
+sides of `copy` and `diff` can reach clusters with different tokens or CAs.
+*/
+pub fn uri_remote_config(uri: &KVUri, profile_name: Option<&str>) -> Result<RemoteConfig, KVError> {
+    let mut remote_cfg = RemoteConfig::from_uri(uri)?;
+    let Some(name) = profile_name else {
+        return Ok(remote_cfg);
+    };
+    let profile = Profile {
+        url: None,
+        ..load_profile(name, uri.backend)?
+    };
+    match &mut remote_cfg {
+        RemoteConfig::Consul(cfg) => apply_settings(&profile, None, settings_of!(cfg)),
+        RemoteConfig::Etcd(cfg) => apply_settings(&profile, None, settings_of!(cfg)),
+        RemoteConfig::Vault(cfg) => apply_settings(&profile, None, settings_of!(cfg)),
+    }
+    return Ok(remote_cfg);
+}
@@ -0,0 +1,124 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use serde::Deserialize;
+
+/// Entry of the OS secret store: Secret Service on Linux, Keychain on macOS.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyringEntry {
+    pub service: String,
+    pub user: String,
+}
+
+/// Place a token is read from. Tokens are read with the first request that needs them,
+/// so they never show up in shell history or `ps` output.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenSource {
+    /// File with the token, surrounding whitespace is ignored
+    File(PathBuf),
+    /// Shell command printing the token, e.g. `pass show consul`
+    Command(String),
+    Keyring(KeyringEntry),
+}
+
+impl TokenSource {
+    /// First configured source, in the order file, command, keyring.
+    pub fn pick(
+        file: &Option<PathBuf>,
+        command: &Option<String>,
+        keyring: &Option<KeyringEntry>,
+    ) -> Option<TokenSource> {
+        return match (file, command, keyring) {
+            (Some(file), _, _) => Some(TokenSource::File(file.to_owned())),
+            (_, Some(command), _) => Some(TokenSource::Command(command.to_owned())),
+            (_, _, Some(entry)) => Some(TokenSource::Keyring(entry.clone())),
+            _ => None,
+        };
+    }
+
+    /**
+    Read the token. Error is the reason it could not be read.
+
+    Examples:
+
+    ```
+    use kivi_rs::kv_secret::TokenSource;
+
+    assert_eq!(Ok("s3cr3t".to_owned()), TokenSource::Command("echo ' s3cr3t '".to_owned()).resolve());
+    assert!(TokenSource::Command("exit 3".to_owned()).resolve().is_err());
+    assert!(TokenSource::File("/nonexistent/token".into()).resolve().is_err());
+    ```
+    */
+    pub fn resolve(&self) -> Result<String, String> {
+        let token = match self {
+            TokenSource::File(path) => {
+                fs::read_to_string(path).map_err(|err| format!("{self}: {err}"))?
+            }
+            TokenSource::Command(command) => {
+                let mut shell = Command::new("sh");
+                shell.arg("-c").arg(command);
+                command_output(shell, self)?
+            }
+            TokenSource::Keyring(entry) => command_output(keyring_lookup(entry), self)?,
+        };
+        let token = token.trim();
+        if token.is_empty() {
+            return Err(format!("{self} gave an empty token"));
+        }
+        return Ok(token.to_owned());
+    }
+}
+
+impl Display for TokenSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenSource::File(path) => write!(f, "token file {}", path.display()),
+            TokenSource::Command(command) => write!(f, "token command '{command}'"),
+            TokenSource::Keyring(entry) => {
+                write!(f, "keyring entry {}/{}", entry.service, entry.user)
+            }
+        }
+    }
+}
+
+/// Stdout of the command. Failed command's stderr is the error.
+fn command_output(mut command: Command, source: &TokenSource) -> Result<String, String> {
+    let output = command.output().map_err(|err| {
+        let program = command.get_program().to_string_lossy();
+        format!("{source}: {program} failed to start: {err}")
+    })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = match stderr.trim() {
+            "" => output.status.to_string(),
+            stderr => stderr.to_owned(),
+        };
+        return Err(format!("{source} failed: {reason}"));
+    }
+    return String::from_utf8(output.stdout).map_err(|_| format!("{source} gave non UTF-8 token"));
+}
+
+#[cfg(target_os = "macos")]
+fn keyring_lookup(entry: &KeyringEntry) -> Command {
+    let mut security = Command::new("security");
+    security.args([
+        "find-generic-password",
+        "-w",
+        "-s",
+        &entry.service,
+        "-a",
+        &entry.user,
+    ]);
+    return security;
+}
+
+/// Secret Service is queried with `secret-tool` from libsecret.
+#[cfg(not(target_os = "macos"))]
+fn keyring_lookup(entry: &KeyringEntry) -> Command {
+    let mut secret_tool = Command::new("secret-tool");
+    secret_tool.args(["lookup", "service", &entry.service, "username", &entry.user]);
+    return secret_tool;
+}
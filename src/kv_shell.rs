@@ -0,0 +1,419 @@
+use std::env;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use clap::{CommandFactory, Parser, Subcommand};
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{CompletionType, Config, Context, Editor, Helper};
+
+use crate::cli_def::{
+    DeleteCmdConfig, KVSubs, ListCmdConfig, ReadCmdConfig, ShellCmdConfig, TreeCmdConfig,
+    WriteCmdConfig,
+};
+use crate::dispatcher::dispatch;
+use crate::kv_commons::{KVError, KVRemoteSource, UserPrompt};
+use crate::output::{CommandOutput, Renderer};
+use crate::utils::{create_path_linter, join_path};
+
+const PATH_DELIMITER: char = '/';
+/// History file in the home directory, shared by sessions against any remote
+const HISTORY_FILE: &str = ".kivi_history";
+
+#[derive(Parser, Debug)]
+#[command(name = "", no_binary_name = true, disable_version_flag = true)]
+#[command(subcommand_required = true)]
+struct ShellLine {
+    #[command(subcommand)]
+    command: ShellSubs,
+}
+
+#[derive(Subcommand, Debug)]
+enum ShellSubs {
+    /// Change current prefix, `..` goes one level up, `/` to the root
+    Cd {
+        #[arg(default_value_t = PATH_DELIMITER.to_string())]
+        path: String,
+    },
+    /// Show current prefix
+    Pwd,
+    /// List keys under the prefix
+    Ls {
+        #[arg(default_value_t = String::new())]
+        path: String,
+    },
+    /// Show keys and values under the prefix as a tree
+    Tree {
+        #[arg(default_value_t = String::new())]
+        path: String,
+    },
+    /// Show value
+    Cat { path: String },
+    /// Edit value in $EDITOR
+    Edit { path: String },
+    /// Delete value, or with `-r` every value under the prefix
+    Rm {
+        #[arg(short = 'r', action)]
+        recursive: bool,
+        path: String,
+    },
+    /// Write value given inline, words are joined with a space
+    Put {
+        path: String,
+        #[arg(required = true, num_args = 1.., allow_hyphen_values = true, trailing_var_arg = true)]
+        value: Vec<String>,
+    },
+    /// Leave the shell
+    #[command(alias = "quit")]
+    Exit,
+}
+
+/**
+Resolve path typed in the shell against the current prefix. Paths starting with `/` are taken
+from the root, `.` and `..` parts are resolved. Trailing `/` is kept.
+
+Examples:
+
+```
+use kivi_rs::kv_shell::resolve_path;
+
+assert_eq!("svc/meta/top", resolve_path("svc/meta/", "top"));
+assert_eq!("svc/top/", resolve_path("svc/meta/", "../top/"));
+assert_eq!("top", resolve_path("svc/meta/", "/top"));
+assert_eq!("", resolve_path("svc/", ".."));
+```
+*/
+pub fn resolve_path(cwd: &str, path: &str) -> String {
+    let full_path = match path.starts_with(PATH_DELIMITER) {
+        true => path.to_owned(),
+        false => join_path(cwd, path),
+    };
+    let mut parts: Vec<&str> = vec![];
+    for part in full_path.split(PATH_DELIMITER) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    let resolved = parts.join(&PATH_DELIMITER.to_string());
+    return match path.ends_with(PATH_DELIMITER) && !resolved.is_empty() {
+        true => format!("{resolved}{PATH_DELIMITER}"),
+        false => resolved,
+    };
+}
+
+/**
+Prefix `cd` changes to, always empty or ending with `/`. See [`resolve_path`].
+
+Examples:
+
+```
+use kivi_rs::kv_shell::change_dir;
+
+assert_eq!("svc/meta/", change_dir("svc/", "meta"));
+assert_eq!("", change_dir("svc/", ".."));
+assert_eq!("other/", change_dir("svc/meta/", "/other"));
+```
+*/
+pub fn change_dir(cwd: &str, path: &str) -> String {
+    return join_path(&resolve_path(cwd, path), "");
+}
+
+/**
+Whether the line is kept in the shell history. `put` lines carry values, which may be secrets.
+
+Examples:
+
+```
+use kivi_rs::kv_shell::is_kept_in_history;
+
+assert!(is_kept_in_history("cat svc/top"));
+assert!(!is_kept_in_history("  put svc/token s3cret"));
+```
+*/
+pub fn is_kept_in_history(line: &str) -> bool {
+    return line.split_whitespace().next() != Some("put");
+}
+
+/**
+Completion candidates of the last word of `line`: command names for the first word,
+otherwise names of keys under the prefix the word points to, resolved against `cwd`.
+Returns where the completed word starts and the candidates.
+*/
+pub fn complete_line(remote: &dyn KVRemoteSource, cwd: &str, line: &str) -> (usize, Vec<Pair>) {
+    let word_start = line.rfind(char::is_whitespace).map_or(0, |idx| idx + 1);
+    let word = &line[word_start..];
+    let candidate = |name: &str, replacement: String| Pair {
+        display: name.to_owned(),
+        replacement,
+    };
+    if word_start == 0 {
+        let names = ShellLine::command()
+            .get_subcommands()
+            .map(|cmd| cmd.get_name().to_owned())
+            .filter(|name| name.starts_with(word))
+            .map(|name| candidate(&name, format!("{name} ")))
+            .collect();
+        return (word_start, names);
+    }
+
+    let (dir, partial) = match word.rfind(PATH_DELIMITER) {
+        Some(idx) => word.split_at(idx + 1),
+        None => ("", word),
+    };
+    let prefix = join_path(&resolve_path(cwd, dir), "");
+    // completion must not break the line being typed, failed lists offer nothing
+    let names = remote
+        .list(ListCmdConfig { prefix })
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|name| name.starts_with(partial))
+        .map(|name| candidate(&name, format!("{dir}{name}")))
+        .collect();
+    return (word_start, names);
+}
+
+/**
+Split shell line into words. Single and double quotes group words with spaces, no escapes
+are supported.
+
+Examples:
+
+```
+use kivi_rs::kv_shell::split_words;
+
+assert_eq!(vec!["put", "greeting", "hello world"], split_words("put greeting 'hello world'").unwrap());
+assert!(split_words("put greeting \"hello").is_err());
+```
+*/
+pub fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    for ch in line.chars() {
+        match (quote, ch) {
+            (Some(open), ch) if ch == open => quote = None,
+            (Some(_), ch) => word.get_or_insert_with(String::new).push(ch),
+            (None, '\'' | '"') => {
+                quote = Some(ch);
+                word.get_or_insert_with(String::new);
+            }
+            (None, ch) if ch.is_whitespace() => words.extend(word.take()),
+            (None, ch) => word.get_or_insert_with(String::new).push(ch),
+        }
+    }
+    if let Some(open) = quote {
+        return Err(format!("unclosed {open} quote"));
+    }
+    words.extend(word);
+    return Ok(words);
+}
+
+/// Completes command names and key names under the current prefix.
+struct ShellHelper<'a> {
+    remote: &'a dyn KVRemoteSource,
+    /// Current prefix, empty or ending with `/`
+    cwd: String,
+}
+
+impl Completer for ShellHelper<'_> {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        return Ok(complete_line(self.remote, &self.cwd, &line[..pos]));
+    }
+}
+
+impl Hinter for ShellHelper<'_> {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper<'_> {}
+
+impl Validator for ShellHelper<'_> {}
+
+impl Helper for ShellHelper<'_> {}
+
+/// Interactive session against a single remote. The remote, and so its authenticated
+/// client, is shared by all commands of the session.
+struct Shell<'a> {
+    remote: &'a dyn KVRemoteSource,
+    renderer: &'a Renderer<'a>,
+    prompt: &'a dyn UserPrompt,
+    /// Current prefix, empty or ending with `/`
+    cwd: String,
+}
+
+impl Shell<'_> {
+    fn prompt(&self) -> String {
+        return format!("{}:/{}> ", self.remote.backend_name(), self.cwd);
+    }
+
+    /// Run a single command. Returns `false` when the session should end.
+    fn execute(&mut self, command: ShellSubs) -> Result<bool, KVError> {
+        let resolve = |path: &str| resolve_path(&self.cwd, path);
+        let kv_command = match command {
+            ShellSubs::Exit => return Ok(false),
+            ShellSubs::Pwd => {
+                println!("/{}", self.cwd);
+                return Ok(true);
+            }
+            ShellSubs::Cd { path } => {
+                self.cwd = change_dir(&self.cwd, &path);
+                return Ok(true);
+            }
+            ShellSubs::Put { path, value } => {
+                let path = resolve(&path);
+                self.remote
+                    .write_value(&path, value.join(" ").into_bytes(), None)?;
+                return self.print(&CommandOutput::Done { path });
+            }
+            ShellSubs::Ls { path } => KVSubs::List(ListCmdConfig {
+                prefix: join_path(&resolve(&path), ""),
+            }),
+            ShellSubs::Tree { path } => KVSubs::Tree(TreeCmdConfig {
+                depth: None,
+                prefix: join_path(&resolve(&path), ""),
+            }),
+            ShellSubs::Cat { path } => KVSubs::Read(ReadCmdConfig {
+                is_encoded: false,
+                is_hex: false,
+                out_file: None,
+                version: None,
+                path: resolve(&path),
+            }),
+            ShellSubs::Edit { path } => KVSubs::Write(WriteCmdConfig {
+                is_inline_edit: true,
+                data_file: None,
+                path: resolve(&path),
+            }),
+            ShellSubs::Rm { recursive, path } => KVSubs::Delete(DeleteCmdConfig {
+                is_recursive: recursive,
+                assume_yes: false,
+                path: resolve(&path),
+            }),
+        };
+        return self.print(&dispatch(self.remote, &kv_command, self.prompt)?);
+    }
+
+    fn print(&self, output: &CommandOutput) -> Result<bool, KVError> {
+        let mut text = self.renderer.render(output);
+        // values are printed as stored, the prompt must still start on its own line
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        io::stdout()
+            .write_all(text.as_bytes())
+            .or_else(KVError::wrap_as_write_err)?;
+        return Ok(true);
+    }
+
+    /// Parse and run a line. Errors are reported and do not end the session.
+    fn execute_line(&mut self, line: &str) -> bool {
+        let words = match split_words(line) {
+            Ok(words) if words.is_empty() => return true,
+            Ok(words) => words,
+            Err(reason) => {
+                eprint!(
+                    "{}",
+                    self.renderer.render_error(&KVError::CommandErr(reason))
+                );
+                return true;
+            }
+        };
+        let command = match ShellLine::try_parse_from(words) {
+            Ok(shell_line) => shell_line.command,
+            Err(err) => {
+                let _ = err.print();
+                return true;
+            }
+        };
+        return match self.execute(command) {
+            Ok(keep_running) => keep_running,
+            Err(err) => {
+                eprint!("{}", self.renderer.render_error(&err));
+                true
+            }
+        };
+    }
+}
+
+fn history_file() -> Option<PathBuf> {
+    return env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+}
+
+/**
+Interactive prompt with a current prefix. Supports `cd`, `pwd`, `ls`, `tree`, `cat`, `edit`, `rm`
+and `put`, key names are completed with Tab. History is kept in `~/.kivi_history`, readable only by
+its owner. `put` lines are left out of it.
+Results are presented with the renderer, errors are printed and the session goes on.
+Questions of commands, e.g. whether to delete keys, are asked with `prompt`.
+*/
+pub fn run_shell(
+    remote: &dyn KVRemoteSource,
+    shell_cmd: &ShellCmdConfig,
+    renderer: &Renderer,
+    prompt: &dyn UserPrompt,
+) -> Result<CommandOutput, KVError> {
+    let wrap_err = |err: ReadlineError| KVError::ValueWriteErr(err.to_string());
+    let cwd = join_path(&create_path_linter()(shell_cmd.prefix.to_owned()), "");
+    // complete common part first and list candidates on the second Tab, like shells do
+    let config = Config::builder()
+        .completion_type(CompletionType::List)
+        .build();
+    let mut editor: Editor<ShellHelper, DefaultHistory> =
+        Editor::with_config(config).map_err(wrap_err)?;
+    editor.set_helper(Some(ShellHelper {
+        remote,
+        cwd: cwd.to_owned(),
+    }));
+    let history = history_file();
+    if let Some(history) = &history {
+        // missing history is fine for the first session
+        let _ = editor.load_history(history);
+    }
+
+    let mut shell = Shell {
+        remote,
+        renderer,
+        prompt,
+        cwd,
+    };
+    loop {
+        let line = match editor.readline(&shell.prompt()) {
+            Ok(line) => line,
+            // Ctrl-C drops the line being typed
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(wrap_err(err)),
+        };
+        if is_kept_in_history(&line) {
+            let _ = editor.add_history_entry(line.as_str());
+        }
+        if !shell.execute_line(&line) {
+            break;
+        }
+        if let Some(helper) = editor.helper_mut() {
+            helper.cwd = shell.cwd.to_owned();
+        }
+    }
+
+    if let Some(history) = &history {
+        let _ = editor.save_history(history);
+    }
+    return Ok(CommandOutput::Done {
+        path: shell.cwd.to_owned(),
+    });
+}
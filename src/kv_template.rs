@@ -0,0 +1,395 @@
+use serde_json::Value;
+
+use crate::cli_def::{ListCmdConfig, ReadCmdConfig};
+use crate::kv_commons::{KVError, KVRemoteSource};
+use crate::utils::join_path;
+
+const ACTION_START: &str = "{{";
+const ACTION_END: &str = "}}";
+/// Marker that trims whitespace next to an action, e.g. `{{- end -}}`
+const TRIM_MARKER: char = '-';
+const PATH_DELIMITER: char = '/';
+
+/// Value of `.Key` or `.Value`, or a quoted string.
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Literal(String),
+    Key,
+    Value,
+}
+
+/// Single step of a pipeline. Result of the previous step is appended to call arguments.
+#[derive(Debug, Clone, PartialEq)]
+enum Term {
+    Operand(Operand),
+    Call { name: String, args: Vec<Operand> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Action(Vec<Term>),
+    Range {
+        pipeline: Vec<Term>,
+        body: Vec<Node>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Literal(String),
+    Pipe,
+}
+
+/// Key/value pair produced by `ls` and `tree`. `key` is relative to the listed prefix.
+#[derive(Debug, Clone)]
+struct Entry {
+    key: String,
+    value: String,
+}
+
+enum Data {
+    Text(String),
+    Entries(Vec<Entry>),
+}
+
+/// Rendered template together with every key and prefix it read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedTemplate {
+    pub text: String,
+    pub inputs: Vec<String>,
+}
+
+/**
+Template in the spirit of consul-template. Text is copied as is, `{{ ... }}` actions are replaced
+with values read from the remote:
+
+* `{{ key "svc/meta/top" }}` - value of the key, fails when the key is missing
+* `{{ keyOrDefault "svc/meta/top" "none" }}` - value of the key or the default
+* `{{ range ls "svc/meta" }}{{ .Key }}={{ .Value }}{{ end }}` - immediate children of the prefix,
+  `tree` lists every value at any depth. `.Key` is relative to the prefix
+* `{{ key "svc/meta/webdav/config" | json "listen.port" }}` - field of a JSON value,
+  path is dot separated, array items are selected with `[0]` or `.0`
+
+Result of each pipeline step is passed as the last argument to the next one.
+`{{-` and `-}}` trim whitespace before and after the action.
+
+Examples:
+
+```
+use kivi_rs::kv_template::Template;
+
+assert!(Template::parse(r#"port: {{ key "svc/port" | json "http" }}"#).is_ok());
+assert!(Template::parse(r#"{{ range ls "svc" }}{{ .Key }}"#).is_err());
+assert!(Template::parse(r#"{{ key "svc/port }}"#).is_err());
+```
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+fn template_err(reason: impl Into<String>) -> KVError {
+    return KVError::TemplateErr(reason.into());
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Template, KVError> {
+        // ranges being parsed, each with the nodes of its parent
+        let mut open_ranges: Vec<(Vec<Term>, Vec<Node>)> = vec![];
+        let mut nodes = vec![];
+        let mut rest = source;
+        let mut trim_next = false;
+        loop {
+            let (text, action_start) = match rest.find(ACTION_START) {
+                Some(idx) => (&rest[..idx], Some(&rest[idx + ACTION_START.len()..])),
+                None => (rest, None),
+            };
+            let mut text = match trim_next {
+                true => text.trim_start(),
+                false => text,
+            };
+            let Some(action_start) = action_start else {
+                if !text.is_empty() {
+                    nodes.push(Node::Text(text.to_owned()));
+                }
+                break;
+            };
+            let end = action_start
+                .find(ACTION_END)
+                .ok_or_else(|| template_err(format!("'{ACTION_START}' is not closed")))?;
+            let mut action = &action_start[..end];
+            rest = &action_start[end + ACTION_END.len()..];
+            if let Some(trimmed) = action.strip_prefix(TRIM_MARKER) {
+                text = text.trim_end();
+                action = trimmed;
+            }
+            trim_next = match action.strip_suffix(TRIM_MARKER) {
+                Some(trimmed) => {
+                    action = trimmed;
+                    true
+                }
+                None => false,
+            };
+            if !text.is_empty() {
+                nodes.push(Node::Text(text.to_owned()));
+            }
+
+            let tokens = tokenize(action)?;
+            match tokens.first() {
+                Some(Token::Word(word)) if word == "range" => {
+                    let pipeline = parse_pipeline(&tokens[1..])?;
+                    open_ranges.push((pipeline, std::mem::take(&mut nodes)));
+                }
+                Some(Token::Word(word)) if word == "end" => {
+                    let (pipeline, parent) = open_ranges
+                        .pop()
+                        .ok_or_else(|| template_err("'end' without 'range'"))?;
+                    let body = std::mem::replace(&mut nodes, parent);
+                    nodes.push(Node::Range { pipeline, body });
+                }
+                _ => nodes.push(Node::Action(parse_pipeline(&tokens)?)),
+            }
+        }
+        if !open_ranges.is_empty() {
+            return Err(template_err("'range' without 'end'"));
+        }
+        return Ok(Template { nodes });
+    }
+
+    /// Render the template with values read from the remote.
+    pub fn render(&self, remote: &dyn KVRemoteSource) -> Result<RenderedTemplate, KVError> {
+        let mut renderer = TemplateRenderer {
+            remote,
+            inputs: vec![],
+        };
+        let text = renderer.render_nodes(&self.nodes, None)?;
+        return Ok(RenderedTemplate {
+            text,
+            inputs: renderer.inputs,
+        });
+    }
+}
+
+/// Split action into words, quoted strings and pipes.
+fn tokenize(action: &str) -> Result<Vec<Token>, KVError> {
+    let mut tokens = vec![];
+    let mut chars = action.char_indices().peekable();
+    while let Some((start, ch)) = chars.next() {
+        match ch {
+            _ if ch.is_whitespace() => {}
+            '|' => tokens.push(Token::Pipe),
+            '"' => {
+                let mut escaped = false;
+                let end = loop {
+                    match chars.next() {
+                        None => {
+                            return Err(template_err(format!("string is not closed: {action}")))
+                        }
+                        Some((idx, '"')) if !escaped => break idx,
+                        Some((_, '\\')) => escaped = !escaped,
+                        Some(_) => escaped = false,
+                    }
+                };
+                // quoted strings follow JSON escaping rules
+                let literal = serde_json::from_str(&action[start..=end])
+                    .map_err(|err| template_err(format!("invalid string: {err}")))?;
+                tokens.push(Token::Literal(literal));
+            }
+            _ => {
+                let mut end = start + ch.len_utf8();
+                while let Some((idx, next)) = chars.peek() {
+                    if next.is_whitespace() || *next == '|' || *next == '"' {
+                        break;
+                    }
+                    end = idx + next.len_utf8();
+                    chars.next();
+                }
+                tokens.push(Token::Word(action[start..end].to_owned()));
+            }
+        }
+    }
+    return Ok(tokens);
+}
+
+fn parse_operand(token: &Token) -> Result<Operand, KVError> {
+    return match token {
+        Token::Literal(literal) => Ok(Operand::Literal(literal.to_owned())),
+        Token::Word(word) if word == ".Key" => Ok(Operand::Key),
+        Token::Word(word) if word == ".Value" => Ok(Operand::Value),
+        Token::Word(word) => Err(template_err(format!("unexpected '{word}'"))),
+        Token::Pipe => Err(template_err("unexpected '|'")),
+    };
+}
+
+fn parse_pipeline(tokens: &[Token]) -> Result<Vec<Term>, KVError> {
+    return tokens
+        .split(|token| *token == Token::Pipe)
+        .map(|command| match command {
+            [] => Err(template_err("empty action")),
+            [Token::Word(name), args @ ..] if !name.starts_with('.') => Ok(Term::Call {
+                name: name.to_owned(),
+                args: args.iter().map(parse_operand).collect::<Result<_, _>>()?,
+            }),
+            [operand] => parse_operand(operand).map(Term::Operand),
+            [_, extra, ..] => Err(template_err(format!("unexpected {extra:?}"))),
+        })
+        .collect();
+}
+
+/**
+Select a field of JSON document. Path is dot separated, array items are selected by index,
+`$.` prefix and `[0]` brackets are accepted. Strings are returned as is, other values as JSON.
+
+Examples:
+
+```
+use kivi_rs::kv_template::json_select;
+let document = r#"{"listen": {"port": 8080, "hosts": ["a", "b"]}}"#;
+
+assert_eq!("8080", json_select("listen.port", document).unwrap());
+assert_eq!("b", json_select("$.listen.hosts[1]", document).unwrap());
+assert!(json_select("listen.missing", document).is_err());
+```
+*/
+pub fn json_select(path: &str, document: &str) -> Result<String, KVError> {
+    let document: Value = serde_json::from_str(document)
+        .map_err(|err| template_err(format!("value is not JSON: {err}")))?;
+    let normalized = path
+        .trim_start_matches('$')
+        .replace('[', ".")
+        .replace(']', "");
+    let mut selected = &document;
+    for field in normalized.split('.').filter(|field| !field.is_empty()) {
+        let next = match selected {
+            Value::Array(items) => field.parse::<usize>().ok().and_then(|idx| items.get(idx)),
+            other => other.get(field),
+        };
+        selected = next.ok_or_else(|| template_err(format!("'{path}' is not found")))?;
+    }
+    return Ok(match selected {
+        Value::String(text) => text.to_owned(),
+        other => other.to_string(),
+    });
+}
+
+struct TemplateRenderer<'a> {
+    remote: &'a dyn KVRemoteSource,
+    inputs: Vec<String>,
+}
+
+impl<'a> TemplateRenderer<'a> {
+    fn render_nodes(&mut self, nodes: &[Node], entry: Option<&Entry>) -> Result<String, KVError> {
+        let mut rendered = String::new();
+        for node in nodes {
+            match node {
+                Node::Text(text) => rendered.push_str(text),
+                Node::Action(pipeline) => match self.eval(pipeline, entry)? {
+                    Data::Text(text) => rendered.push_str(&text),
+                    Data::Entries(_) => {
+                        return Err(template_err("list can only be used with 'range'"))
+                    }
+                },
+                Node::Range { pipeline, body } => match self.eval(pipeline, entry)? {
+                    Data::Entries(entries) => {
+                        for item in &entries {
+                            rendered.push_str(&self.render_nodes(body, Some(item))?);
+                        }
+                    }
+                    Data::Text(_) => return Err(template_err("'range' needs 'ls' or 'tree'")),
+                },
+            }
+        }
+        return Ok(rendered);
+    }
+
+    fn eval(&mut self, pipeline: &[Term], entry: Option<&Entry>) -> Result<Data, KVError> {
+        let mut result: Option<Data> = None;
+        for term in pipeline {
+            result = Some(match term {
+                Term::Operand(operand) => Data::Text(resolve(operand, entry)?),
+                Term::Call { name, args } => {
+                    let mut values = args
+                        .iter()
+                        .map(|arg| resolve(arg, entry).map(Data::Text))
+                        .collect::<Result<Vec<Data>, KVError>>()?;
+                    values.extend(result.take());
+                    self.call(name, values)?
+                }
+            });
+        }
+        return result.ok_or_else(|| template_err("empty action"));
+    }
+
+    fn call(&mut self, name: &str, args: Vec<Data>) -> Result<Data, KVError> {
+        return match (name, args.as_slice()) {
+            ("key", [Data::Text(path)]) => self.read_key(path).map(Data::Text),
+            ("keyOrDefault", [Data::Text(path), Data::Text(default)]) => {
+                match self.read_key(path) {
+                    Err(KVError::NoValueErr(_)) => Ok(Data::Text(default.to_owned())),
+                    other => other.map(Data::Text),
+                }
+            }
+            ("ls", [Data::Text(prefix)]) => self.read_entries(prefix, false),
+            ("tree", [Data::Text(prefix)]) => self.read_entries(prefix, true),
+            ("json", [Data::Text(path), Data::Text(document)]) => {
+                json_select(path, document).map(Data::Text)
+            }
+            ("key" | "keyOrDefault" | "ls" | "tree" | "json", _) => {
+                Err(template_err(format!("wrong arguments of '{name}'")))
+            }
+            _ => Err(template_err(format!("unknown function '{name}'"))),
+        };
+    }
+
+    fn read_key(&mut self, path: &str) -> Result<String, KVError> {
+        self.inputs.push(path.to_owned());
+        let value = self.remote.read_path(ReadCmdConfig {
+            is_encoded: false,
+            is_hex: false,
+            out_file: None,
+            version: None,
+            path: path.to_owned(),
+        })?;
+        return Ok(String::from_utf8_lossy(&value.value).into_owned());
+    }
+
+    /// Values under the prefix. Folder keys are skipped, nested ones only when `recursive`.
+    fn read_entries(&mut self, prefix: &str, recursive: bool) -> Result<Data, KVError> {
+        let prefix = join_path(prefix, "");
+        self.inputs.push(prefix.to_owned());
+        let values = match self.remote.read_all(ListCmdConfig {
+            prefix: prefix.to_owned(),
+        }) {
+            Err(KVError::NoValueErr(_)) => vec![],
+            other => other?,
+        };
+        let entries = values
+            .into_iter()
+            .filter_map(|value| {
+                let key = value.path.strip_prefix(&prefix)?.to_owned();
+                let is_nested = key
+                    .trim_end_matches(PATH_DELIMITER)
+                    .contains(PATH_DELIMITER);
+                if key.is_empty() || key.ends_with(PATH_DELIMITER) || (is_nested && !recursive) {
+                    return None;
+                }
+                return Some(Entry {
+                    key,
+                    value: String::from_utf8_lossy(&value.value).into_owned(),
+                });
+            })
+            .collect();
+        return Ok(Data::Entries(entries));
+    }
+}
+
+fn resolve(operand: &Operand, entry: Option<&Entry>) -> Result<String, KVError> {
+    return match (operand, entry) {
+        (Operand::Literal(literal), _) => Ok(literal.to_owned()),
+        (Operand::Key, Some(entry)) => Ok(entry.key.to_owned()),
+        (Operand::Value, Some(entry)) => Ok(entry.value.to_owned()),
+        (_, None) => Err(template_err(".Key and .Value can only be used in 'range'")),
+    };
+}
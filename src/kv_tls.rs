@@ -0,0 +1,236 @@
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+
+use clap::Args;
+use serde::Deserialize;
+use ureq::rustls::client::danger::{
+    HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier,
+};
+use ureq::rustls::crypto::{self, CryptoProvider};
+use ureq::rustls::pki_types::pem::PemObject;
+use ureq::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use ureq::rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use ureq::{AgentBuilder, ReadWrite, TlsConnector};
+
+/// Environment variables backend tools use for TLS settings, e.g. `CONSUL_CACERT`.
+pub struct TlsEnv {
+    pub ca_cert: &'static str,
+    pub client_cert: &'static str,
+    pub client_key: &'static str,
+    pub server_name: Option<&'static str>,
+    /// Variable that disables verification when set to `1` or `true`
+    pub skip_verify: Option<&'static str>,
+}
+
+/// TLS client settings. System certificates are used when no CA certificate is given.
+#[derive(Args, Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsOptions {
+    #[arg(long = "ca-cert")]
+    /// PEM file with CA certificates to verify remote with, instead of system ones
+    pub ca_cert: Option<PathBuf>,
+
+    #[arg(long = "client-cert", requires = "client_key")]
+    /// PEM file with client certificate, for remotes that require mutual TLS
+    pub client_cert: Option<PathBuf>,
+
+    #[arg(long = "client-key", requires = "client_cert")]
+    /// PEM file with client certificate private key
+    pub client_key: Option<PathBuf>,
+
+    #[arg(long = "tls-server-name")]
+    /// name sent in SNI and expected in remote certificate, instead of the URL host
+    pub tls_server_name: Option<String>,
+
+    #[arg(long = "insecure-skip-verify", action)]
+    /// do not verify remote certificate. Use for testing only
+    #[serde(default)]
+    pub insecure_skip_verify: bool,
+}
+
+/// TLS client configuration could not be built. Passed through `ureq` as a transport error.
+#[derive(Debug)]
+pub struct TlsError(pub String);
+
+impl Display for TlsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for TlsError {}
+
+impl TlsOptions {
+    /**
+    Fill settings missing on the command line from backend environment variables.
+
+    Examples:
+
+    ```
+    use kivi_rs::kv_tls::{TlsEnv, TlsOptions};
+
+    let env = TlsEnv { ca_cert: "KIVI_DOC_CACERT", client_cert: "KIVI_DOC_CERT", client_key: "KIVI_DOC_KEY", server_name: None, skip_verify: Some("KIVI_DOC_SKIP_VERIFY") };
+    std::env::set_var("KIVI_DOC_CACERT", "/etc/ca.pem");
+    std::env::set_var("KIVI_DOC_SKIP_VERIFY", "true");
+
+    let options = TlsOptions::default().with_env(&env);
+    assert_eq!(Some("/etc/ca.pem".into()), options.ca_cert);
+    assert!(options.insecure_skip_verify);
+    ```
+    */
+    pub fn with_env(&self, tls_env: &TlsEnv) -> TlsOptions {
+        let from_env = |name: &str| env::var_os(name).filter(|value| !value.is_empty());
+        let is_set = |name: Option<&str>| {
+            name.and_then(|name| env::var(name).ok())
+                .is_some_and(|value| matches!(value.to_lowercase().as_str(), "1" | "true"))
+        };
+        let options = self.clone();
+        return TlsOptions {
+            ca_cert: options
+                .ca_cert
+                .or(from_env(tls_env.ca_cert).map(PathBuf::from)),
+            client_cert: options
+                .client_cert
+                .or(from_env(tls_env.client_cert).map(PathBuf::from)),
+            client_key: options
+                .client_key
+                .or(from_env(tls_env.client_key).map(PathBuf::from)),
+            tls_server_name: options.tls_server_name.or(tls_env
+                .server_name
+                .and_then(from_env)
+                .map(|name| name.to_string_lossy().into_owned())),
+            insecure_skip_verify: options.insecure_skip_verify || is_set(tls_env.skip_verify),
+        };
+    }
+
+    /// Use the settings for HTTPS requests of the agent. Agent defaults are kept when nothing is set.
+    pub fn configure(&self, agent_builder: AgentBuilder) -> AgentBuilder {
+        if *self == TlsOptions::default() {
+            return agent_builder;
+        }
+        return agent_builder.tls_connector(Arc::new(ConfiguredTls {
+            options: self.clone(),
+            config: OnceLock::new(),
+        }));
+    }
+
+    fn client_config(&self) -> Result<ClientConfig, String> {
+        let provider = Arc::new(crypto::ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|err| err.to_string())?;
+        let builder = match self.insecure_skip_verify {
+            true => builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(SkipVerification(provider))),
+            false => builder.with_root_certificates(self.root_store()?),
+        };
+        return match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => {
+                let key = PrivateKeyDer::from_pem_file(key)
+                    .map_err(|err| format!("client key {}: {err}", key.display()))?;
+                builder
+                    .with_client_auth_cert(read_certs(cert)?, key)
+                    .map_err(|err| format!("client certificate {}: {err}", cert.display()))
+            }
+            (None, None) => Ok(builder.with_no_client_auth()),
+            _ => Err("client certificate and key must be set together".to_owned()),
+        };
+    }
+
+    fn root_store(&self) -> Result<RootCertStore, String> {
+        let mut roots = RootCertStore::empty();
+        match &self.ca_cert {
+            Some(ca_cert) => {
+                for cert in read_certs(ca_cert)? {
+                    roots
+                        .add(cert)
+                        .map_err(|err| format!("CA certificate {}: {err}", ca_cert.display()))?;
+                }
+            }
+            None => {
+                let native_certs = rustls_native_certs::load_native_certs()
+                    .map_err(|err| format!("system certificates: {err}"))?;
+                roots.add_parsable_certificates(native_certs);
+            }
+        }
+        return Ok(roots);
+    }
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| format!("{}: {err}", path.display()))?;
+    if certs.is_empty() {
+        return Err(format!("{}: no certificates found", path.display()));
+    }
+    return Ok(certs);
+}
+
+/// Connects with client configuration built on first use, so unreadable certificates
+/// fail requests instead of remote construction.
+struct ConfiguredTls {
+    options: TlsOptions,
+    config: OnceLock<Result<Arc<ClientConfig>, String>>,
+}
+
+impl TlsConnector for ConfiguredTls {
+    fn connect(
+        &self,
+        dns_name: &str,
+        io: Box<dyn ReadWrite>,
+    ) -> Result<Box<dyn ReadWrite>, ureq::Error> {
+        let config = self
+            .config
+            .get_or_init(|| self.options.client_config().map(Arc::new))
+            .clone()
+            .map_err(|reason| io::Error::other(TlsError(reason)))?;
+        let server_name = self.options.tls_server_name.as_deref().unwrap_or(dns_name);
+        return config.connect(server_name, io);
+    }
+}
+
+/// Accepts any remote certificate. Handshake signatures are still checked.
+#[derive(Debug)]
+struct SkipVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for SkipVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, ureq::rustls::Error> {
+        return Ok(ServerCertVerified::assertion());
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, ureq::rustls::Error> {
+        let algorithms = &self.0.signature_verification_algorithms;
+        return crypto::verify_tls12_signature(message, cert, dss, algorithms);
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, ureq::rustls::Error> {
+        let algorithms = &self.0.signature_verification_algorithms;
+        return crypto::verify_tls13_signature(message, cert, dss, algorithms);
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        return self.0.signature_verification_algorithms.supported_schemes();
+    }
+}
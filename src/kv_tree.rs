@@ -0,0 +1,130 @@
+use std::collections::BTreeMap;
+
+use crate::kv_commons::KVValue;
+use crate::utils::{create_path_linter, create_str_linter};
+
+const PATH_DELIMITER: char = '/';
+
+/// Node of a key tree. Leaves carry value size, directories carry children.
+#[derive(Debug, Default)]
+struct TreeNode {
+    size: Option<usize>,
+    children: BTreeMap<String, TreeNode>,
+}
+
+impl TreeNode {
+    /// Insert path relative to the tree root. Every chunk but the last one is a directory.
+    fn insert(&mut self, relative_path: &str, size: usize) {
+        let mut node = self;
+        let mut chunks = relative_path.split(PATH_DELIMITER).peekable();
+        while let Some(chunk) = chunks.next() {
+            if chunk.is_empty() {
+                continue;
+            }
+            match chunks.peek() {
+                Some(_) => {
+                    node = node
+                        .children
+                        .entry(format!("{chunk}{PATH_DELIMITER}"))
+                        .or_default()
+                }
+                None => node.children.entry(chunk.to_owned()).or_default().size = Some(size),
+            }
+        }
+    }
+
+    fn count_leaves(&self) -> usize {
+        return self
+            .children
+            .values()
+            .map(|child| child.count_leaves())
+            .sum::<usize>()
+            + self.size.map_or(0, |_| 1);
+    }
+
+    fn render(&self, indent: &str, depth_left: Option<usize>, lines: &mut Vec<String>) {
+        let last_idx = self.children.len().saturating_sub(1);
+        for (idx, (name, child)) in self.children.iter().enumerate() {
+            let (branch, child_indent) = match idx == last_idx {
+                true => ("└── ", "    "),
+                false => ("├── ", "│   "),
+            };
+            let is_collapsed = depth_left == Some(0) && !child.children.is_empty();
+            let details = match (child.size, is_collapsed) {
+                (_, true) => format!(" ({} keys)", child.count_leaves()),
+                (Some(size), false) => format!(" ({})", human_size(size)),
+                (None, false) => "".to_owned(),
+            };
+            lines.push(format!("{indent}{branch}{name}{details}"));
+            if !is_collapsed {
+                let next_indent = [indent, child_indent].join("");
+                child.render(&next_indent, depth_left.map(|d| d.saturating_sub(1)), lines);
+            }
+        }
+    }
+}
+
+/**
+Format byte count in binary units.
+
+Examples:
+
+```
+use kivi_rs::kv_tree::human_size;
+
+assert_eq!("12 B", human_size(12));
+assert_eq!("1.5 KiB", human_size(1536));
+```
+*/
+pub fn human_size(size: usize) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if size < 1024 {
+        return format!("{size} B");
+    }
+    let mut scaled = size as f64 / 1024.0;
+    let mut unit = 0;
+    while scaled >= 1024.0 && unit < UNITS.len() - 1 {
+        scaled /= 1024.0;
+        unit += 1;
+    }
+    return format!("{scaled:.1} {}", UNITS[unit]);
+}
+
+/**
+Render values under the prefix as an indented tree. Leaves show value sizes.
+
+* `prefix` - tree root, removed from every value path
+* `values` - values to render, order does not matter
+* `depth` - optional number of levels to show. Deeper directories are collapsed and show their key count
+
+Examples:
+
+```
+use kivi_rs::kv_commons::KVValue;
+use kivi_rs::kv_tree::render_tree;
+let value = |path: &str, value: &str| KVValue { path: path.to_owned(), value: value.as_bytes().to_vec(), version: None, metadata: Default::default() };
+let values = vec![value("svc/a/config", "{}"), value("svc/b/config", "port=80"), value("svc/top", "x")];
+
+assert_eq!(
+    "svc/\n├── a/\n│   └── config (2 B)\n├── b/\n│   └── config (7 B)\n└── top (1 B)",
+    render_tree("svc/", &values, None)
+);
+assert_eq!("svc/\n├── a/ (1 keys)\n├── b/ (1 keys)\n└── top (1 B)", render_tree("svc/", &values, Some(1)));
+```
+*/
+pub fn render_tree(prefix: &str, values: &[KVValue], depth: Option<usize>) -> String {
+    let prefix = create_path_linter()(prefix.to_owned());
+    let prefix_linter = create_str_linter(Some(prefix.to_owned()), None, false);
+    let mut root = TreeNode::default();
+    for value in values {
+        root.insert(&prefix_linter(value.path.to_owned()), value.value.len());
+    }
+
+    let root_name = match prefix.is_empty() {
+        true => PATH_DELIMITER.to_string(),
+        false => prefix,
+    };
+    let mut lines = vec![root_name];
+    root.render("", depth.map(|d| d.saturating_sub(1)), &mut lines);
+    return lines.join("\n");
+}
@@ -0,0 +1,161 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use clap::Parser;
+use serde::Deserialize;
+use ureq::AgentBuilder;
+
+use crate::consul_remote::{ConsulCommandConfig, ConsulRemote};
+use crate::etcd_remote::{EtcdCommandConfig, EtcdRemote};
+use crate::kv_commons::{KVError, KVRemoteSource};
+use crate::vault_remote::{VaultCommandConfig, VaultRemote};
+
+const SCHEME_DELIMITER: &str = "://";
+const HTTPS_SUFFIX: &str = "+https";
+
+/// Backends that can be addressed with [`KVUri`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    Consul,
+    Etcd,
+    Vault,
+}
+
+impl BackendKind {
+    /// URI scheme of the backend, same as its subcommand name.
+    pub fn name(&self) -> &'static str {
+        return match self {
+            BackendKind::Consul => "consul",
+            BackendKind::Etcd => "etcd",
+            BackendKind::Vault => "vault",
+        };
+    }
+
+    fn default_port(&self) -> u16 {
+        return match self {
+            BackendKind::Consul => 8500,
+            BackendKind::Etcd => 2379,
+            BackendKind::Vault => 8200,
+        };
+    }
+}
+
+/**
+Backend address with a prefix, e.g. `consul://127.0.0.1:8500/svc/meta`.
+
+Scheme is a backend name, `+https` suffix switches to TLS (`etcd+https://...`).
+Port defaults to the backend's well known one. Credentials are taken from the
+same environment variables backend subcommands use, e.g. `CONSUL_HTTP_TOKEN`.
+
+Examples:
+
+```
+use kivi_rs::kv_uri::{BackendKind, KVUri};
+
+let uri: KVUri = "etcd+https://etcd.local/svc/meta".parse().unwrap();
+assert_eq!(BackendKind::Etcd, uri.backend);
+assert_eq!("https://etcd.local:2379", uri.url);
+assert_eq!("svc/meta", uri.prefix);
+
+let uri: KVUri = "consul://127.0.0.1:18500".parse().unwrap();
+assert_eq!("http://127.0.0.1:18500", uri.url);
+assert_eq!("", uri.prefix);
+
+assert!("zookeeper://127.0.0.1/".parse::<KVUri>().is_err());
+```
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct KVUri {
+    pub backend: BackendKind,
+    /// Backend HTTP address
+    pub url: String,
+    pub prefix: String,
+}
+
+impl FromStr for KVUri {
+    type Err = String;
+
+    fn from_str(uri: &str) -> Result<Self, Self::Err> {
+        let (scheme, rest) = uri.split_once(SCHEME_DELIMITER).ok_or(format!(
+            "'{uri}' is not a backend URI, e.g. consul://host/prefix"
+        ))?;
+        let (backend_name, http_scheme) = match scheme.strip_suffix(HTTPS_SUFFIX) {
+            Some(name) => (name, "https"),
+            None => (scheme, "http"),
+        };
+        let backend = match backend_name {
+            "consul" => BackendKind::Consul,
+            "etcd" => BackendKind::Etcd,
+            "vault" => BackendKind::Vault,
+            other => return Err(format!("unknown backend '{other}'")),
+        };
+        let (host, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        if host.is_empty() {
+            return Err(format!("'{uri}' has no host"));
+        }
+        let host = match host.contains(':') {
+            true => host.to_owned(),
+            false => format!("{host}:{}", backend.default_port()),
+        };
+
+        return Ok(KVUri {
+            backend,
+            url: format!("{http_scheme}://{host}"),
+            prefix: prefix.to_owned(),
+        });
+    }
+}
+
+impl Display for KVUri {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let backend = self.backend.name();
+        let (http_scheme, host) = self.url.split_once(SCHEME_DELIMITER).unwrap_or(("", ""));
+        let suffix = match http_scheme {
+            "https" => HTTPS_SUFFIX,
+            _ => "",
+        };
+        write!(
+            f,
+            "{backend}{suffix}{SCHEME_DELIMITER}{host}/{}",
+            self.prefix
+        )
+    }
+}
+
+/// Backend configuration built from [`KVUri`]. Owns the config remotes borrow.
+#[derive(Debug)]
+pub enum RemoteConfig {
+    Consul(ConsulCommandConfig),
+    Etcd(EtcdCommandConfig),
+    Vault(VaultCommandConfig),
+}
+
+/// Parse backend config from environment only. Url is the single argument supplied.
+fn config_from_env<T: Parser>(url: &str) -> Result<T, KVError> {
+    let command = T::command().subcommand_required(false);
+    let name = command.get_name().to_owned();
+    return command
+        .try_get_matches_from([name.as_str(), "--url", url])
+        .and_then(|matches| T::from_arg_matches(&matches))
+        .map_err(|err| KVError::ConfigErr(err.to_string().trim_end().to_owned()));
+}
+
+impl RemoteConfig {
+    /// Config of the remote the URI points to. Fails when backend environment variables are invalid.
+    pub fn from_uri(uri: &KVUri) -> Result<Self, KVError> {
+        return Ok(match uri.backend {
+            BackendKind::Consul => RemoteConfig::Consul(config_from_env(&uri.url)?),
+            BackendKind::Etcd => RemoteConfig::Etcd(config_from_env(&uri.url)?),
+            BackendKind::Vault => RemoteConfig::Vault(config_from_env(&uri.url)?),
+        });
+    }
+
+    pub fn build_remote(&self, agent_builder: AgentBuilder) -> Box<dyn KVRemoteSource + '_> {
+        return match self {
+            RemoteConfig::Consul(cfg) => Box::new(ConsulRemote::new(cfg, agent_builder)),
+            RemoteConfig::Etcd(cfg) => Box::new(EtcdRemote::new(cfg, agent_builder)),
+            RemoteConfig::Vault(cfg) => Box::new(VaultRemote::new(cfg, agent_builder)),
+        };
+    }
+}
@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+use std::process::Command;
+
+use serde::Serialize;
+
+use crate::kv_commons::{KVError, KVValue};
+use crate::kv_tree::human_size;
+use crate::utils::unified_diff;
+
+/// Kind of a single change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchEventKind {
+    /// Key was created or its value changed
+    Put,
+    Delete,
+}
+
+impl WatchEventKind {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            WatchEventKind::Put => "put",
+            WatchEventKind::Delete => "delete",
+        };
+    }
+}
+
+/// Change of a single key seen by a watch.
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchEvent {
+    pub path: String,
+    pub kind: WatchEventKind,
+    /// Remote index of the change: Consul `ModifyIndex` or etcd `mod_revision`
+    pub index: u64,
+    /// Value before the change, when remote knows it
+    pub old: Option<KVValue>,
+    /// Value after the change, none for deletes
+    pub new: Option<KVValue>,
+}
+
+impl WatchEvent {
+    /// Plain text event: header line with index, kind and path followed by diff of the values.
+    pub fn render(&self) -> String {
+        let header = format!("[{}] {} {}\n", self.index, self.kind.name(), self.path);
+        let size = |value: &Option<KVValue>| {
+            human_size(value.as_ref().map(|v| v.value.len()).unwrap_or_default())
+        };
+        return match (value_text(&self.old), value_text(&self.new)) {
+            (Some(old), Some(new)) => header + &unified_diff(old, new, "old", "new"),
+            _ => format!(
+                "{header}<binary_value:{} -> {}>\n",
+                size(&self.old),
+                size(&self.new)
+            ),
+        };
+    }
+}
+
+/// Missing value is an empty text, binary one has none.
+fn value_text(value: &Option<KVValue>) -> Option<&str> {
+    return match value {
+        None => Some(""),
+        Some(value) => value.as_text(),
+    };
+}
+
+/**
+Compare current values under the prefix with the known ones and replace known values with current.
+
+Used by remotes that can only tell that something under the prefix changed, e.g. Consul blocking
+queries. A key is changed when its version or value differs.
+
+Examples:
+
+```
+use std::collections::BTreeMap;
+use kivi_rs::kv_commons::KVValue;
+use kivi_rs::kv_watch::{snapshot_events, WatchEventKind};
+let value = |path: &str, version: u64| KVValue { path: path.to_owned(), value: b"x".to_vec(), version: Some(version), metadata: Default::default() };
+
+let mut known = BTreeMap::new();
+snapshot_events(&mut known, vec![value("a", 1), value("b", 2)], 2);
+
+let events = snapshot_events(&mut known, vec![value("a", 3)], 3);
+assert_eq!(2, events.len());
+assert_eq!((WatchEventKind::Put, 3), (events[0].kind, events[0].index));
+assert_eq!(("b", WatchEventKind::Delete), (events[1].path.as_str(), events[1].kind));
+```
+*/
+pub fn snapshot_events(
+    known: &mut BTreeMap<String, KVValue>,
+    current: Vec<KVValue>,
+    index: u64,
+) -> Vec<WatchEvent> {
+    let current: BTreeMap<String, KVValue> = current
+        .into_iter()
+        .map(|value| (value.path.to_owned(), value))
+        .collect();
+    let mut events = vec![];
+    for (path, value) in &current {
+        let old = known.get(path);
+        if old.is_some_and(|old| old.version == value.version && old.value == value.value) {
+            continue;
+        }
+        events.push(WatchEvent {
+            path: path.to_owned(),
+            kind: WatchEventKind::Put,
+            index: value.version.unwrap_or(index),
+            old: old.cloned(),
+            new: Some(value.clone()),
+        });
+    }
+    for (path, old) in known.iter() {
+        if current.contains_key(path) {
+            continue;
+        }
+        events.push(WatchEvent {
+            path: path.to_owned(),
+            kind: WatchEventKind::Delete,
+            index,
+            old: Some(old.clone()),
+            new: None,
+        });
+    }
+    *known = current;
+    return events;
+}
+
+/**
+Run shell command after a change. Event is passed in `KIVI_WATCH_PATH`, `KIVI_WATCH_KIND`
+and `KIVI_WATCH_INDEX` environment variables. Command that failed or could not start
+is reported as [`KVError::CommandErr`].
+
+Examples:
+
+```
+use kivi_rs::kv_watch::{run_on_change, WatchEvent, WatchEventKind};
+
+let event = WatchEvent {
+    path: "svc/top".to_owned(),
+    kind: WatchEventKind::Delete,
+    index: 12,
+    old: None,
+    new: None,
+};
+assert!(run_on_change("test \"$KIVI_WATCH_KIND\" = delete", &event).is_ok());
+assert_eq!(
+    "Error: command: 'exit 3' failed: exit status: 3",
+    run_on_change("exit 3", &event).unwrap_err().to_string()
+);
+```
+*/
+pub fn run_on_change(command: &str, event: &WatchEvent) -> Result<(), KVError> {
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .env("KIVI_WATCH_PATH", &event.path)
+        .env("KIVI_WATCH_KIND", event.kind.name())
+        .env("KIVI_WATCH_INDEX", event.index.to_string())
+        .status();
+    return match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(KVError::CommandErr(format!("'{command}' failed: {status}"))),
+        Err(err) => Err(KVError::CommandErr(format!(
+            "'{command}' failed to start: {err}"
+        ))),
+    };
+}
@@ -1,6 +1,23 @@
 pub mod cli_def;
 pub mod consul_remote;
+pub mod dispatcher;
 pub mod etcd_remote;
 pub mod http_ext;
+pub mod kv_browse;
 pub mod kv_commons;
+pub mod kv_diff;
+pub mod kv_dir;
+pub mod kv_dump;
+pub mod kv_exec;
+pub mod kv_plan;
+pub mod kv_profile;
+pub mod kv_secret;
+pub mod kv_shell;
+pub mod kv_template;
+pub mod kv_tls;
+pub mod kv_tree;
+pub mod kv_uri;
+pub mod kv_watch;
+pub mod output;
 pub mod utils;
+pub mod vault_remote;
@@ -1,18 +1,20 @@
-mod cli_def;
-mod consul_remote;
-mod etcd_remote;
-mod http_ext;
-mod kv_commons;
-mod utils;
-
-use base64::write::StrConsumer;
-use clap::Parser;
-use etcd_remote::EtcdRemote;
+use clap::{CommandFactory, FromArgMatches};
+use std::io::{self, stderr, stdin, IsTerminal, Write};
+use std::process;
 use std::time::Duration;
 use ureq::AgentBuilder;
 
-use crate::{cli_def::Cli, cli_def::Subs};
-use crate::{consul_remote::ConsulRemote, kv_commons::KVRemoteSource};
+use kivi_rs::cli_def::{Cli, CopyCmdConfig, KVSubs, RemoteRenderCmdConfig, Subs};
+use kivi_rs::consul_remote::ConsulRemote;
+use kivi_rs::dispatcher::{copy_prefix, diff_sides, dispatch, watch, watch_template};
+use kivi_rs::etcd_remote::EtcdRemote;
+use kivi_rs::kv_commons::{KVError, KVRemoteSource, KVValue, UserPrompt};
+use kivi_rs::kv_exec::exec_watched;
+use kivi_rs::kv_profile::{apply_profile, uri_remote_config};
+use kivi_rs::kv_shell::run_shell;
+use kivi_rs::output::{CommandOutput, OutputFormat, Renderer};
+use kivi_rs::utils::unified_diff;
+use kivi_rs::vault_remote::VaultRemote;
 
 const DEFAULT_KO_TIME: Duration = Duration::from_secs(5);
 
@@ -22,18 +24,201 @@ fn build_client() -> AgentBuilder {
         .timeout_read(DEFAULT_KO_TIME);
 }
 
+/// Client of streaming commands waits for remote as long as needed.
+fn build_stream_client() -> AgentBuilder {
+    return AgentBuilder::new().timeout_connect(DEFAULT_KO_TIME);
+}
+
+/// Ask user a yes/no question. Prompt goes to stderr, so stdout stays clean.
+///
+/// Anything but `y`/`yes` answer is a "no".
+fn ask_confirmation(question: &str) -> bool {
+    let mut err_out = stderr();
+    let _ = write!(err_out, "{question} [y/N]: ");
+    let _ = err_out.flush();
+
+    let mut answer = String::new();
+    return match stdin().read_line(&mut answer) {
+        Ok(_) => matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"),
+        Err(_) => false,
+    };
+}
+
+/// Answers command questions with prompts on the terminal.
+struct TerminalPrompt;
+
+impl UserPrompt for TerminalPrompt {
+    fn confirm(&self, details: &str, question: &str) -> bool {
+        eprintln!("{details}");
+        return ask_confirmation(question);
+    }
+
+    fn on_conflict(&self, edited: &str, read_remote: &dyn Fn() -> Result<KVValue, KVError>) {
+        if !ask_confirmation("Remote value changed while it was edited. Show diff?") {
+            return;
+        }
+        match read_remote() {
+            Ok(remote) => print!(
+                "{}",
+                unified_diff(
+                    &String::from_utf8_lossy(&remote.value),
+                    edited,
+                    "remote",
+                    "edited"
+                )
+            ),
+            Err(err) => eprintln!("{err}"),
+        }
+    }
+
+    fn on_error(&self, err: &KVError) {
+        eprintln!("{err}\nPress Enter to return");
+        let _ = stdin().read_line(&mut String::new());
+    }
+}
+
+fn dispatch_kv(
+    remote: &dyn KVRemoteSource,
+    command: &Option<KVSubs>,
+    format: OutputFormat,
+) -> Result<CommandOutput, KVError> {
+    return match command {
+        Some(KVSubs::Watch(watch_cmd)) => {
+            let renderer = Renderer::new(remote.backend_name(), format);
+            watch(
+                remote,
+                watch_cmd,
+                &mut |output| {
+                    io::stdout()
+                        .write_all(renderer.render(output).as_bytes())
+                        .or_else(KVError::wrap_as_write_err)
+                },
+                &mut |err| eprint!("{}", renderer.render_error(err)),
+            )
+        }
+        Some(KVSubs::Render(render_cmd)) if render_cmd.watch => {
+            let renderer = Renderer::new(remote.backend_name(), format);
+            watch_template(remote, render_cmd, &mut |output| {
+                io::stdout()
+                    .write_all(renderer.render(output).as_bytes())
+                    .or_else(KVError::wrap_as_write_err)
+            })
+        }
+        Some(KVSubs::Exec(exec_cmd)) if exec_cmd.watch => {
+            exec_watched(remote, exec_cmd, |code| process::exit(code))
+        }
+        Some(KVSubs::Shell(shell_cmd)) => {
+            let renderer = Renderer::new(remote.backend_name(), format);
+            run_shell(remote, shell_cmd, &renderer, &TerminalPrompt)
+        }
+        Some(kv_cmd) => dispatch(remote, kv_cmd, &TerminalPrompt),
+        None => Err(KVError::UnsupportedErr),
+    };
+}
+
+/// Render with the remote the URI points to, credentials and TLS settings are taken from `profile`.
+fn render_with_uri(
+    render_cfg: &RemoteRenderCmdConfig,
+    profile: Option<&str>,
+    client_builder: AgentBuilder,
+    format: OutputFormat,
+) -> Result<CommandOutput, KVError> {
+    let remote_cfg = uri_remote_config(&render_cfg.remote, profile)?;
+    let remote = remote_cfg.build_remote(client_builder);
+    let command = Some(KVSubs::Render(render_cfg.render.clone()));
+    return dispatch_kv(remote.as_ref(), &command, format);
+}
+
+/// Copy with a client per remote, so each side can be reached with its own settings.
+fn copy_between(
+    copy_cfg: &CopyCmdConfig,
+    client_builder: AgentBuilder,
+) -> Result<CommandOutput, KVError> {
+    let source_cfg = uri_remote_config(&copy_cfg.from, copy_cfg.from_profile.as_deref())?;
+    let target_cfg = uri_remote_config(&copy_cfg.to, copy_cfg.to_profile.as_deref())?;
+    let source = source_cfg.build_remote(client_builder);
+    let target = target_cfg.build_remote(build_client());
+    return copy_prefix(
+        source.as_ref(),
+        &copy_cfg.from,
+        target.as_ref(),
+        &copy_cfg.to,
+    );
+}
+
 fn main() {
-    let cli = Cli::parse();
-    let client_builder: AgentBuilder = build_client();
-    match &cli.command {
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    if let Err(err) = apply_profile(&mut cli, &matches) {
+        let renderer = Renderer::new(matches.subcommand_name().unwrap_or_default(), cli.output);
+        eprint!("{}", renderer.render_error(&err));
+        process::exit(err.exit_code());
+    }
+    let client_builder: AgentBuilder = match &cli.command {
+        Some(command) if command.is_streaming() => build_stream_client(),
+        _ => build_client(),
+    };
+    let (backend, command_res) = match &cli.command {
         Some(Subs::Consul(cfg)) => {
             let consul = ConsulRemote::new(cfg, client_builder);
-            consul.execute_kv_command();
+            (
+                consul.backend_name(),
+                dispatch_kv(&consul, &cfg.kv_command, cli.output),
+            )
         }
         Some(Subs::Etcd(cfg)) => {
             let etcd = EtcdRemote::new(cfg, client_builder);
-            etcd.execute_kv_command();
+            (
+                etcd.backend_name(),
+                dispatch_kv(&etcd, &cfg.kv_command, cli.output),
+            )
+        }
+        Some(Subs::Vault(cfg)) => {
+            let vault = VaultRemote::new(cfg, client_builder);
+            let vault_res = match &cfg.kv_command {
+                Some(vault_cmd) => vault.dispatch_vault_command(vault_cmd, &TerminalPrompt),
+                None => Err(KVError::UnsupportedErr),
+            };
+            (vault.backend_name(), vault_res)
+        }
+        Some(Subs::Copy(copy_cfg)) => (
+            copy_cfg.to.backend.name(),
+            copy_between(copy_cfg, client_builder),
+        ),
+        Some(Subs::Render(render_cfg)) => (
+            render_cfg.remote.backend.name(),
+            render_with_uri(
+                render_cfg,
+                cli.profile.as_deref(),
+                client_builder,
+                cli.output,
+            ),
+        ),
+        Some(Subs::Diff(diff_cfg)) => (
+            diff_cfg.left.backend_name(),
+            diff_sides(diff_cfg, build_client),
+        ),
+        None => {
+            println!("Nothing happened");
+            return;
+        }
+    };
+
+    let renderer = Renderer::new(backend, cli.output);
+    match command_res {
+        // piped values are passed through byte to byte
+        Ok(CommandOutput::Value(value))
+            if cli.output == OutputFormat::Raw && !io::stdout().is_terminal() =>
+        {
+            let _ = io::stdout().write_all(&value.value);
+        }
+        Ok(output) => {
+            print!("{}", renderer.render(&output));
+            process::exit(output.exit_code());
+        }
+        Err(err) => {
+            eprint!("{}", renderer.render_error(&err));
+            process::exit(err.exit_code());
         }
-        None => println!("Nothing happened"),
     }
 }
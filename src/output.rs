@@ -0,0 +1,454 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::dispatcher::CopyReport;
+use crate::kv_commons::{KVError, KVValue};
+use crate::kv_diff::{DiffEntry, KVDiff};
+use crate::kv_plan::{KVPlan, PlanEntry};
+use crate::kv_tree::{human_size, render_tree};
+use crate::kv_watch::WatchEvent;
+
+/// Format of command results and errors.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// plain values as stored in remote
+    #[default]
+    Raw,
+    Json,
+    Yaml,
+    /// aligned columns
+    Table,
+}
+
+/// Result of a single command, ready to be rendered by [`Renderer`].
+#[derive(Debug)]
+pub enum CommandOutput {
+    /// Single value read
+    Value(KVValue),
+    /// Immediate children of the prefix
+    Keys { prefix: String, keys: Vec<String> },
+    /// Every value under the prefix
+    Tree {
+        prefix: String,
+        values: Vec<KVValue>,
+        depth: Option<usize>,
+    },
+    /// Command changed remote state and has nothing to show
+    Done { path: String },
+    /// Remote specific result. `raw` is its [`OutputFormat::Raw`] representation
+    Document {
+        path: String,
+        data: Value,
+        raw: String,
+    },
+    /// Planned writes, `applied` when they were written to remote
+    Plan { plan: KVPlan, applied: bool },
+    /// Values copied between remotes
+    Copy(CopyReport),
+    /// Keys that differ between two sides
+    Diff(KVDiff),
+    /// Single change seen by a watch
+    Event(WatchEvent),
+    /// Command run by kivi exited, its output went straight to the terminal
+    Exited { code: i32 },
+}
+
+/// Exit code of a diff that found differences, see [`CommandOutput::exit_code()`].
+pub const DIFF_FOUND_EXIT_CODE: i32 = 9;
+
+impl CommandOutput {
+    /// Process exit code of a successful command. Partially failed commands exit with `1`,
+    /// diffs with differences exit with [`DIFF_FOUND_EXIT_CODE`], commands run by kivi with their own code.
+    pub fn exit_code(&self) -> i32 {
+        return match self {
+            CommandOutput::Exited { code } => *code,
+            CommandOutput::Copy(report) if !report.failed.is_empty() => 1,
+            CommandOutput::Plan { plan, .. } if !plan.failed.is_empty() => 1,
+            CommandOutput::Diff(diff) if diff.has_changes() => DIFF_FOUND_EXIT_CODE,
+            _ => 0,
+        };
+    }
+
+    /// Data that is not a JSON object is placed under `data` field.
+    pub fn document<T: Serialize>(path: &str, data: &T, raw: String) -> Self {
+        let data = match serde_json::to_value(data).unwrap_or_default() {
+            Value::Object(fields) => Value::Object(fields),
+            other => Value::Object(Map::from_iter([("data".to_owned(), other)])),
+        };
+        return CommandOutput::Document {
+            path: path.to_owned(),
+            data,
+            raw,
+        };
+    }
+}
+
+/// Result document of a read command.
+#[derive(Serialize)]
+struct ValueDocument<'a> {
+    backend: &'a str,
+    #[serde(flatten)]
+    value: &'a KVValue,
+}
+
+/// Result document of a list command.
+#[derive(Serialize)]
+struct KeysDocument<'a> {
+    backend: &'a str,
+    prefix: &'a str,
+    keys: &'a [String],
+}
+
+/// Result document of a tree command.
+#[derive(Serialize)]
+struct ValuesDocument<'a> {
+    backend: &'a str,
+    prefix: &'a str,
+    values: &'a [KVValue],
+}
+
+/// Result document of commands that only change remote state.
+#[derive(Serialize)]
+struct StatusDocument<'a> {
+    backend: &'a str,
+    path: &'a str,
+    status: &'a str,
+}
+
+/// Result document of commands that write a [`KVPlan`].
+#[derive(Serialize)]
+struct PlanDocument<'a> {
+    backend: &'a str,
+    applied: bool,
+    #[serde(flatten)]
+    plan: &'a KVPlan,
+}
+
+/// Result document of copy command.
+#[derive(Serialize)]
+struct CopyDocument<'a> {
+    backend: &'a str,
+    #[serde(flatten)]
+    report: &'a CopyReport,
+}
+
+/// Result document of diff command.
+#[derive(Serialize)]
+struct DiffDocument<'a> {
+    backend: &'a str,
+    #[serde(flatten)]
+    diff: &'a KVDiff,
+}
+
+/// Document of a single watch event.
+#[derive(Serialize)]
+struct EventDocument<'a> {
+    backend: &'a str,
+    #[serde(flatten)]
+    event: &'a WatchEvent,
+}
+
+/// Result document of any other command.
+#[derive(Serialize)]
+struct GenericDocument<'a> {
+    backend: &'a str,
+    path: &'a str,
+    #[serde(flatten)]
+    data: &'a Value,
+}
+
+#[derive(Serialize)]
+struct ErrorDocument<'a> {
+    backend: &'a str,
+    error: &'a KVError,
+    message: String,
+    exit_code: i32,
+}
+
+/// Renders command results of a single remote in the requested [`OutputFormat`].
+///
+/// Rendered text is complete, including trailing newline, and may be printed as is.
+pub struct Renderer<'a> {
+    backend: &'a str,
+    format: OutputFormat,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(backend: &'a str, format: OutputFormat) -> Self {
+        return Renderer { backend, format };
+    }
+
+    pub fn render(&self, output: &CommandOutput) -> String {
+        return match output {
+            CommandOutput::Value(value) => self.render_value(value),
+            CommandOutput::Keys { prefix, keys } => self.render_keys(prefix, keys),
+            CommandOutput::Tree {
+                prefix,
+                values,
+                depth,
+            } => self.render_tree(prefix, values, *depth),
+            CommandOutput::Done { path } => self.render_done(path),
+            CommandOutput::Document { path, data, raw } => self.render_document(path, data, raw),
+            CommandOutput::Plan { plan, applied } => self.render_plan(plan, *applied),
+            CommandOutput::Copy(report) => self.render_copy(report),
+            CommandOutput::Diff(diff) => self.render_diff(diff),
+            CommandOutput::Event(event) => self.render_event(event),
+            CommandOutput::Exited { .. } => "".to_owned(),
+        };
+    }
+
+    pub fn render_error(&self, err: &KVError) -> String {
+        let document = ErrorDocument {
+            backend: self.backend,
+            error: err,
+            message: err.to_string(),
+            exit_code: err.exit_code(),
+        };
+        return match self.format {
+            OutputFormat::Raw | OutputFormat::Table => format!("{err}\n"),
+            _ => self.format_document(&document),
+        };
+    }
+
+    fn render_value(&self, value: &KVValue) -> String {
+        let document = ValueDocument {
+            backend: self.backend,
+            value,
+        };
+        return match self.format {
+            OutputFormat::Raw => match value.as_text() {
+                Some(text) => text.to_owned(),
+                None => format!(
+                    "<binary_value:{}, use --encoded, --hex or --out>\n",
+                    human_size(value.value.len())
+                ),
+            },
+            OutputFormat::Table => fields_table(&document),
+            _ => self.format_document(&document),
+        };
+    }
+
+    fn render_keys(&self, prefix: &str, keys: &[String]) -> String {
+        let document = KeysDocument {
+            backend: self.backend,
+            prefix,
+            keys,
+        };
+        return match self.format {
+            OutputFormat::Raw => format!("{}\n", keys.join("\n")),
+            OutputFormat::Table => format!(
+                "{}\n",
+                render_table(&["KEY"], keys.iter().map(|key| vec![key.to_owned()]))
+            ),
+            _ => self.format_document(&document),
+        };
+    }
+
+    fn render_tree(&self, prefix: &str, values: &[KVValue], depth: Option<usize>) -> String {
+        let document = ValuesDocument {
+            backend: self.backend,
+            prefix,
+            values,
+        };
+        let to_row = |value: &KVValue| {
+            vec![
+                value.path.to_owned(),
+                human_size(value.value.len()),
+                value.version.map(|v| v.to_string()).unwrap_or_default(),
+            ]
+        };
+        return match self.format {
+            OutputFormat::Raw => format!("{}\n", render_tree(prefix, values, depth)),
+            OutputFormat::Table => format!(
+                "{}\n",
+                render_table(&["PATH", "SIZE", "VERSION"], values.iter().map(to_row))
+            ),
+            _ => self.format_document(&document),
+        };
+    }
+
+    /// Raw output of a successful command without result is empty.
+    fn render_done(&self, path: &str) -> String {
+        let document = StatusDocument {
+            backend: self.backend,
+            path,
+            status: "ok",
+        };
+        return match self.format {
+            OutputFormat::Raw => "".to_owned(),
+            OutputFormat::Table => fields_table(&document),
+            _ => self.format_document(&document),
+        };
+    }
+
+    fn render_plan(&self, plan: &KVPlan, applied: bool) -> String {
+        let document = PlanDocument {
+            backend: self.backend,
+            applied,
+            plan,
+        };
+        let state = match (applied, plan.has_changes()) {
+            (true, _) => "Applied",
+            (false, true) => "Dry run",
+            (false, false) => "No changes",
+        };
+        let failures = plan
+            .failed
+            .iter()
+            .map(|failure| format!("! {}: {}\n", failure.path, failure.error))
+            .collect::<String>();
+        let to_row = |entry: &PlanEntry| vec![format!("{:?}", entry.action), entry.path.to_owned()];
+        return match self.format {
+            OutputFormat::Raw if plan.entries.is_empty() => {
+                format!("{state}: {}\n", plan.summary())
+            }
+            OutputFormat::Raw => {
+                format!("{}\n{failures}{state}: {}\n", plan.render(), plan.summary())
+            }
+            OutputFormat::Table => format!(
+                "{}\n{failures}",
+                render_table(&["ACTION", "PATH"], plan.entries.iter().map(to_row))
+            ),
+            _ => self.format_document(&document),
+        };
+    }
+
+    fn render_copy(&self, report: &CopyReport) -> String {
+        let document = CopyDocument {
+            backend: self.backend,
+            report,
+        };
+        let failures = report
+            .failed
+            .iter()
+            .map(|failure| format!("! {}: {}\n", failure.path, failure.error))
+            .collect::<String>();
+        let copied_rows = report
+            .copied
+            .iter()
+            .map(|path| vec![path.to_owned(), "copied".to_owned()]);
+        let failed_rows = report
+            .failed
+            .iter()
+            .map(|failure| vec![failure.path.to_owned(), failure.error.to_owned()]);
+        return match self.format {
+            OutputFormat::Raw => format!("{failures}{}\n", report.summary()),
+            OutputFormat::Table => format!(
+                "{}\n",
+                render_table(&["PATH", "STATUS"], copied_rows.chain(failed_rows))
+            ),
+            _ => self.format_document(&document),
+        };
+    }
+
+    fn render_diff(&self, diff: &KVDiff) -> String {
+        let document = DiffDocument {
+            backend: self.backend,
+            diff,
+        };
+        let to_row = |entry: &DiffEntry| vec![format!("{:?}", entry.action), entry.path.to_owned()];
+        return match self.format {
+            OutputFormat::Raw => format!("{}{}\n", diff.render(), diff.summary()),
+            OutputFormat::Table => format!(
+                "{}\n",
+                render_table(&["ACTION", "PATH"], diff.entries.iter().map(to_row))
+            ),
+            _ => self.format_document(&document),
+        };
+    }
+
+    fn render_event(&self, event: &WatchEvent) -> String {
+        let document = EventDocument {
+            backend: self.backend,
+            event,
+        };
+        return match self.format {
+            OutputFormat::Raw => event.render(),
+            OutputFormat::Table => fields_table(&document),
+            _ => self.format_document(&document),
+        };
+    }
+
+    fn render_document(&self, path: &str, data: &Value, raw: &str) -> String {
+        let document = GenericDocument {
+            backend: self.backend,
+            path,
+            data,
+        };
+        return match self.format {
+            OutputFormat::Raw => format!("{raw}\n"),
+            OutputFormat::Table => fields_table(&document),
+            _ => self.format_document(&document),
+        };
+    }
+
+    fn format_document<T: Serialize>(&self, document: &T) -> String {
+        return match self.format {
+            OutputFormat::Yaml => serde_yaml::to_string(document).unwrap_or_default(),
+            _ => format!("{}\n", to_json(document)),
+        };
+    }
+}
+
+/// Pretty printed JSON of the document.
+pub fn to_json<T: Serialize>(document: &T) -> String {
+    return serde_json::to_string_pretty(document).unwrap_or_default();
+}
+
+/// Two column table of top level document fields, with trailing newline. Nested values are shown as compact JSON.
+fn fields_table<T: Serialize>(document: &T) -> String {
+    let rows = match serde_json::to_value(document) {
+        Ok(Value::Object(fields)) => fields
+            .into_iter()
+            .map(|(name, value)| vec![name, table_cell(&value)])
+            .collect(),
+        _ => vec![],
+    };
+    return format!("{}\n", render_table(&["FIELD", "VALUE"], rows));
+}
+
+fn table_cell(value: &Value) -> String {
+    let cell = match value {
+        Value::Null => "".to_owned(),
+        Value::String(st) => st.to_owned(),
+        other => other.to_string(),
+    };
+    return cell.replace('\n', "\\n");
+}
+
+/**
+Render rows as left aligned columns separated by two spaces.
+
+Examples:
+
+```
+use kivi_rs::output::render_table;
+
+let rows = vec![vec!["a".to_owned(), "1".to_owned()], vec!["long".to_owned(), "22".to_owned()]];
+assert_eq!("KEY   VALUE\na     1\nlong  22", render_table(&["KEY", "VALUE"], rows));
+```
+*/
+pub fn render_table(headers: &[&str], rows: impl IntoIterator<Item = Vec<String>>) -> String {
+    let header_row: Vec<String> = headers.iter().map(|h| h.to_string()).collect();
+    let rows: Vec<Vec<String>> = std::iter::once(header_row).chain(rows).collect();
+    let mut widths = vec![0; headers.len()];
+    for row in &rows {
+        for (idx, cell) in row.iter().enumerate().take(widths.len()) {
+            widths[idx] = widths[idx].max(cell.chars().count());
+        }
+    }
+
+    return rows
+        .iter()
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect();
+            return cells.join("  ").trim_end().to_owned();
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+}
@@ -1,14 +1,105 @@
 use base64::{engine::general_purpose, Engine as _};
+use similar::TextDiff;
 
 const PATH_DELIMITER: &str = "/";
 
-/// Safely decodes Base 64 encoded string
+/// Safely decodes Base 64 encoded string. Bytes that are not valid UTF-8 yield an empty string,
+/// use [`decode_b64_bytes()`] for binary data
 pub fn decodeb_64_safe(value: &str) -> String {
-    if value.is_empty() {
-        return value.to_string();
+    return String::from_utf8(decode_b64_bytes(value)).unwrap_or_default();
+}
+
+/// Safely decodes Base 64 encoded string into raw bytes. Invalid input yields no bytes
+pub fn decode_b64_bytes(value: &str) -> Vec<u8> {
+    return try_decode_b64(value).unwrap_or_default();
+}
+
+/// Decodes Base 64 encoded string into raw bytes. Invalid input yields [`None`]
+pub fn try_decode_b64(value: &str) -> Option<Vec<u8>> {
+    return general_purpose::STANDARD.decode(value).ok();
+}
+
+/// Encodes bytes as Base 64 string
+pub fn encode_b64(value: &[u8]) -> String {
+    return general_purpose::STANDARD.encode(value);
+}
+
+/**
+Encodes bytes as lowercase hex string.
+
+Examples:
+
+```
+use kivi_rs::utils::{decode_hex, encode_hex};
+
+assert_eq!("00ff10", encode_hex(&[0, 255, 16]));
+assert_eq!(Some(vec![0, 255, 16]), decode_hex("00FF10"));
+assert_eq!(None, decode_hex("0f1"));
+```
+*/
+pub fn encode_hex(value: &[u8]) -> String {
+    return value.iter().map(|byte| format!("{byte:02x}")).collect();
+}
+
+/// Decodes hex string of any case. Odd length or non hex digits yield [`None`]
+pub fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    let digit = |byte: &u8| char::from(*byte).to_digit(16);
+    return value
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [high, low] => Some((digit(high)? * 16 + digit(low)?) as u8),
+            _ => None,
+        })
+        .collect();
+}
+
+/**
+Compute the start of a key range that covers every key starting with `prefix`.
+
+Prefix is the start itself, except the empty one: etcd rejects an empty key,
+so the range starts from `[0]`, the lowest possible key.
+
+Examples:
+
+```
+use kivi_rs::utils::prefix_range_start;
+
+assert_eq!(b"svc/meta".to_vec(), prefix_range_start(b"svc/meta"));
+assert_eq!(vec![0], prefix_range_start(b""));
+```
+*/
+pub fn prefix_range_start(prefix: &[u8]) -> Vec<u8> {
+    if prefix.is_empty() {
+        return vec![0];
     }
-    let bytes: Vec<u8> = general_purpose::STANDARD.decode(value).unwrap_or(vec![]);
-    return String::from_utf8(bytes).unwrap_or_default();
+    return prefix.to_vec();
+}
+
+/**
+Compute the exclusive end of a key range that covers every key starting with `prefix`.
+
+Last byte of the prefix is incremented, trailing `0xff` bytes are dropped.
+Empty or all `0xff` prefix yields `[0]`, which means "till the end of keyspace" for etcd.
+
+Examples:
+
+```
+use kivi_rs::utils::prefix_range_end;
+
+assert_eq!(b"svc/metb".to_vec(), prefix_range_end(b"svc/meta"));
+assert_eq!(vec![0], prefix_range_end(b""));
+```
+*/
+pub fn prefix_range_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    return vec![0];
 }
 
 /// Simply return a copy of this string
@@ -61,6 +152,29 @@ pub fn create_path_linter() -> impl Fn(String) -> String {
     return create_str_linter(Some(PATH_DELIMITER.to_owned()), None, true);
 }
 
+/**
+Render line based unified diff of two texts. Empty string means texts are equal.
+
+Examples:
+
+```
+use kivi_rs::utils::unified_diff;
+let diff = unified_diff("a\nb\n", "a\nc\n", "old", "new");
+
+assert_eq!("--- old\n+++ new\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n", diff);
+assert_eq!("", unified_diff("same", "same", "old", "new"));
+```
+*/
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    if old == new {
+        return "".to_owned();
+    }
+    return TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(old_name, new_name)
+        .to_string();
+}
+
 /**
 Build a full URL using url parts. All url parts are [`str::trim()`]'ed before url is built.
 
@@ -97,7 +211,50 @@ pub fn build_url(url: &str, base_path: &str, suffix: &str) -> String {
 
     let linted_base_url = base_url_linter(url.to_owned());
     let linted_suffix = str_linter(suffix.to_owned());
-    let consul_url_parts = vec![&linted_base_url, base_path, &linted_suffix];
+    let url_parts = [&linted_base_url, base_path, &linted_suffix];
+
+    return url_parts.join("");
+}
+
+/**
+Join key path chunks with a single `/`. Empty prefix leaves path as is.
 
-    return consul_url_parts.join("");
+Examples:
+
+```
+use kivi_rs::utils::join_path;
+
+assert_eq!("svc/meta/top", join_path("svc/meta/", "/top"));
+assert_eq!("top", join_path("", "top"));
+```
+*/
+pub fn join_path(prefix: &str, path: &str) -> String {
+    if prefix.is_empty() {
+        return path.to_owned();
+    }
+    return format!(
+        "{}{PATH_DELIMITER}{}",
+        prefix.trim_end_matches(PATH_DELIMITER),
+        path.trim_start_matches(PATH_DELIMITER)
+    );
+}
+
+/**
+Resolve key path against the prefix. Paths starting with `/` are taken from the root as is.
+
+Examples:
+
+```
+use kivi_rs::utils::scope_path;
+
+assert_eq!("team-a/svc/meta", scope_path("team-a", "svc/meta"));
+assert_eq!("/svc/meta", scope_path("team-a", "/svc/meta"));
+assert_eq!("team-a/", scope_path("team-a", ""));
+```
+*/
+pub fn scope_path(prefix: &str, path: &str) -> String {
+    return match path.starts_with(PATH_DELIMITER) {
+        true => path.to_owned(),
+        false => join_path(prefix, path),
+    };
 }
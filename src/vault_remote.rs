@@ -0,0 +1,1163 @@
+use core::result::Result;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use ureq::{Agent, AgentBuilder, Error, Request, Response};
+
+use crate::dispatcher::dispatch;
+use crate::http_ext::{remap_transport_error, SharedToken, TokenAuthHeaderMiddleware};
+use crate::kv_secret::{KeyringEntry, TokenSource};
+use crate::kv_tls::{TlsEnv, TlsOptions};
+use crate::output::{to_json, CommandOutput};
+use crate::{
+    cli_def::{DeleteCmdConfig, KVSubs, ListCmdConfig, ReadCmdConfig, WriteCmdConfig},
+    kv_commons::{
+        confirm_delete, offer_conflict_diff, KVError, KVRemoteSource, KVValue, UserPrompt,
+    },
+    kv_watch::WatchEvent,
+    utils::*,
+};
+
+const TOKEN_HEADER: &str = "X-Vault-Token";
+const API_PATH: &str = "/v1/";
+const MOUNTS_ENDPOINT: &str = "sys/internal/ui/mounts/";
+const LIST_PARAMS: &str = "?list=true";
+const V2_DATA_PATH: &str = "data/";
+const V2_METADATA_PATH: &str = "metadata/";
+const V2_DELETE_PATH: &str = "delete/";
+const V2_UNDELETE_PATH: &str = "undelete/";
+const V2_DESTROY_PATH: &str = "destroy/";
+const VERSION_PARAM: &str = "?version=";
+const AUTH_PATH: &str = "auth/";
+const LOGIN_ENDPOINT: &str = "/login";
+const LOOKUP_SELF_ENDPOINT: &str = "auth/token/lookup-self";
+const RENEW_SELF_ENDPOINT: &str = "auth/token/renew-self";
+/// Token is renewed once less than `1/RENEW_FRACTION` of its lease is left
+const RENEW_FRACTION: u32 = 3;
+
+/// Vault tools' TLS environment variables, used when TLS flags are not given
+const TLS_ENV: TlsEnv = TlsEnv {
+    ca_cert: "VAULT_CACERT",
+    client_cert: "VAULT_CLIENT_CERT",
+    client_key: "VAULT_CLIENT_KEY",
+    server_name: Some("VAULT_TLS_SERVER_NAME"),
+    skip_verify: Some("VAULT_SKIP_VERIFY"),
+};
+
+#[derive(Parser, Debug, Default)]
+/// Subset of Vault specific commands
+pub struct VaultCommandConfig {
+    /// Vault token for authentication
+    #[arg(
+        short = 't',
+        long = "token",
+        env = "VAULT_TOKEN",
+        help = "Vault token to supply, leave blank to skip authentication"
+    )]
+    pub token: Option<String>,
+
+    /// Vault token file
+    #[arg(
+        long = "token-file",
+        help = "File with Vault token, read with the first request that needs it"
+    )]
+    pub token_file: Option<PathBuf>,
+
+    /// Vault token command
+    #[arg(
+        long = "token-command",
+        help = "Shell command printing Vault token, e.g. 'pass show vault'"
+    )]
+    pub token_command: Option<String>,
+
+    /// OS secret store entry with Vault token. Set by profiles only
+    #[arg(skip)]
+    pub keyring: Option<KeyringEntry>,
+
+    /// Vault url
+    #[arg(
+        short = 'u',
+        long = "url",
+        env = "VAULT_ADDR",
+        help = "Vault remote address",
+        default_value_t = String::from("http://127.0.0.1:8200")
+    )]
+    pub url: String,
+
+    #[command(flatten)]
+    pub tls: TlsOptions,
+
+    #[command(flatten)]
+    pub auth: VaultAuthConfig,
+
+    /// Vault command to execute
+    #[command(subcommand)]
+    pub kv_command: Option<VaultSubs>,
+}
+
+impl VaultCommandConfig {
+    /// Where token is read from when it is not given inline.
+    pub fn token_source(&self) -> Option<TokenSource> {
+        return TokenSource::pick(&self.token_file, &self.token_command, &self.keyring);
+    }
+}
+
+/// Supported ways to obtain Vault token.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum VaultAuthMethod {
+    /// use supplied token as is, it is never renewed
+    #[default]
+    Token,
+    /// log in with AppRole 'role_id'/'secret_id'
+    Approle,
+    /// log in with username and password
+    Userpass,
+}
+
+/// Vault login settings. Tokens acquired by login are renewed automatically, supplied tokens are not:
+/// they are used until Vault rejects them.
+#[derive(Args, Debug, Default)]
+pub struct VaultAuthConfig {
+    #[arg(
+        short = 'm',
+        long = "method",
+        env = "VAULT_AUTH_METHOD",
+        value_enum,
+        default_value_t
+    )]
+    /// Vault auth method
+    pub method: VaultAuthMethod,
+
+    #[arg(long = "auth-path", env = "VAULT_AUTH_PATH")]
+    /// Auth method mount path. Defaults to method name
+    pub auth_path: Option<String>,
+
+    #[arg(long = "role-id", env = "VAULT_ROLE_ID")]
+    /// AppRole role ID
+    pub role_id: Option<String>,
+
+    #[arg(long = "secret-id", env = "VAULT_SECRET_ID")]
+    /// AppRole secret ID
+    pub secret_id: Option<String>,
+
+    #[arg(long = "username", env = "VAULT_USERNAME")]
+    /// userpass username
+    pub username: Option<String>,
+
+    #[arg(long = "password", env = "VAULT_PASSWORD")]
+    /// userpass password
+    pub password: Option<String>,
+}
+
+/// Common KV commands extended with KV v2 versioning ones.
+#[derive(Subcommand, Debug)]
+#[command(subcommand_required = true)]
+pub enum VaultSubs {
+    #[command(flatten)]
+    Kv(KVSubs),
+    Versions(VersionsCmdConfig),
+    DeleteVersions(DeleteVersionsCmdConfig),
+    Undelete(UndeleteCmdConfig),
+    Destroy(DestroyCmdConfig),
+    Metadata(MetadataCmdConfig),
+    Token(TokenCmdConfig),
+}
+
+impl VaultSubs {
+    /// Resolve secret paths of the command against the prefix, see [`KVSubs::scope()`].
+    pub fn scope(&mut self, prefix: &str) {
+        if prefix.is_empty() {
+            return;
+        }
+        let scope = |path: &mut String| *path = scope_path(prefix, path);
+        match self {
+            VaultSubs::Kv(kv_cmd) => kv_cmd.scope(prefix),
+            VaultSubs::Versions(cmd) => scope(&mut cmd.path),
+            VaultSubs::DeleteVersions(cmd) => scope(&mut cmd.path),
+            VaultSubs::Undelete(cmd) => scope(&mut cmd.path),
+            VaultSubs::Destroy(cmd) => scope(&mut cmd.path),
+            VaultSubs::Metadata(cmd) => scope(&mut cmd.path),
+            VaultSubs::Token(_) => {}
+        }
+    }
+}
+
+#[derive(Parser, Clone, Debug)]
+/// Inspect or renew the token in use
+pub struct TokenCmdConfig {
+    #[command(subcommand)]
+    pub token_command: TokenSubs,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+pub enum TokenSubs {
+    /// Show token properties
+    LookupSelf,
+    /// Extend token lease
+    RenewSelf {
+        #[arg(long = "increment")]
+        /// requested lease extension, e.g. '1h'. Token TTL is used when omitted
+        increment: Option<String>,
+    },
+}
+
+#[derive(Parser, Clone, Debug)]
+/// Show version history of a secret (KV v2 only)
+pub struct VersionsCmdConfig {
+    #[arg()]
+    /// secret path
+    pub path: String,
+}
+
+#[derive(Parser, Clone, Debug)]
+/// Soft delete secret versions, they can be restored with 'undelete' (KV v2 only)
+pub struct DeleteVersionsCmdConfig {
+    #[arg(long = "versions", value_delimiter = ',', required = true)]
+    /// comma separated versions to delete
+    pub versions: Vec<u64>,
+
+    #[arg()]
+    /// secret path
+    pub path: String,
+}
+
+#[derive(Parser, Clone, Debug)]
+/// Restore soft deleted secret versions (KV v2 only)
+pub struct UndeleteCmdConfig {
+    #[arg(long = "versions", value_delimiter = ',', required = true)]
+    /// comma separated versions to restore
+    pub versions: Vec<u64>,
+
+    #[arg()]
+    /// secret path
+    pub path: String,
+}
+
+#[derive(Parser, Clone, Debug)]
+/// Permanently remove secret versions data (KV v2 only)
+pub struct DestroyCmdConfig {
+    #[arg(long = "versions", value_delimiter = ',', required = true)]
+    /// comma separated versions to destroy
+    pub versions: Vec<u64>,
+
+    #[arg()]
+    /// secret path
+    pub path: String,
+}
+
+#[derive(Parser, Clone, Debug)]
+/// Show or update secret metadata (KV v2 only). Metadata is shown when no update is requested
+pub struct MetadataCmdConfig {
+    #[arg(long = "max-versions")]
+    /// number of versions to keep
+    pub max_versions: Option<u32>,
+
+    #[arg(long = "cas-required")]
+    /// require check-and-set parameter on writes
+    pub cas_required: Option<bool>,
+
+    #[arg(long = "custom-metadata", value_parser = parse_key_value)]
+    /// 'key=value' pair of custom metadata. Repeat to set multiple pairs. Replaces existing custom metadata
+    pub custom_metadata: Vec<(String, String)>,
+
+    #[arg()]
+    /// secret path
+    pub path: String,
+}
+
+impl MetadataCmdConfig {
+    fn is_update(&self) -> bool {
+        return self.max_versions.is_some()
+            || self.cas_required.is_some()
+            || !self.custom_metadata.is_empty();
+    }
+}
+
+fn parse_key_value(arg: &str) -> Result<(String, String), String> {
+    return arg
+        .split_once('=')
+        .map(|(k, v)| (k.to_owned(), v.to_owned()))
+        .ok_or(format!("'{arg}' is not a 'key=value' pair"));
+}
+
+/// Represents Vault KV secrets engine source. Both KV v1 and v2 are supported.
+///
+/// Paths are full paths including the secrets engine mount, e.g. `secret/app/config`.
+pub struct VaultRemote<'a> {
+    pub config: &'a VaultCommandConfig,
+    pub agent: Agent,
+    auth_token: SharedToken,
+    lease: Mutex<Option<VaultLease>>,
+}
+
+impl<'a> VaultRemote<'a> {
+    /// Ctor for [`VaultRemote`]
+    ///
+    /// Supplied token is used right away and is never renewed, as its lease is unknown.
+    /// Login methods authenticate with the first request and renew their token once
+    /// two thirds of its lease have passed.
+    pub fn new(config: &'a VaultCommandConfig, agent_builder: AgentBuilder) -> Self {
+        let agent_builder = config.tls.with_env(&TLS_ENV).configure(agent_builder);
+        let auth_token = SharedToken::default();
+        let lease = match config.auth.method {
+            VaultAuthMethod::Token => {
+                *auth_token.write().unwrap() = config.token.to_owned();
+                Some(VaultLease::unlimited())
+            }
+            _ => None,
+        };
+        // tokens of login methods come from login responses only
+        let token_source = match config.auth.method {
+            VaultAuthMethod::Token => config.token_source(),
+            _ => None,
+        };
+        let authorizer =
+            TokenAuthHeaderMiddleware::shared(TOKEN_HEADER.to_owned(), auth_token.clone())
+                .with_source(token_source);
+        Self {
+            config,
+            agent: agent_builder.middleware(authorizer).build(),
+            auth_token,
+            lease: Mutex::new(lease),
+        }
+    }
+}
+
+/// Lifetime of the token in use.
+#[derive(Debug, Clone, Copy)]
+struct VaultLease {
+    renewable: bool,
+    duration: Duration,
+    obtained_at: Instant,
+}
+
+impl VaultLease {
+    /// Lease that never needs a renewal, e.g. supplied token or root token. Supplied tokens get it
+    /// without asking Vault, so they are used until Vault rejects them.
+    fn unlimited() -> Self {
+        Self {
+            renewable: false,
+            duration: Duration::ZERO,
+            obtained_at: Instant::now(),
+        }
+    }
+
+    fn is_expiring(&self) -> bool {
+        return !self.duration.is_zero()
+            && self.obtained_at.elapsed() > self.duration - self.duration / RENEW_FRACTION;
+    }
+}
+
+impl From<&VaultAuthInfo> for VaultLease {
+    fn from(auth: &VaultAuthInfo) -> Self {
+        Self {
+            renewable: auth.renewable,
+            duration: Duration::from_secs(auth.lease_duration),
+            obtained_at: Instant::now(),
+        }
+    }
+}
+
+/// `auth` block of login and renew responses.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VaultAuthInfo {
+    #[serde(skip_serializing)]
+    pub client_token: String,
+    #[serde(default)]
+    pub accessor: String,
+    #[serde(default)]
+    pub policies: Vec<String>,
+    #[serde(default)]
+    pub lease_duration: u64,
+    #[serde(default)]
+    pub renewable: bool,
+}
+
+/// Login and renew responses wrap the payload into `auth` field.
+#[derive(Debug, Deserialize)]
+struct VaultAuthResponse {
+    auth: VaultAuthInfo,
+}
+
+/// Body of AppRole login request.
+#[derive(Debug, Serialize)]
+struct VaultAppRoleLogin<'c> {
+    role_id: &'c str,
+    secret_id: &'c str,
+}
+
+/// Body of userpass login request.
+#[derive(Debug, Serialize)]
+struct VaultUserpassLogin<'c> {
+    password: &'c str,
+}
+
+/// Body of `renew-self` request.
+#[derive(Debug, Serialize)]
+struct VaultRenewRequest<'c> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    increment: Option<&'c str>,
+}
+
+/// Every Vault response wraps the payload into `data` field.
+#[derive(Debug, Deserialize)]
+struct VaultResponse<T> {
+    data: T,
+}
+
+/// Mount description returned by `sys/internal/ui/mounts/<path>`.
+#[derive(Debug, Deserialize)]
+struct VaultMountInfo {
+    path: String,
+    #[serde(default)]
+    options: Option<Map<String, Value>>,
+}
+
+/// Payload of KV v2 `data/` read. `data` is `null` for deleted versions.
+#[derive(Debug, Deserialize)]
+struct VaultV2Secret {
+    data: Option<Map<String, Value>>,
+    metadata: Option<VaultVersionInfo>,
+}
+
+/// KV v2 secret version details.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VaultVersionInfo {
+    #[serde(default)]
+    pub version: Option<u64>,
+    #[serde(default)]
+    pub created_time: String,
+    #[serde(default)]
+    pub deletion_time: String,
+    #[serde(default)]
+    pub destroyed: bool,
+}
+
+/// KV v2 secret metadata as returned by `metadata/` read.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VaultMetadata {
+    pub current_version: u64,
+    pub oldest_version: u64,
+    pub max_versions: u32,
+    pub cas_required: bool,
+    #[serde(default)]
+    pub delete_version_after: String,
+    #[serde(default)]
+    pub custom_metadata: Option<Map<String, Value>>,
+    #[serde(default, skip_serializing)]
+    pub versions: BTreeMap<u64, VaultVersionInfo>,
+}
+
+impl VaultMetadata {
+    /// Render version history as a table, oldest version first.
+    pub fn versions_table(&self) -> String {
+        let mut lines = vec![format!(
+            "{:<10}{:<34}{:<34}{}",
+            "VERSION", "CREATED", "DELETED", "DESTROYED"
+        )];
+        for (version, info) in &self.versions {
+            let deleted = match info.deletion_time.is_empty() {
+                true => "-",
+                false => info.deletion_time.as_str(),
+            };
+            lines.push(format!(
+                "{:<10}{:<34}{:<34}{}",
+                version, info.created_time, deleted, info.destroyed
+            ));
+        }
+        return lines.join("\n");
+    }
+}
+
+/// Body of KV v2 `metadata/` write. Only supplied fields are updated.
+#[derive(Debug, Serialize)]
+struct VaultMetadataWrite {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_versions: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cas_required: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    custom_metadata: Option<Map<String, Value>>,
+}
+
+/// Body of KV v2 `delete/`, `undelete/` and `destroy/` writes.
+#[derive(Debug, Serialize)]
+struct VaultVersionsWrite<'v> {
+    versions: &'v [u64],
+}
+
+/// Body of KV v2 `data/` write.
+#[derive(Debug, Serialize)]
+struct VaultV2Write {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<VaultV2WriteOptions>,
+    data: Map<String, Value>,
+}
+
+/// Check-and-set option of KV v2 write. Write succeeds only if `cas` matches current secret version.
+#[derive(Debug, Serialize)]
+struct VaultV2WriteOptions {
+    cas: u64,
+}
+
+/// Payload of list requests.
+#[derive(Debug, Deserialize)]
+struct VaultKeys {
+    keys: Vec<String>,
+}
+
+/// KV secrets engine mount the path belongs to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VaultMount {
+    /// Mount path with trailing `/`, e.g. `secret/`
+    pub path: String,
+    /// KV engine version, either 1 or 2
+    pub version: u8,
+}
+
+impl VaultMount {
+    /**
+    Rewrite full secret path into API path for the mount.
+
+    KV v1 paths are used as is. KV v2 paths get `api_prefix` (`data/` or `metadata/`) inserted right after the mount.
+
+    Examples:
+
+    ```
+    use kivi_rs::vault_remote::VaultMount;
+    let v1 = VaultMount { path: "kv/".to_owned(), version: 1 };
+    let v2 = VaultMount { path: "secret/".to_owned(), version: 2 };
+
+    assert_eq!("kv/app/config", v1.to_api_path("kv/app/config", "data/"));
+    assert_eq!("secret/data/app/config", v2.to_api_path("secret/app/config", "data/"));
+    assert_eq!("secret/metadata/app/", v2.to_api_path("/secret/app/", "metadata/"));
+    ```
+    */
+    pub fn to_api_path(&self, full_path: &str, api_prefix: &str) -> String {
+        let secret_path = create_path_linter()(full_path.to_owned());
+        if self.version < 2 {
+            return secret_path;
+        }
+        let relative = secret_path.strip_prefix(&self.path).unwrap_or_default();
+        return [self.path.as_str(), api_prefix, relative].join("");
+    }
+}
+
+impl From<VaultMountInfo> for VaultMount {
+    fn from(info: VaultMountInfo) -> Self {
+        let version = info
+            .options
+            .and_then(|opts| {
+                opts.get("version")
+                    .and_then(Value::as_str)
+                    .and_then(|v| v.parse().ok())
+            })
+            .unwrap_or(1);
+        Self {
+            path: info.path,
+            version,
+        }
+    }
+}
+
+/// Secrets are JSON objects. Display value is a pretty printed JSON, so it is easy to read and edit.
+///
+/// Metadata carries the mount details and, for KV v2, details of the read version.
+fn secret_to_kv_value(
+    path: String,
+    mount: &VaultMount,
+) -> impl Fn((Map<String, Value>, Option<VaultVersionInfo>)) -> KVValue {
+    let mount_metadata = Map::from_iter([
+        ("mount".to_owned(), Value::from(mount.path.to_owned())),
+        ("kv_version".to_owned(), Value::from(mount.version)),
+    ]);
+    return move |(secret, version_info): (Map<String, Value>, Option<VaultVersionInfo>)| {
+        let value = serde_json::to_vec_pretty(&secret).unwrap_or_default();
+        let version = version_info.as_ref().and_then(|info| info.version);
+        let mut metadata = mount_metadata.clone();
+        if let Some(Value::Object(info)) =
+            version_info.and_then(|info| serde_json::to_value(info).ok())
+        {
+            metadata.extend(info.into_iter().filter(|(name, _)| name != "version"));
+        }
+
+        return KVValue {
+            path: path.to_owned(),
+            value,
+            version,
+            metadata,
+        };
+    };
+}
+
+fn to_secret(content: &[u8]) -> Result<Map<String, Value>, KVError> {
+    return serde_json::from_slice::<Map<String, Value>>(content).or(Err(KVError::ValueFormatErr));
+}
+
+fn process_vault_response<T: for<'de> Deserialize<'de>>(response: Response) -> Result<T, KVError> {
+    return response
+        .into_json::<VaultResponse<T>>()
+        .map(|resp| resp.data)
+        .or(Err(KVError::ValueFormatErr));
+}
+
+impl<'a> VaultRemote<'a> {
+    /**
+    Create properly formed Vault HTTP API URL.
+
+    See [build_url()]
+
+    Examples:
+
+    ```
+    use kivi_rs::vault_remote::{VaultCommandConfig, VaultRemote};
+    use ureq::AgentBuilder;
+    let cmd_cfg = VaultCommandConfig {url: "http://127.0.0.1:8200".to_owned(), token: None, auth: Default::default(), kv_command: None, ..Default::default()};
+    let me = VaultRemote::new(&cmd_cfg, AgentBuilder::new());
+
+    assert_eq!("http://127.0.0.1:8200/v1/secret/data/app", me.to_vault_url("/secret/data/app"));
+    ````
+     */
+    pub fn to_vault_url(&self, suffix: &str) -> String {
+        return build_url(&self.config.url, API_PATH, suffix);
+    }
+
+    /// Create authenticated request. Logs in or renews the token first when needed.
+    fn vault_request(&self, method: &str, url: &str) -> Result<Request, KVError> {
+        let lease = *self.lease.lock().unwrap();
+        match lease {
+            None => self.login()?,
+            Some(lease) if lease.is_expiring() && lease.renewable => self
+                .renew_self(None)
+                .map(|_| ())
+                .or_else(|_| self.login())?,
+            Some(lease) if lease.is_expiring() => self.login()?,
+            Some(_) => {}
+        }
+        return Ok(self.agent.request(method, url));
+    }
+
+    /// Log in with configured auth method and cache the token for subsequent requests.
+    pub fn login(&self) -> Result<(), KVError> {
+        let auth_cfg = &self.config.auth;
+        let auth_path = match (&auth_cfg.auth_path, auth_cfg.method) {
+            (Some(path), _) => create_path_linter()(path.to_owned())
+                .trim_end_matches('/')
+                .to_owned(),
+            (None, method) => method
+                .to_possible_value()
+                .map(|v| v.get_name().to_owned())
+                .unwrap_or_default(),
+        };
+        // stale token must not be sent along with login request
+        *self.auth_token.write().unwrap() = None;
+        let res_response = match (auth_cfg.method, &auth_cfg.username, &auth_cfg.password) {
+            (VaultAuthMethod::Token, _, _) => {
+                *self.auth_token.write().unwrap() = self.config.token.to_owned();
+                *self.lease.lock().unwrap() = Some(VaultLease::unlimited());
+                return Ok(());
+            }
+            (VaultAuthMethod::Approle, _, _) => {
+                let login_url =
+                    self.to_vault_url(&[AUTH_PATH, &auth_path, LOGIN_ENDPOINT].join(""));
+                self.agent.post(&login_url).send_json(VaultAppRoleLogin {
+                    role_id: auth_cfg
+                        .role_id
+                        .as_deref()
+                        .ok_or(KVError::AuthenticationErr(
+                            "AppRole login requires a role id".to_owned(),
+                        ))?,
+                    secret_id: auth_cfg.secret_id.as_deref().unwrap_or_default(),
+                })
+            }
+            (VaultAuthMethod::Userpass, Some(username), Some(password)) => {
+                let login_url = self
+                    .to_vault_url(&[AUTH_PATH, &auth_path, LOGIN_ENDPOINT, "/", username].join(""));
+                self.agent
+                    .post(&login_url)
+                    .send_json(VaultUserpassLogin { password })
+            }
+            (VaultAuthMethod::Userpass, _, _) => {
+                return Err(KVError::AuthenticationErr(
+                    "userpass login requires a username and password".to_owned(),
+                ))
+            }
+        };
+
+        return match res_response {
+            Err(Error::Status(400, response)) => Err(KVError::AuthenticationErr(
+                vault_error_message(&response.into_string().unwrap_or_default()),
+            )),
+            Err(status) => remap_vault_errors(status),
+            Ok(response) => response
+                .into_json::<VaultAuthResponse>()
+                .map(|resp| self.store_auth(&resp.auth))
+                .or(Err(KVError::ValueFormatErr)),
+        };
+    }
+
+    fn store_auth(&self, auth: &VaultAuthInfo) {
+        *self.auth_token.write().unwrap() = Some(auth.client_token.to_owned());
+        *self.lease.lock().unwrap() = Some(VaultLease::from(auth));
+    }
+
+    /// Look up properties of the token in use.
+    pub fn lookup_self(&self) -> Result<Map<String, Value>, KVError> {
+        let res_response = self
+            .vault_request("GET", &self.to_vault_url(LOOKUP_SELF_ENDPOINT))?
+            .call();
+
+        return match res_response {
+            Err(status) => remap_vault_errors(status),
+            Ok(response) => process_vault_response::<Map<String, Value>>(response),
+        };
+    }
+
+    /// Extend lease of the token in use. Token TTL is used as increment when `increment` is omitted.
+    pub fn renew_self(&self, increment: Option<&str>) -> Result<VaultAuthInfo, KVError> {
+        if self.lease.lock().unwrap().is_none() {
+            self.login()?;
+        }
+        let res_response = self
+            .agent
+            .post(&self.to_vault_url(RENEW_SELF_ENDPOINT))
+            .send_json(VaultRenewRequest { increment });
+
+        return match res_response {
+            Err(status) => remap_vault_errors(status),
+            Ok(response) => response
+                .into_json::<VaultAuthResponse>()
+                .map(|resp| {
+                    self.store_auth(&resp.auth);
+                    resp.auth
+                })
+                .or(Err(KVError::ValueFormatErr)),
+        };
+    }
+
+    /// Detect KV secrets engine mount (and its version) that serves the path.
+    pub fn detect_mount(&self, path: &str) -> Result<VaultMount, KVError> {
+        let mounts_url =
+            self.to_vault_url(MOUNTS_ENDPOINT) + &create_path_linter()(path.to_owned());
+        let res_response = self.vault_request("GET", &mounts_url)?.call();
+
+        return match res_response {
+            Err(status) => remap_vault_errors(status),
+            Ok(response) => {
+                process_vault_response::<VaultMountInfo>(response).map(VaultMount::from)
+            }
+        };
+    }
+
+    /// Detect mount of the path and make sure it is a KV v2 one.
+    fn detect_v2_mount(&self, path: &str) -> Result<VaultMount, KVError> {
+        return self
+            .detect_mount(path)
+            .and_then(|mount| match mount.version {
+                1 => Err(KVError::UnsupportedErr),
+                _ => Ok(mount),
+            });
+    }
+
+    /// Read KV v2 secret metadata including version history.
+    pub fn read_metadata(&self, path: &str) -> Result<VaultMetadata, KVError> {
+        let mount = self.detect_v2_mount(path)?;
+        let vault_url = self.to_vault_url(&mount.to_api_path(path, V2_METADATA_PATH));
+        let res_response = self.vault_request("GET", &vault_url)?.call();
+
+        return match res_response {
+            Err(status) => remap_vault_errors(status),
+            Ok(response) => process_vault_response::<VaultMetadata>(response),
+        };
+    }
+
+    /// Update KV v2 secret metadata. Fields that are not set in config are left untouched.
+    pub fn write_metadata(&self, metadata_cfg: MetadataCmdConfig) -> Result<(), KVError> {
+        let mount = self.detect_v2_mount(&metadata_cfg.path)?;
+        let vault_url = self.to_vault_url(&mount.to_api_path(&metadata_cfg.path, V2_METADATA_PATH));
+        let custom_metadata = match metadata_cfg.custom_metadata.is_empty() {
+            true => None,
+            false => Some(
+                metadata_cfg
+                    .custom_metadata
+                    .into_iter()
+                    .map(|(k, v)| (k, Value::String(v)))
+                    .collect(),
+            ),
+        };
+        let res_response = self
+            .vault_request("POST", &vault_url)?
+            .send_json(VaultMetadataWrite {
+                max_versions: metadata_cfg.max_versions,
+                cas_required: metadata_cfg.cas_required,
+                custom_metadata,
+            });
+
+        return match res_response {
+            Err(status) => remap_vault_errors(status),
+            Ok(_) => Ok(()),
+        };
+    }
+
+    /// List full paths of all secrets under the prefix, at any depth.
+    pub fn list_all_keys(&self, prefix: &str) -> Result<Vec<String>, KVError> {
+        let prefix = create_str_linter(None, Some("/".to_owned()), false)(create_path_linter()(
+            prefix.to_owned(),
+        ));
+        let mut secrets = vec![];
+        for key in self.list(ListCmdConfig {
+            prefix: prefix.to_owned(),
+        })? {
+            let full_path = [prefix.as_str(), "/", &key].join("");
+            match key.ends_with('/') {
+                true => secrets.extend(self.list_all_keys(&full_path)?),
+                false => secrets.push(full_path),
+            }
+        }
+        return Ok(secrets);
+    }
+
+    /// Delete secret. KV v1 secrets are removed, KV v2 ones are soft deleted.
+    ///
+    /// Latest KV v2 version is deleted when `versions` is empty.
+    pub fn delete_versions(&self, path: &str, versions: &[u64]) -> Result<(), KVError> {
+        let mount = self.detect_mount(path)?;
+        let res_response = match (mount.version, versions.is_empty()) {
+            (1, false) => return Err(KVError::UnsupportedErr),
+            (_, true) => self
+                .vault_request(
+                    "DELETE",
+                    &self.to_vault_url(&mount.to_api_path(path, V2_DATA_PATH)),
+                )?
+                .call(),
+            (_, false) => self
+                .vault_request(
+                    "POST",
+                    &self.to_vault_url(&mount.to_api_path(path, V2_DELETE_PATH)),
+                )?
+                .send_json(VaultVersionsWrite { versions }),
+        };
+
+        return match res_response {
+            Err(status) => remap_vault_errors(status),
+            Ok(_) => Ok(()),
+        };
+    }
+
+    /// Restore soft deleted KV v2 secret versions.
+    pub fn undelete_versions(&self, path: &str, versions: &[u64]) -> Result<(), KVError> {
+        return self.write_versions(path, V2_UNDELETE_PATH, versions);
+    }
+
+    /// Permanently remove KV v2 secret versions data.
+    pub fn destroy_versions(&self, path: &str, versions: &[u64]) -> Result<(), KVError> {
+        return self.write_versions(path, V2_DESTROY_PATH, versions);
+    }
+
+    fn write_versions(
+        &self,
+        path: &str,
+        api_prefix: &str,
+        versions: &[u64],
+    ) -> Result<(), KVError> {
+        let mount = self.detect_v2_mount(path)?;
+        let vault_url = self.to_vault_url(&mount.to_api_path(path, api_prefix));
+        let res_response = self
+            .vault_request("POST", &vault_url)?
+            .send_json(VaultVersionsWrite { versions });
+
+        return match res_response {
+            Err(status) => remap_vault_errors(status),
+            Ok(_) => Ok(()),
+        };
+    }
+}
+
+impl<'a> VaultRemote<'a> {
+    /// Execute Vault command. KV commands are handed over to the shared [`dispatch`].
+    pub fn dispatch_vault_command(
+        &self,
+        command: &VaultSubs,
+        prompt: &dyn UserPrompt,
+    ) -> Result<CommandOutput, KVError> {
+        return match command {
+            VaultSubs::Kv(kv_cmd) => dispatch(self, kv_cmd, prompt),
+            VaultSubs::Versions(versions_cmd) => {
+                self.read_metadata(&versions_cmd.path).map(|metadata| {
+                    let versions = Map::from_iter([(
+                        "versions".to_owned(),
+                        serde_json::to_value(&metadata.versions).unwrap_or_default(),
+                    )]);
+                    CommandOutput::document(
+                        &versions_cmd.path,
+                        &versions,
+                        metadata.versions_table(),
+                    )
+                })
+            }
+            VaultSubs::DeleteVersions(delete_cmd) => self
+                .delete_versions(&delete_cmd.path, &delete_cmd.versions)
+                .map(|_| CommandOutput::Done {
+                    path: delete_cmd.path.to_owned(),
+                }),
+            VaultSubs::Undelete(undelete_cmd) => self
+                .undelete_versions(&undelete_cmd.path, &undelete_cmd.versions)
+                .map(|_| CommandOutput::Done {
+                    path: undelete_cmd.path.to_owned(),
+                }),
+            VaultSubs::Destroy(destroy_cmd) => self
+                .destroy_versions(&destroy_cmd.path, &destroy_cmd.versions)
+                .map(|_| CommandOutput::Done {
+                    path: destroy_cmd.path.to_owned(),
+                }),
+            VaultSubs::Metadata(metadata_cmd) if metadata_cmd.is_update() => self
+                .write_metadata(metadata_cmd.clone())
+                .map(|_| CommandOutput::Done {
+                    path: metadata_cmd.path.to_owned(),
+                }),
+            VaultSubs::Metadata(metadata_cmd) => {
+                self.read_metadata(&metadata_cmd.path).map(|metadata| {
+                    CommandOutput::document(&metadata_cmd.path, &metadata, to_json(&metadata))
+                })
+            }
+            VaultSubs::Token(token_cmd) => match &token_cmd.token_command {
+                TokenSubs::LookupSelf => self.lookup_self().map(|data| {
+                    CommandOutput::document(LOOKUP_SELF_ENDPOINT, &data, to_json(&data))
+                }),
+                TokenSubs::RenewSelf { increment } => {
+                    self.renew_self(increment.as_deref()).map(|auth| {
+                        CommandOutput::document(RENEW_SELF_ENDPOINT, &auth, to_json(&auth))
+                    })
+                }
+            },
+        };
+    }
+}
+
+impl<'a> KVRemoteSource for VaultRemote<'a> {
+    fn backend_name(&self) -> &'static str {
+        return "vault";
+    }
+
+    fn can_watch(&self) -> bool {
+        return false;
+    }
+
+    /// KV v2 secrets are soft deleted and can be restored with 'undelete'. Their metadata
+    /// is kept, so deleted secrets are still listed.
+    fn delete_path(
+        &self,
+        delete_cfg: DeleteCmdConfig,
+        prompt: &dyn UserPrompt,
+    ) -> Result<(), KVError> {
+        let path = create_path_linter()(delete_cfg.path);
+        let keys = match delete_cfg.is_recursive {
+            true => self.list_all_keys(&path)?,
+            false => vec![path],
+        };
+        confirm_delete(&keys, delete_cfg.assume_yes, prompt)?;
+
+        return keys
+            .iter()
+            .try_for_each(|key| self.delete_versions(key, &[]));
+    }
+
+    /// Vault has no change notifications for KV secrets.
+    fn watch(
+        &self,
+        _prefix: &str,
+        _on_event: &mut dyn FnMut(WatchEvent) -> Result<(), KVError>,
+    ) -> Result<(), KVError> {
+        return Err(KVError::UnsupportedErr);
+    }
+
+    /// Vault has no check-and-set for deletes. When `version` is set, current version
+    /// is compared right before the delete, which narrows but does not close the race.
+    fn delete_value(&self, path: &str, version: Option<u64>) -> Result<(), KVError> {
+        if let Some(expected) = version {
+            let current = self.read_path(ReadCmdConfig {
+                is_encoded: false,
+                is_hex: false,
+                out_file: None,
+                version: None,
+                path: path.to_owned(),
+            })?;
+            if current.version != Some(expected) {
+                return Err(KVError::ConflictErr);
+            }
+        }
+        return self.delete_versions(path, &[]);
+    }
+
+    fn list(&self, list_cfg: ListCmdConfig) -> Result<Vec<String>, KVError> {
+        let mount = self.detect_mount(&list_cfg.prefix)?;
+        let vault_url =
+            self.to_vault_url(&mount.to_api_path(&list_cfg.prefix, V2_METADATA_PATH)) + LIST_PARAMS;
+        let res_response = self.vault_request("GET", &vault_url)?.call();
+
+        return match res_response {
+            Err(status) => remap_vault_errors(status),
+            Ok(response) => process_vault_response::<VaultKeys>(response).map(|keys| keys.keys),
+        };
+    }
+
+    /// Vault has no bulk read, every secret is read with a separate request.
+    /// Soft deleted KV v2 secrets are still listed, they are skipped.
+    fn read_all(&self, list_cfg: ListCmdConfig) -> Result<Vec<KVValue>, KVError> {
+        let mut values = vec![];
+        for path in self.list_all_keys(&list_cfg.prefix)? {
+            let read_res = self.read_path(ReadCmdConfig {
+                is_encoded: false,
+                is_hex: false,
+                out_file: None,
+                version: None,
+                path,
+            });
+            match read_res {
+                Ok(value) => values.push(value),
+                Err(KVError::NoValueErr(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        return Ok(values);
+    }
+
+    fn read_path(&self, read_cfg: ReadCmdConfig) -> Result<KVValue, KVError> {
+        let mount = self.detect_mount(&read_cfg.path)?;
+        let version_params = match (mount.version, read_cfg.version) {
+            (_, None) => "".to_owned(),
+            (1, Some(_)) => return Err(KVError::UnsupportedErr),
+            (_, Some(version)) => format!("{VERSION_PARAM}{version}"),
+        };
+        let vault_url =
+            self.to_vault_url(&mount.to_api_path(&read_cfg.path, V2_DATA_PATH)) + &version_params;
+        let res_response = self.vault_request("GET", &vault_url)?.call();
+        let kv_value_mapper = secret_to_kv_value(read_cfg.path, &mount);
+
+        return match res_response {
+            Err(status) => remap_vault_errors(status),
+            Ok(response) => match mount.version {
+                1 => process_vault_response::<Map<String, Value>>(response)
+                    .map(|secret| (secret, None)),
+                _ => process_vault_response::<VaultV2Secret>(response).and_then(|secret| {
+                    secret
+                        .data
+                        .map(|data| (data, secret.metadata))
+                        .ok_or(KVError::NoValueErr("secret version is deleted".to_owned()))
+                }),
+            }
+            .map(kv_value_mapper),
+        };
+    }
+
+    /// Write secret. When `version` is set, KV v2 secret is written only if it is still the current version.
+    /// KV v1 has no check-and-set, write with `version` is refused with [`KVError::UnsupportedErr`].
+    fn write_value(
+        &self,
+        path: &str,
+        content: Vec<u8>,
+        version: Option<u64>,
+    ) -> Result<(), KVError> {
+        let secret = to_secret(&content)?;
+        let mount = self.detect_mount(path)?;
+        if mount.version == 1 && version.is_some() {
+            return Err(KVError::UnsupportedErr);
+        }
+        let vault_url = self.to_vault_url(&mount.to_api_path(path, V2_DATA_PATH));
+        let request = self.vault_request("POST", &vault_url)?;
+
+        let res_response = match mount.version {
+            1 => request.send_json(secret),
+            _ => request.send_json(VaultV2Write {
+                options: version.map(|cas| VaultV2WriteOptions { cas }),
+                data: secret,
+            }),
+        };
+
+        return match res_response {
+            Err(Error::Status(400, response)) if version.is_some() => {
+                let body = response.into_string().unwrap_or_default();
+                match body.contains("check-and-set") {
+                    true => Err(KVError::ConflictErr),
+                    false => Err(KVError::RemoteErr {
+                        status: 400,
+                        message: vault_error_message(&body),
+                    }),
+                }
+            }
+            Err(status) => remap_vault_errors(status),
+            Ok(_) => Ok(()),
+        };
+    }
+
+    fn write_path(
+        &self,
+        write_cfg: WriteCmdConfig,
+        prompt: &dyn UserPrompt,
+    ) -> Result<(), KVError> {
+        let write_new_value = |content: Vec<u8>, version: Option<u64>| {
+            self.write_value(&write_cfg.path, content, version)
+        };
+        let read_cfg = ReadCmdConfig {
+            is_encoded: false,
+            is_hex: false,
+            out_file: None,
+            version: None,
+            path: write_cfg.path.to_owned(),
+        };
+
+        return if write_cfg.is_inline_edit {
+            // edit is written only if remote value was not modified meanwhile
+            let kv_val = self.read_path(read_cfg.clone())?;
+            let edited = kv_val.inline_edit_value()?;
+            let write_res = write_new_value(edited.as_bytes().to_vec(), kv_val.version);
+            offer_conflict_diff(
+                write_res,
+                &edited,
+                &|| self.read_path(read_cfg.clone()),
+                prompt,
+            )
+        } else {
+            match write_cfg.data_file.to_owned() {
+                Some(file) => {
+                    return fs::read(file)
+                        .or_else(KVError::wrap_as_write_err)
+                        .and_then(|content| write_new_value(content, None));
+                }
+                None => Ok(()),
+            }
+        };
+    }
+}
+
+/// Error body of Vault API.
+#[derive(Debug, Deserialize)]
+struct VaultErrorResponse {
+    #[serde(default)]
+    errors: Vec<String>,
+}
+
+/// Join errors reported by Vault, falling back to raw body. Vault sends no errors for missing secrets.
+fn vault_error_message(body: &str) -> String {
+    return match serde_json::from_str::<VaultErrorResponse>(body) {
+        Ok(err) => err.errors.join("; "),
+        _ => body.trim().to_owned(),
+    };
+}
+
+/// Vault answers 403 for both missing and insufficient token
+fn remap_vault_errors<T>(status: Error) -> Result<T, KVError> {
+    match status {
+        Error::Transport(transport) => Err(remap_transport_error(transport)),
+        Error::Status(status, response) => Err(KVError::from_status(
+            status,
+            vault_error_message(&response.into_string().unwrap_or_default()),
+        )),
+    }
+}
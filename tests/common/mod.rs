@@ -0,0 +1,312 @@
+// every test crate uses only a part of the helpers
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use kivi_rs::cli_def::{DeleteCmdConfig, ListCmdConfig, ReadCmdConfig, WriteCmdConfig};
+use kivi_rs::etcd_remote::create_children_linter;
+use kivi_rs::kv_commons::{KVError, KVRemoteSource, KVValue, UserPrompt};
+use kivi_rs::kv_watch::WatchEvent;
+use serde_json::Map;
+
+/// In-memory remote with versioned values and check-and-set semantics of real backends.
+/// Missing prefixes are reported as [`KVError::NoValueErr`], like Consul does.
+#[derive(Default)]
+pub struct MemoryRemote {
+    /// Value and version of every key
+    pub values: RefCell<BTreeMap<String, (Vec<u8>, u64)>>,
+    /// Path and check-and-set version of every write and delete, in call order
+    pub calls: RefCell<Vec<(String, Option<u64>)>>,
+    /// Paths every write and delete fails on
+    pub failing: Vec<String>,
+    pub last_version: RefCell<u64>,
+}
+
+impl MemoryRemote {
+    pub fn with_values(values: &[(&str, &str)]) -> Self {
+        let remote = MemoryRemote::default();
+        for (path, value) in values {
+            remote.put(path, value);
+        }
+        return remote;
+    }
+
+    /// Write a value the way another client would, bumping its version.
+    pub fn put(&self, path: &str, value: &str) {
+        let version = self.next_version();
+        self.values
+            .borrow_mut()
+            .insert(path.to_owned(), (value.as_bytes().to_vec(), version));
+    }
+
+    pub fn value(&self, path: &str) -> Option<String> {
+        return self
+            .values
+            .borrow()
+            .get(path)
+            .map(|(value, _)| String::from_utf8_lossy(value).into_owned());
+    }
+
+    fn next_version(&self) -> u64 {
+        let mut last_version = self.last_version.borrow_mut();
+        *last_version += 1;
+        return *last_version;
+    }
+
+    /// Record the call and check `version` against the current one, `0` for missing keys.
+    fn check_and_set(&self, path: &str, version: Option<u64>) -> Result<(), KVError> {
+        self.calls.borrow_mut().push((path.to_owned(), version));
+        if self.failing.iter().any(|failing| failing == path) {
+            return Err(KVError::RemoteErr {
+                status: 500,
+                message: "rejected".to_owned(),
+            });
+        }
+        let current = self
+            .values
+            .borrow()
+            .get(path)
+            .map(|(_, version)| *version)
+            .unwrap_or(0);
+        return match version {
+            Some(expected) if expected != current => Err(KVError::ConflictErr),
+            _ => Ok(()),
+        };
+    }
+}
+
+impl KVRemoteSource for MemoryRemote {
+    fn backend_name(&self) -> &'static str {
+        return "memory";
+    }
+
+    /// Immediate children of the prefix, folders end with `/`, like real backends list them.
+    fn list(&self, list_cfg: ListCmdConfig) -> Result<Vec<String>, KVError> {
+        let linter = create_children_linter(list_cfg.prefix.to_owned());
+        return self
+            .read_all(list_cfg)
+            .map(|values| linter(values.into_iter().map(|value| value.path).collect()));
+    }
+
+    fn read_all(&self, list_cfg: ListCmdConfig) -> Result<Vec<KVValue>, KVError> {
+        let values: Vec<KVValue> = self
+            .values
+            .borrow()
+            .iter()
+            .filter(|(path, _)| path.starts_with(&list_cfg.prefix))
+            .map(|(path, (value, version))| KVValue {
+                path: path.to_owned(),
+                value: value.to_owned(),
+                version: Some(*version),
+                metadata: Map::new(),
+            })
+            .collect();
+        return match values.is_empty() {
+            true => Err(KVError::NoValueErr("".to_owned())),
+            false => Ok(values),
+        };
+    }
+
+    fn read_path(&self, read_cfg: ReadCmdConfig) -> Result<KVValue, KVError> {
+        return self
+            .values
+            .borrow()
+            .get(&read_cfg.path)
+            .map(|(value, version)| KVValue {
+                path: read_cfg.path.to_owned(),
+                value: value.to_owned(),
+                version: Some(*version),
+                metadata: Map::new(),
+            })
+            .ok_or(KVError::NoValueErr("".to_owned()));
+    }
+
+    fn write_path(
+        &self,
+        _write_cfg: WriteCmdConfig,
+        _prompt: &dyn UserPrompt,
+    ) -> Result<(), KVError> {
+        return Err(KVError::UnsupportedErr);
+    }
+
+    fn write_value(
+        &self,
+        path: &str,
+        content: Vec<u8>,
+        version: Option<u64>,
+    ) -> Result<(), KVError> {
+        self.check_and_set(path, version)?;
+        let version = self.next_version();
+        self.values
+            .borrow_mut()
+            .insert(path.to_owned(), (content, version));
+        return Ok(());
+    }
+
+    fn delete_path(
+        &self,
+        _delete_cfg: DeleteCmdConfig,
+        _prompt: &dyn UserPrompt,
+    ) -> Result<(), KVError> {
+        return Err(KVError::UnsupportedErr);
+    }
+
+    fn delete_value(&self, path: &str, version: Option<u64>) -> Result<(), KVError> {
+        self.check_and_set(path, version)?;
+        self.values.borrow_mut().remove(path);
+        return Ok(());
+    }
+
+    fn can_watch(&self) -> bool {
+        return false;
+    }
+
+    fn watch(
+        &self,
+        _prefix: &str,
+        _on_event: &mut dyn FnMut(WatchEvent) -> Result<(), KVError>,
+    ) -> Result<(), KVError> {
+        return Err(KVError::UnsupportedErr);
+    }
+}
+
+/// Answers "no" to every question and records edits that conflicted, along with the remote
+/// value read at that moment.
+#[derive(Default)]
+pub struct RecordingPrompt {
+    pub conflicts: RefCell<Vec<(String, String)>>,
+}
+
+impl UserPrompt for RecordingPrompt {
+    fn confirm(&self, _details: &str, _question: &str) -> bool {
+        return false;
+    }
+
+    fn on_conflict(&self, edited: &str, read_remote: &dyn Fn() -> Result<KVValue, KVError>) {
+        let remote = read_remote()
+            .map(|value| String::from_utf8_lossy(&value.value).into_owned())
+            .unwrap_or_else(|err| err.to_string());
+        self.conflicts
+            .borrow_mut()
+            .push((edited.to_owned(), remote));
+    }
+
+    fn on_error(&self, _err: &KVError) {}
+}
+
+/// Value with no version or metadata, as read from a dump or a directory.
+pub fn kv_value(path: &str, value: &str) -> KVValue {
+    return KVValue {
+        path: path.to_owned(),
+        value: value.as_bytes().to_vec(),
+        version: None,
+        metadata: Map::new(),
+    };
+}
+
+/// Request received by [`StubServer`].
+#[derive(Debug)]
+pub struct StubRequest {
+    pub method: String,
+    /// Path with query string
+    pub path: String,
+    /// Header names are lowercase
+    pub headers: BTreeMap<String, String>,
+    /// Body as text, see `raw_body` for exact bytes
+    pub body: String,
+    pub raw_body: Vec<u8>,
+}
+
+impl StubRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        return self.headers.get(name).map(String::as_str);
+    }
+}
+
+/// Local HTTP server that answers requests with prepared `(status, body)` responses in order,
+/// a connection per request. Stops after the last response or when nobody connects for a while.
+pub struct StubServer {
+    pub url: String,
+    handle: JoinHandle<Vec<StubRequest>>,
+}
+
+/// Time [`StubServer`] waits for the next request
+const STUB_ACCEPT_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl StubServer {
+    pub fn start(responses: &[(u16, &str)]) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let responses: Vec<(u16, String)> = responses
+            .iter()
+            .map(|(status, body)| (*status, body.to_string()))
+            .collect();
+        let handle = thread::spawn(move || {
+            let mut requests = vec![];
+            for (status, body) in responses {
+                let deadline = Instant::now() + STUB_ACCEPT_TIMEOUT;
+                let mut stream = loop {
+                    match listener.accept() {
+                        Ok((stream, _)) => break stream,
+                        Err(_) if Instant::now() < deadline => {
+                            thread::sleep(Duration::from_millis(10))
+                        }
+                        Err(_) => return requests,
+                    }
+                };
+                stream.set_nonblocking(false).unwrap();
+                requests.push(read_stub_request(&mut BufReader::new(&stream)));
+                let response = format!(
+                    "HTTP/1.1 {status} Stub\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            return requests;
+        });
+        return StubServer { url, handle };
+    }
+
+    /// Requests received, once every response was sent.
+    pub fn requests(self) -> Vec<StubRequest> {
+        return self.handle.join().unwrap();
+    }
+}
+
+fn read_stub_request(reader: &mut impl BufRead) -> StubRequest {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_owned();
+    let path = parts.next().unwrap_or_default().to_owned();
+    let mut headers = BTreeMap::new();
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        match header.trim_end().split_once(':') {
+            Some((name, value)) => {
+                headers.insert(name.to_lowercase(), value.trim().to_owned());
+            }
+            None => break,
+        }
+    }
+    let length = headers
+        .get("content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+    return StubRequest {
+        method,
+        path,
+        headers,
+        body: String::from_utf8_lossy(&body).into_owned(),
+        raw_body: body,
+    };
+}
@@ -0,0 +1,111 @@
+mod common;
+
+#[cfg(test)]
+#[path = "../src/consul_remote.rs"]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use kivi_rs::cli_def::{ReadCmdConfig, WriteCmdConfig};
+    use kivi_rs::consul_remote::{ConsulCommandConfig, ConsulRemote};
+    use kivi_rs::kv_commons::{KVError, KVRemoteSource};
+    use kivi_rs::utils::encode_b64;
+    use ureq::AgentBuilder;
+
+    use crate::common::{RecordingPrompt, StubServer};
+
+    /// Not valid UTF-8, like DER certificates or gzip'd configs
+    const BINARY_VALUE: &[u8] = &[0x1f, 0x8b, 0x08, 0x00, 0xff, 0xfe, 0x00, 0x9f];
+
+    fn consul_value(value: &str, modify_index: u64) -> String {
+        return consul_bytes(value.as_bytes(), modify_index);
+    }
+
+    fn consul_bytes(value: &[u8], modify_index: u64) -> String {
+        return format!(
+            r#"[{{"LockIndex":0,"Key":"app/config","Flags":0,"Value":"{}","CreateIndex":1,"ModifyIndex":{modify_index}}}]"#,
+            encode_b64(value)
+        );
+    }
+
+    /// Editor that replaces the edited file with `content`.
+    fn use_editor(content: &str) {
+        let editor = env::temp_dir().join(format!("kivi-editor-{}", std::process::id()));
+        fs::write(&editor, format!("#!/bin/sh\nprintf '{content}' > \"$1\"\n")).unwrap();
+        fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).unwrap();
+        env::set_var("VISUAL", &editor);
+        env::set_var("EDITOR", &editor);
+    }
+
+    #[test]
+    fn test_inline_edit_of_changed_value_is_a_conflict() {
+        use_editor("edited");
+        let read = consul_value("old", 5);
+        let read_again = consul_value("theirs", 7);
+        let server = StubServer::start(&[(200, &read), (200, "false"), (200, &read_again)]);
+        let config = ConsulCommandConfig {
+            url: server.url.to_owned(),
+            ..Default::default()
+        };
+        let prompt = RecordingPrompt::default();
+        let write_res = ConsulRemote::new(&config, AgentBuilder::new()).write_path(
+            WriteCmdConfig {
+                is_inline_edit: true,
+                data_file: None,
+                path: "app/config".to_owned(),
+            },
+            &prompt,
+        );
+        assert!(matches!(write_res, Err(KVError::ConflictErr)));
+        assert_eq!(
+            vec![("edited".to_owned(), "theirs".to_owned())],
+            *prompt.conflicts.borrow()
+        );
+
+        let requests = server.requests();
+        assert_eq!(
+            ("PUT", "/v1/kv/app/config?cas=5", "edited"),
+            (
+                requests[1].method.as_str(),
+                requests[1].path.as_str(),
+                requests[1].body.as_str()
+            )
+        );
+    }
+
+    #[test]
+    fn test_binary_values_are_read_and_written_as_is() {
+        let file = env::temp_dir().join(format!("kivi-binary-{}", std::process::id()));
+        fs::write(&file, BINARY_VALUE).unwrap();
+        let read = consul_bytes(BINARY_VALUE, 3);
+        let server = StubServer::start(&[(200, &read), (200, "true")]);
+        let config = ConsulCommandConfig {
+            url: server.url.to_owned(),
+            ..Default::default()
+        };
+        let consul = ConsulRemote::new(&config, AgentBuilder::new());
+        let value = consul
+            .read_path(ReadCmdConfig {
+                is_encoded: false,
+                is_hex: false,
+                out_file: None,
+                version: None,
+                path: "app/config".to_owned(),
+            })
+            .unwrap();
+        let write_res = consul.write_path(
+            WriteCmdConfig {
+                is_inline_edit: false,
+                data_file: Some(file.display().to_string()),
+                path: "app/config".to_owned(),
+            },
+            &RecordingPrompt::default(),
+        );
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(BINARY_VALUE, value.value);
+        assert!(write_res.is_ok());
+        assert_eq!(BINARY_VALUE, server.requests()[1].raw_body);
+    }
+}
@@ -0,0 +1,235 @@
+mod common;
+
+#[cfg(test)]
+#[path = "../src/dispatcher.rs"]
+mod test {
+    use std::env;
+    use std::fs;
+
+    use kivi_rs::cli_def::{ApplyCmdConfig, KVSubs, ReadCmdConfig};
+    use kivi_rs::dispatcher::{copy_prefix, dispatch};
+    use kivi_rs::kv_commons::{KVRemoteSource, NoPrompt};
+    use kivi_rs::kv_uri::KVUri;
+    use kivi_rs::output::{CommandOutput, OutputFormat, Renderer};
+    use kivi_rs::utils::decode_hex;
+
+    use crate::common::MemoryRemote;
+
+    fn uri(uri: &str) -> KVUri {
+        return uri.parse().unwrap();
+    }
+
+    #[test]
+    fn test_copy_prefix_replaces_prefix_and_skips_folders() {
+        let source = MemoryRemote::with_values(&[
+            ("svc/meta/", ""),
+            ("svc/meta/top", "1"),
+            ("svc/meta/webdav/", ""),
+            ("svc/meta/webdav/config", "2"),
+        ]);
+        let target = MemoryRemote::default();
+        let output = copy_prefix(
+            &source,
+            &uri("consul://127.0.0.1/svc/meta"),
+            &target,
+            &uri("etcd://127.0.0.1/backup"),
+        )
+        .unwrap();
+
+        let CommandOutput::Copy(report) = &output else {
+            panic!("copy report expected");
+        };
+        assert_eq!(vec!["backup/top", "backup/webdav/config"], report.copied);
+        assert!(report.failed.is_empty());
+        assert_eq!(Some("2".to_owned()), target.value("backup/webdav/config"));
+        assert_eq!(2, target.values.borrow().len());
+        assert_eq!(0, output.exit_code());
+    }
+
+    #[test]
+    fn test_copy_prefix_skips_sibling_prefix() {
+        let source =
+            MemoryRemote::with_values(&[("app/config/x", "1"), ("app/configuration/x", "2")]);
+        let target = MemoryRemote::default();
+        let output = copy_prefix(
+            &source,
+            &uri("consul://127.0.0.1/app/config"),
+            &target,
+            &uri("consul://127.0.0.1/backup"),
+        )
+        .unwrap();
+
+        let CommandOutput::Copy(report) = &output else {
+            panic!("copy report expected");
+        };
+        assert_eq!(vec!["backup/x"], report.copied);
+        assert_eq!(Some("1".to_owned()), target.value("backup/x"));
+        assert_eq!(1, target.values.borrow().len());
+    }
+
+    #[test]
+    fn test_copy_prefix_reports_failed_keys() {
+        let source = MemoryRemote::with_values(&[("svc/a", "1"), ("svc/b", "2")]);
+        let target = MemoryRemote {
+            failing: vec!["copy/a".to_owned()],
+            ..Default::default()
+        };
+        let output = copy_prefix(
+            &source,
+            &uri("consul://127.0.0.1/svc"),
+            &target,
+            &uri("consul://127.0.0.1/copy"),
+        )
+        .unwrap();
+
+        let CommandOutput::Copy(report) = &output else {
+            panic!("copy report expected");
+        };
+        assert_eq!(vec!["copy/b"], report.copied);
+        assert_eq!("copy/a", report.failed[0].path);
+        assert_eq!(1, output.exit_code());
+    }
+
+    #[test]
+    fn test_apply_with_prune_to_missing_prefix() {
+        let dir = env::temp_dir().join(format!("kivi-apply-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("webdav")).unwrap();
+        fs::write(dir.join("webdav/config"), "{}").unwrap();
+        let remote = MemoryRemote::with_values(&[("other/key", "1")]);
+        let command = KVSubs::Apply(ApplyCmdConfig {
+            prefix: "svc/meta".to_owned(),
+            prune: true,
+            dry_run: false,
+            assume_yes: true,
+            dir: dir.to_owned(),
+        });
+        let output = dispatch(&remote, &command, &NoPrompt);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let Ok(CommandOutput::Plan { plan, applied }) = output else {
+            panic!("applied plan expected");
+        };
+        assert!(applied);
+        assert_eq!(
+            "1 to create, 0 to change, 0 to delete, 0 unchanged",
+            plan.summary()
+        );
+        assert_eq!(
+            Some("{}".to_owned()),
+            remote.value("svc/meta/webdav/config")
+        );
+        assert_eq!(Some("1".to_owned()), remote.value("other/key"));
+    }
+
+    #[test]
+    fn test_apply_with_prune_keeps_folder_keys() {
+        let dir = env::temp_dir().join(format!("kivi-prune-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("webdav")).unwrap();
+        fs::write(dir.join("webdav/config"), "{}").unwrap();
+        let remote = MemoryRemote::with_values(&[
+            ("svc/meta/", ""),
+            ("svc/meta/webdav/", ""),
+            ("svc/meta/webdav/config", "{}"),
+            ("svc/meta/old", "1"),
+        ]);
+        let command = KVSubs::Apply(ApplyCmdConfig {
+            prefix: "svc/meta".to_owned(),
+            prune: true,
+            dry_run: false,
+            assume_yes: true,
+            dir: dir.to_owned(),
+        });
+        let output = dispatch(&remote, &command, &NoPrompt);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let Ok(CommandOutput::Plan { plan, .. }) = output else {
+            panic!("applied plan expected");
+        };
+        assert_eq!(
+            "0 to create, 0 to change, 1 to delete, 1 unchanged",
+            plan.summary()
+        );
+        assert_eq!(None, remote.value("svc/meta/old"));
+        assert_eq!(Some("".to_owned()), remote.value("svc/meta/webdav/"));
+    }
+
+    #[test]
+    fn test_dry_run_is_not_applied() {
+        let dir = env::temp_dir().join(format!("kivi-dry-run-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("top"), "2").unwrap();
+        let remote = MemoryRemote::with_values(&[("svc/top", "1")]);
+        let command = KVSubs::Apply(ApplyCmdConfig {
+            prefix: "svc".to_owned(),
+            prune: false,
+            dry_run: true,
+            assume_yes: false,
+            dir: dir.to_owned(),
+        });
+        let output = dispatch(&remote, &command, &NoPrompt);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(
+            output,
+            Ok(CommandOutput::Plan { applied: false, .. })
+        ));
+        assert_eq!(Some("1".to_owned()), remote.value("svc/top"));
+    }
+
+    #[test]
+    fn test_binary_value_round_trips_through_out_file_and_hex() {
+        let binary: Vec<u8> = vec![0x1f, 0x8b, 0x00, 0xff, 0x9f];
+        let remote = MemoryRemote::default();
+        remote
+            .write_value("certs/ca.der", binary.to_owned(), None)
+            .unwrap();
+        let out_file = env::temp_dir().join(format!("kivi-out-test-{}", std::process::id()));
+        let read = |is_hex: bool, out_file: Option<String>| {
+            KVSubs::Read(ReadCmdConfig {
+                is_encoded: false,
+                is_hex,
+                out_file,
+                version: None,
+                path: "certs/ca.der".to_owned(),
+            })
+        };
+
+        dispatch(
+            &remote,
+            &read(false, Some(out_file.display().to_string())),
+            &NoPrompt,
+        )
+        .unwrap();
+        let written = fs::read(&out_file).unwrap();
+        fs::remove_file(&out_file).unwrap();
+        assert_eq!(binary, written);
+
+        let Ok(CommandOutput::Value(hex)) = dispatch(&remote, &read(true, None), &NoPrompt) else {
+            panic!("value expected");
+        };
+        assert_eq!("1f8b00ff9f", hex.as_text().unwrap());
+        assert_eq!(Some(binary), decode_hex("1f8b00ff9f"));
+    }
+
+    #[test]
+    fn test_binary_value_is_not_printed_raw() {
+        let remote = MemoryRemote::default();
+        remote.write_value("blob", vec![0xff, 0xfe], None).unwrap();
+        let output = dispatch(
+            &remote,
+            &KVSubs::Read(ReadCmdConfig {
+                is_encoded: false,
+                is_hex: false,
+                out_file: None,
+                version: None,
+                path: "blob".to_owned(),
+            }),
+            &NoPrompt,
+        )
+        .unwrap();
+        assert_eq!(
+            "<binary_value:2 B, use --encoded, --hex or --out>\n",
+            Renderer::new("memory", OutputFormat::Raw).render(&output)
+        );
+    }
+}
@@ -0,0 +1,118 @@
+mod common;
+
+#[cfg(test)]
+#[path = "../src/etcd_remote.rs"]
+mod test {
+    use kivi_rs::cli_def::ReadCmdConfig;
+    use kivi_rs::etcd_remote::{create_children_linter, EtcdCommandConfig, EtcdRemote};
+    use kivi_rs::kv_commons::{KVError, KVRemoteSource};
+    use kivi_rs::utils::encode_b64;
+    use ureq::AgentBuilder;
+
+    use crate::common::StubServer;
+
+    fn keys(keys: &[&str]) -> Vec<String> {
+        return keys.iter().map(|key| key.to_string()).collect();
+    }
+
+    #[test]
+    fn test_children_linter_cuts_keys_after_first_separator() {
+        let linter = create_children_linter("svc/meta/".to_owned());
+        let input = keys(&[
+            "svc/meta/service-a/config",
+            "svc/meta/service-a/secret",
+            "svc/meta/top",
+            "svc/meta/webdav/config",
+        ]);
+        assert_eq!(keys(&["service-a/", "top", "webdav/"]), linter(input));
+    }
+
+    #[test]
+    fn test_children_linter_skips_prefix_itself() {
+        let linter = create_children_linter("svc/".to_owned());
+        assert_eq!(keys(&["top"]), linter(keys(&["svc/", "svc/top"])));
+    }
+
+    #[test]
+    fn test_children_linter_with_root_prefix() {
+        let linter = create_children_linter("".to_owned());
+        let input = keys(&["other", "svc/meta/top", "svc/top"]);
+        assert_eq!(keys(&["other", "svc/"]), linter(input));
+    }
+
+    #[test]
+    fn test_rejected_token_is_renewed_and_request_retried() {
+        let range = format!(
+            r#"{{"kvs":[{{"key":"{}","value":"{}","mod_revision":"7"}}]}}"#,
+            encode_b64(b"svc/top"),
+            encode_b64(b"1")
+        );
+        let server = StubServer::start(&[
+            (200, r#"{"token":"first"}"#),
+            (
+                401,
+                r#"{"error":"etcdserver: invalid auth token","code":16}"#,
+            ),
+            (200, r#"{"token":"second"}"#),
+            (200, &range),
+        ]);
+        let config = EtcdCommandConfig {
+            url: server.url.to_owned(),
+            token: Some(encode_b64(b"root:secret")),
+            ..Default::default()
+        };
+        let value = EtcdRemote::new(&config, AgentBuilder::new())
+            .read_path(ReadCmdConfig {
+                is_encoded: false,
+                is_hex: false,
+                out_file: None,
+                version: None,
+                path: "svc/top".to_owned(),
+            })
+            .unwrap();
+        assert_eq!(b"1".to_vec(), value.value);
+        assert_eq!(Some(7), value.version);
+
+        let requests = server.requests();
+        let calls: Vec<(&str, Option<&str>)> = requests
+            .iter()
+            .map(|request| (request.path.as_str(), request.header("authorization")))
+            .collect();
+        assert_eq!(
+            vec![
+                ("/v3/auth/authenticate", None),
+                ("/v3/kv/range", Some("first")),
+                ("/v3/auth/authenticate", None),
+                ("/v3/kv/range", Some("second")),
+            ],
+            calls
+        );
+        assert_eq!(r#"{"name":"root","password":"secret"}"#, requests[2].body);
+    }
+
+    #[test]
+    fn test_write_with_stale_revision_is_a_conflict() {
+        let server = StubServer::start(&[(200, r#"{"succeeded":false}"#)]);
+        let config = EtcdCommandConfig {
+            url: server.url.to_owned(),
+            ..Default::default()
+        };
+        let write_res = EtcdRemote::new(&config, AgentBuilder::new()).write_value(
+            "svc/top",
+            b"2".to_vec(),
+            Some(7),
+        );
+        assert!(matches!(write_res, Err(KVError::ConflictErr)));
+
+        let requests = server.requests();
+        assert_eq!("/v3/kv/txn", requests[0].path);
+        let key = encode_b64(b"svc/top");
+        assert_eq!(
+            format!(
+                r#"{{"compare":[{{"key":"{key}","target":"MOD","result":"EQUAL","mod_revision":"7"}}],"success":[{{"request_put":{{"key":"{key}","value":"{}"}}}}]}}"#,
+                encode_b64(b"2")
+            ),
+            requests[0].body
+        );
+    }
+}
@@ -0,0 +1,85 @@
+mod common;
+
+#[cfg(test)]
+#[path = "../src/kv_browse.rs"]
+mod test {
+    use kivi_rs::kv_browse::Browser;
+    use kivi_rs::kv_commons::NoPrompt;
+
+    use crate::common::MemoryRemote;
+
+    fn remote() -> MemoryRemote {
+        return MemoryRemote::with_values(&[
+            ("svc/meta/service-a/config", "{}"),
+            ("svc/meta/top", "1"),
+            ("svc/other", "2"),
+        ]);
+    }
+
+    fn paths(browser: &Browser) -> Vec<String> {
+        return browser.rows().into_iter().map(|row| row.path).collect();
+    }
+
+    fn selected_path(browser: &Browser) -> String {
+        return browser.selected_row().unwrap().path;
+    }
+
+    #[test]
+    fn test_rows_of_expanded_folders_follow_them() {
+        let remote = remote();
+        let mut browser = Browser::new(&remote, &NoPrompt, "svc");
+        assert_eq!(vec!["svc/meta/", "svc/other"], paths(&browser));
+
+        browser.expand();
+        let rows = browser.rows();
+        assert_eq!(
+            vec![
+                "svc/meta/",
+                "svc/meta/service-a/",
+                "svc/meta/top",
+                "svc/other"
+            ],
+            paths(&browser)
+        );
+        assert_eq!(
+            ("service-a/", 1, true),
+            (rows[1].name.as_str(), rows[1].depth, rows[1].is_folder)
+        );
+        assert_eq!(
+            ("top", 1, false),
+            (rows[2].name.as_str(), rows[2].depth, rows[2].is_folder)
+        );
+    }
+
+    #[test]
+    fn test_collapse_selects_parent_then_collapses_it() {
+        let remote = remote();
+        let mut browser = Browser::new(&remote, &NoPrompt, "svc/");
+        browser.expand();
+        browser.select(2);
+        assert_eq!("svc/meta/top", selected_path(&browser));
+
+        browser.collapse();
+        assert_eq!("svc/meta/", selected_path(&browser));
+        assert_eq!(4, browser.rows().len());
+
+        browser.collapse();
+        assert_eq!(vec!["svc/meta/", "svc/other"], paths(&browser));
+    }
+
+    #[test]
+    fn test_search_wraps_around() {
+        let remote = remote();
+        let mut browser = Browser::new(&remote, &NoPrompt, "svc");
+        browser.expand();
+        browser.select(3);
+
+        browser.find("top");
+        assert_eq!("svc/meta/top", selected_path(&browser));
+        browser.next_match();
+        assert_eq!("svc/meta/top", selected_path(&browser));
+
+        browser.find("missing");
+        assert_eq!("svc/meta/top", selected_path(&browser));
+    }
+}
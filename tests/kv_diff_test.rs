@@ -0,0 +1,119 @@
+mod common;
+
+#[cfg(test)]
+#[path = "../src/kv_diff.rs"]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use kivi_rs::kv_commons::KVValue;
+    use kivi_rs::kv_diff::{relative_values, DiffAction, DiffSide, KVDiff};
+    use kivi_rs::output::{CommandOutput, DIFF_FOUND_EXIT_CODE};
+    use serde_json::Map;
+    use ureq::AgentBuilder;
+
+    use crate::common::{kv_value, MemoryRemote};
+
+    #[test]
+    fn test_relative_values_skip_sibling_prefix_and_folders() {
+        let remote = MemoryRemote::with_values(&[
+            ("svc/meta/", ""),
+            ("svc/meta/top", "1"),
+            ("svc/meta/webdav/config", "2"),
+            ("svc/metadata/top", "3"),
+        ]);
+        let values = relative_values(&remote, "svc/meta").unwrap();
+
+        let paths: Vec<&str> = values.iter().map(|value| value.path.as_str()).collect();
+        assert_eq!(vec!["top", "webdav/config"], paths);
+    }
+
+    #[test]
+    fn test_diff_entries_are_sorted_by_key() {
+        let left = vec![kv_value("z", "1"), kv_value("b", "1\n")];
+        let right = vec![kv_value("a", "1"), kv_value("b", "2\n")];
+        let diff = KVDiff::build("left", left, "right", right);
+
+        let entries: Vec<(&str, DiffAction)> = diff
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry.action))
+            .collect();
+        assert_eq!(
+            vec![
+                ("a", DiffAction::Added),
+                ("b", DiffAction::Changed),
+                ("z", DiffAction::Removed),
+            ],
+            entries
+        );
+        assert_eq!(
+            "+ a\n~ b\n--- a/b\n+++ b/b\n@@ -1 +1 @@\n-1\n+2\n- z\n",
+            diff.render()
+        );
+    }
+
+    #[test]
+    fn test_changed_binary_value_has_no_text_diff() {
+        let binary = |bytes: &[u8]| KVValue {
+            path: "cert.der".to_owned(),
+            value: bytes.to_vec(),
+            version: None,
+            metadata: Map::new(),
+        };
+        let diff = KVDiff::build(
+            "left",
+            vec![binary(&[0xff, 0x00])],
+            "right",
+            vec![binary(&[0xfe, 0x00])],
+        );
+        assert_eq!(DiffAction::Changed, diff.entries[0].action);
+        assert_eq!(None, diff.entries[0].diff);
+    }
+
+    #[test]
+    fn test_equal_sides_exit_with_success() {
+        let diff = KVDiff::build(
+            "left",
+            vec![kv_value("a", "1")],
+            "right",
+            vec![kv_value("a", "1")],
+        );
+        assert!(!diff.has_changes());
+        assert_eq!(1, diff.unchanged);
+        assert_eq!(0, CommandOutput::Diff(diff).exit_code());
+
+        let diff = KVDiff::build("left", vec![], "right", vec![kv_value("a", "1")]);
+        assert_eq!(DIFF_FOUND_EXIT_CODE, CommandOutput::Diff(diff).exit_code());
+    }
+
+    #[test]
+    fn test_diff_side_is_remote_only_with_scheme() {
+        assert!(matches!(
+            "consul://127.0.0.1/svc".parse::<DiffSide>(),
+            Ok(DiffSide::Remote(uri)) if uri.prefix == "svc"
+        ));
+        assert_eq!(
+            Ok(DiffSide::Directory(PathBuf::from("config/svc"))),
+            "config/svc".parse::<DiffSide>()
+        );
+        assert!("zookeeper://127.0.0.1/svc".parse::<DiffSide>().is_err());
+    }
+
+    #[test]
+    fn test_directory_side_keys_are_relative() {
+        let dir = env::temp_dir().join(format!("kivi-diff-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("webdav")).unwrap();
+        fs::write(dir.join("top"), "1").unwrap();
+        fs::write(dir.join("webdav/config"), "2").unwrap();
+
+        let values = DiffSide::Directory(dir.to_owned())
+            .read_values(AgentBuilder::new(), None)
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let paths: Vec<&str> = values.iter().map(|value| value.path.as_str()).collect();
+        assert_eq!(vec!["top", "webdav/config"], paths);
+    }
+}
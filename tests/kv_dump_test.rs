@@ -0,0 +1,85 @@
+#[cfg(test)]
+#[path = "../src/kv_dump.rs"]
+mod test {
+    use kivi_rs::kv_commons::{KVError, KVValue};
+    use kivi_rs::kv_dump::{export_dump, parse_dump, DumpFormat, KIVI_DUMP_VERSION};
+    use serde_json::{Map, Value};
+
+    fn values() -> Vec<KVValue> {
+        return vec![
+            KVValue {
+                path: "svc/meta/top".to_owned(),
+                value: b"{\"port\":80}\n".to_vec(),
+                version: Some(3),
+                metadata: Map::from_iter([("flags".to_owned(), Value::from(42))]),
+            },
+            KVValue {
+                path: "svc/meta/cert.der".to_owned(),
+                value: vec![0x30, 0x82, 0xff, 0x00],
+                version: None,
+                metadata: Map::new(),
+            },
+        ];
+    }
+
+    fn round_trip(format: DumpFormat, prefix: Option<&str>) -> Vec<KVValue> {
+        let dump = export_dump(format, "consul", "svc/meta/", values());
+        return parse_dump(dump.to_string().as_bytes(), prefix).unwrap();
+    }
+
+    #[test]
+    fn test_consul_dump_round_trip_keeps_bytes_and_flags() {
+        let parsed = round_trip(DumpFormat::Consul, None);
+        let expected = values();
+        assert_eq!(expected.len(), parsed.len());
+        for (expected, parsed) in expected.iter().zip(&parsed) {
+            assert_eq!(expected.path, parsed.path);
+            assert_eq!(expected.value, parsed.value);
+            assert_eq!(None, parsed.version);
+        }
+        assert_eq!(Some(&Value::from(42)), parsed[0].metadata.get("flags"));
+        assert_eq!(Some(&Value::from(0)), parsed[1].metadata.get("flags"));
+    }
+
+    #[test]
+    fn test_kivi_dump_round_trip_keeps_values() {
+        let parsed = round_trip(DumpFormat::Kivi, None);
+        let expected = values();
+        assert_eq!(expected.len(), parsed.len());
+        for (expected, parsed) in expected.iter().zip(&parsed) {
+            assert_eq!(expected.path, parsed.path);
+            assert_eq!(expected.value, parsed.value);
+            assert_eq!(expected.metadata, parsed.metadata);
+        }
+    }
+
+    #[test]
+    fn test_dump_round_trip_with_prefix() {
+        let parsed = round_trip(DumpFormat::Consul, Some("restored"));
+        assert_eq!("restored/svc/meta/top", parsed[0].path);
+    }
+
+    #[test]
+    fn test_parse_dump_rejects_newer_kivi_dump() {
+        let dump = format!(
+            r#"{{"kivi_dump": {}, "backend": "etcd", "prefix": "", "values": []}}"#,
+            KIVI_DUMP_VERSION + 1
+        );
+        assert!(matches!(
+            parse_dump(dump.as_bytes(), None),
+            Err(KVError::UnsupportedErr)
+        ));
+    }
+
+    #[test]
+    fn test_parse_dump_rejects_malformed_input() {
+        assert!(matches!(
+            parse_dump(b"not a dump", None),
+            Err(KVError::ValueFormatErr)
+        ));
+        assert!(matches!(
+            parse_dump(br#"[{"key": "a", "value": "%%%"}]"#, None),
+            Err(KVError::ValueFormatErr)
+        ));
+    }
+}
@@ -0,0 +1,52 @@
+mod common;
+
+#[cfg(test)]
+#[path = "../src/kv_exec.rs"]
+mod test {
+    use kivi_rs::cli_def::ExecCmdConfig;
+    use kivi_rs::kv_commons::KVError;
+    use kivi_rs::kv_exec::{exec_watched, exec_with_env};
+    use kivi_rs::output::CommandOutput;
+
+    use crate::common::MemoryRemote;
+
+    fn exec_cmd(watch: bool) -> ExecCmdConfig {
+        return ExecCmdConfig {
+            prefix: "app/config".to_owned(),
+            keep_prefix: false,
+            no_sanitize: false,
+            no_upcase: false,
+            watch,
+            command: vec!["true".to_owned()],
+        };
+    }
+
+    #[test]
+    fn test_keys_with_the_same_variable_name_are_refused() {
+        let remote = MemoryRemote::with_values(&[
+            ("app/config/db-url", "postgres://a"),
+            ("app/config/db_url", "postgres://b"),
+        ]);
+        let Err(KVError::ConfigErr(reason)) = exec_with_env(&remote, &exec_cmd(false)) else {
+            panic!("config error expected");
+        };
+        assert_eq!(
+            "keys 'app/config/db-url' and 'app/config/db_url' are both passed as DB_URL",
+            reason
+        );
+    }
+
+    #[test]
+    fn test_watch_needs_watchable_remote() {
+        let remote = MemoryRemote::with_values(&[("app/config/db-url", "postgres://a")]);
+        assert!(matches!(
+            exec_watched(&remote, &exec_cmd(true), |_| {}),
+            Err(KVError::UnsupportedErr)
+        ));
+    }
+
+    #[test]
+    fn test_exited_command_passes_its_exit_code() {
+        assert_eq!(3, CommandOutput::Exited { code: 3 }.exit_code());
+    }
+}
@@ -0,0 +1,138 @@
+mod common;
+
+#[cfg(test)]
+#[path = "../src/kv_plan.rs"]
+mod test {
+    use kivi_rs::cli_def::ListCmdConfig;
+    use kivi_rs::kv_commons::{KVError, KVRemoteSource, NoPrompt};
+    use kivi_rs::kv_plan::{KVPlan, PlanAction};
+
+    use crate::common::{kv_value, MemoryRemote};
+
+    fn actions(plan: &KVPlan) -> Vec<(&str, PlanAction)> {
+        return plan
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry.action))
+            .collect();
+    }
+
+    #[test]
+    fn test_build_compares_desired_values_with_remote() {
+        let remote = MemoryRemote::with_values(&[("svc/same", "1"), ("svc/old", "1")]);
+        let desired = vec![
+            kv_value("svc/same", "1"),
+            kv_value("svc/old", "2"),
+            kv_value("svc/new", "3"),
+        ];
+        let plan = KVPlan::build(&remote, desired).unwrap();
+
+        assert_eq!(
+            vec![
+                ("svc/same", PlanAction::Unchanged),
+                ("svc/old", PlanAction::Change),
+                ("svc/new", PlanAction::Create),
+            ],
+            actions(&plan)
+        );
+        assert_eq!(Some(2), plan.entries[1].remote_version);
+        assert!(plan.has_changes());
+        assert_eq!(
+            "1 to create, 1 to change, 0 to delete, 1 unchanged",
+            plan.summary()
+        );
+    }
+
+    #[test]
+    fn test_plan_without_changes() {
+        let remote = MemoryRemote::with_values(&[("svc/same", "1")]);
+        let plan = KVPlan::build(&remote, vec![kv_value("svc/same", "1")]).unwrap();
+        assert!(!plan.has_changes());
+        assert_eq!("= svc/same", plan.render());
+    }
+
+    #[test]
+    fn test_apply_goes_on_after_failed_key() {
+        let remote = MemoryRemote {
+            failing: vec!["svc/a".to_owned()],
+            ..Default::default()
+        };
+        let desired = vec![kv_value("svc/a", "1"), kv_value("svc/b", "2")];
+        let mut plan = KVPlan::build(&remote, desired).unwrap();
+        plan.apply(&remote);
+
+        assert_eq!(Some("2".to_owned()), remote.value("svc/b"));
+        assert_eq!(1, plan.failed.len());
+        assert_eq!("svc/a", plan.failed[0].path);
+        assert_eq!(
+            "2 to create, 0 to change, 0 to delete, 0 unchanged, 1 failed",
+            plan.summary()
+        );
+    }
+
+    #[test]
+    fn test_apply_refuses_change_of_value_modified_after_build() {
+        let remote = MemoryRemote::with_values(&[("svc/a", "1")]);
+        let mut plan = KVPlan::build(&remote, vec![kv_value("svc/a", "2")]).unwrap();
+        remote.put("svc/a", "concurrent");
+        plan.apply(&remote);
+
+        assert_eq!(Some("concurrent".to_owned()), remote.value("svc/a"));
+        assert_eq!(KVError::ConflictErr.to_string(), plan.failed[0].error);
+    }
+
+    #[test]
+    fn test_confirm_is_skipped_only_with_assume_yes() {
+        let plan = KVPlan::default();
+        assert!(plan.confirm(true, &NoPrompt).is_ok());
+        assert!(matches!(
+            plan.confirm(false, &NoPrompt),
+            Err(KVError::AbortedErr)
+        ));
+    }
+
+    #[test]
+    fn test_created_keys_are_written_only_if_absent() {
+        let remote = MemoryRemote::default();
+        let mut plan = KVPlan::build(&remote, vec![kv_value("svc/new", "mine")]).unwrap();
+        assert_eq!(Some(0), plan.entries[0].remote_version);
+
+        remote.put("svc/new", "concurrent");
+        plan.apply(&remote);
+
+        assert_eq!(
+            vec![("svc/new".to_owned(), Some(0))],
+            *remote.calls.borrow()
+        );
+        assert_eq!(Some("concurrent".to_owned()), remote.value("svc/new"));
+        assert_eq!(KVError::ConflictErr.to_string(), plan.failed[0].error);
+    }
+
+    #[test]
+    fn test_prune_deletes_only_unplanned_keys() {
+        let remote = MemoryRemote::with_values(&[("svc/keep", "1"), ("svc/stale", "1")]);
+        let mut plan = KVPlan::build(&remote, vec![kv_value("svc/keep", "1")]).unwrap();
+        plan.prune(
+            remote
+                .read_all(ListCmdConfig {
+                    prefix: "svc/".to_owned(),
+                })
+                .unwrap(),
+        );
+
+        assert_eq!(
+            vec![
+                ("svc/keep", PlanAction::Unchanged),
+                ("svc/stale", PlanAction::Delete)
+            ],
+            actions(&plan)
+        );
+        plan.apply(&remote);
+        assert!(plan.failed.is_empty());
+        assert_eq!(
+            vec![("svc/stale".to_owned(), Some(2))],
+            *remote.calls.borrow()
+        );
+        assert_eq!(None, remote.value("svc/stale"));
+    }
+}
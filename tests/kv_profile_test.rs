@@ -0,0 +1,132 @@
+#[cfg(test)]
+#[path = "../src/kv_profile.rs"]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::OnceLock;
+
+    use clap::{CommandFactory, FromArgMatches};
+    use kivi_rs::cli_def::{Cli, KVSubs, Subs};
+    use kivi_rs::consul_remote::ConsulCommandConfig;
+    use kivi_rs::kv_commons::KVError;
+    use kivi_rs::kv_profile::{apply_profile, uri_remote_config};
+    use kivi_rs::kv_uri::RemoteConfig;
+
+    const CONFIG: &str = r#"
+[profiles.prod]
+backend = "consul"
+url = "https://consul.prod:8501"
+token = "profile-token"
+prefix = "team-a"
+
+[profiles.prod.tls]
+ca_cert = "/profile/ca.pem"
+tls_server_name = "consul.prod"
+
+[profiles.etcd]
+backend = "etcd"
+"#;
+
+    /// Config file shared by every test of the file, tests run in parallel
+    fn use_config() {
+        static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+        let path = CONFIG_PATH.get_or_init(|| {
+            let path =
+                env::temp_dir().join(format!("kivi-profile-test-{}.toml", std::process::id()));
+            fs::write(&path, CONFIG).unwrap();
+            return path;
+        });
+        env::set_var("KIVI_CONFIG", path);
+    }
+
+    fn parse(args: &[&str]) -> Result<Cli, KVError> {
+        use_config();
+        let matches = Cli::command().try_get_matches_from(args).unwrap();
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+        apply_profile(&mut cli, &matches)?;
+        return Ok(cli);
+    }
+
+    fn consul_config(cli: &Cli) -> &ConsulCommandConfig {
+        return match &cli.command {
+            Some(Subs::Consul(cfg)) => cfg,
+            _ => panic!("consul command expected"),
+        };
+    }
+
+    #[test]
+    fn test_profile_fills_settings_and_scopes_paths() {
+        let cli = parse(&["kivi", "--profile", "prod", "consul", "list", "svc"]).unwrap();
+        let cfg = consul_config(&cli);
+
+        assert_eq!("https://consul.prod:8501", cfg.url);
+        assert_eq!(Some("profile-token".to_owned()), cfg.token);
+        assert_eq!(Some(PathBuf::from("/profile/ca.pem")), cfg.tls.ca_cert);
+        assert!(matches!(
+            &cfg.kv_command,
+            Some(KVSubs::List(list_cmd)) if list_cmd.prefix == "team-a/svc"
+        ));
+    }
+
+    #[test]
+    fn test_command_line_wins_over_profile() {
+        let cli = parse(&[
+            "kivi",
+            "--profile",
+            "prod",
+            "consul",
+            "--url",
+            "http://127.0.0.1:8500",
+            "--token-file",
+            "/cli/token",
+            "--tls-server-name",
+            "cli.local",
+            "read",
+            "/svc/top",
+        ])
+        .unwrap();
+        let cfg = consul_config(&cli);
+
+        assert_eq!("http://127.0.0.1:8500", cfg.url);
+        // token sources replace each other as a whole
+        assert_eq!(None, cfg.token);
+        assert_eq!(Some(PathBuf::from("/cli/token")), cfg.token_file);
+        // TLS settings are taken one by one
+        assert_eq!(Some("cli.local".to_owned()), cfg.tls.tls_server_name);
+        assert_eq!(Some(PathBuf::from("/profile/ca.pem")), cfg.tls.ca_cert);
+        // absolute paths are not scoped
+        assert!(matches!(
+            &cfg.kv_command,
+            Some(KVSubs::Read(read_cmd)) if read_cmd.path == "/svc/top"
+        ));
+    }
+
+    #[test]
+    fn test_profile_of_other_backend_is_refused() {
+        let err = parse(&["kivi", "--profile", "etcd", "consul", "list", "svc"]).err();
+        assert!(matches!(err, Some(KVError::ConfigErr(reason)) if reason.contains("is for etcd")));
+
+        let err = parse(&["kivi", "--profile", "stage", "consul", "list", "svc"]).err();
+        assert!(
+            matches!(err, Some(KVError::ConfigErr(reason)) if reason.contains("unknown profile"))
+        );
+    }
+
+    #[test]
+    fn test_uri_keeps_address_and_takes_profile_credentials() {
+        use_config();
+        let uri = "consul://127.0.0.1:18500/svc".parse().unwrap();
+        let Ok(RemoteConfig::Consul(cfg)) = uri_remote_config(&uri, Some("prod")) else {
+            panic!("consul config expected");
+        };
+        assert_eq!("http://127.0.0.1:18500", cfg.url);
+        assert_eq!(Some("profile-token".to_owned()), cfg.token);
+        assert_eq!(Some("consul.prod".to_owned()), cfg.tls.tls_server_name);
+
+        assert!(matches!(
+            uri_remote_config(&uri, Some("etcd")),
+            Err(KVError::ConfigErr(_))
+        ));
+    }
+}
@@ -0,0 +1,152 @@
+mod common;
+
+#[cfg(test)]
+#[path = "../src/kv_secret.rs"]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    use kivi_rs::cli_def::ReadCmdConfig;
+    use kivi_rs::consul_remote::{ConsulCommandConfig, ConsulRemote};
+    use kivi_rs::kv_commons::{KVError, KVRemoteSource};
+    use kivi_rs::kv_secret::{KeyringEntry, TokenSource};
+    use ureq::AgentBuilder;
+
+    use crate::common::StubServer;
+
+    fn temp_path(name: &str) -> PathBuf {
+        return env::temp_dir().join(format!("kivi-{name}-{}", std::process::id()));
+    }
+
+    fn read_cfg() -> ReadCmdConfig {
+        return ReadCmdConfig {
+            is_encoded: false,
+            is_hex: false,
+            out_file: None,
+            version: None,
+            path: "app/config".to_owned(),
+        };
+    }
+
+    #[test]
+    fn test_sources_are_picked_in_order() {
+        let file = Some(PathBuf::from("token"));
+        let command = Some("pass show consul".to_owned());
+        let keyring = Some(KeyringEntry {
+            service: "kivi".to_owned(),
+            user: "prod".to_owned(),
+        });
+        assert_eq!(
+            Some(TokenSource::File(PathBuf::from("token"))),
+            TokenSource::pick(&file, &command, &keyring)
+        );
+        assert_eq!(
+            Some(TokenSource::Command("pass show consul".to_owned())),
+            TokenSource::pick(&None, &command, &keyring)
+        );
+        assert_eq!(
+            Some(TokenSource::Keyring(keyring.clone().unwrap())),
+            TokenSource::pick(&None, &None, &keyring)
+        );
+        assert_eq!(None, TokenSource::pick(&None, &None, &None));
+    }
+
+    #[test]
+    fn test_file_token_is_trimmed() {
+        let file = temp_path("token-file");
+        fs::write(&file, "  s3cr3t\n").unwrap();
+        let token = TokenSource::File(file.to_owned()).resolve();
+        fs::write(&file, "\n").unwrap();
+        let empty = TokenSource::File(file.to_owned()).resolve();
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(Ok("s3cr3t".to_owned()), token);
+        assert_eq!(
+            Err(format!("token file {} gave an empty token", file.display())),
+            empty
+        );
+    }
+
+    #[test]
+    fn test_command_errors_name_the_reason() {
+        assert_eq!(
+            Err("token command 'echo locked >&2; exit 1' failed: locked".to_owned()),
+            TokenSource::Command("echo locked >&2; exit 1".to_owned()).resolve()
+        );
+        assert_eq!(
+            Err("token command 'exit 3' failed: exit status: 3".to_owned()),
+            TokenSource::Command("exit 3".to_owned()).resolve()
+        );
+        assert_eq!(
+            Err("token command 'printf \"\"' gave an empty token".to_owned()),
+            TokenSource::Command("printf \"\"".to_owned()).resolve()
+        );
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn test_keyring_entry_is_looked_up_with_secret_tool() {
+        let bin = temp_path("keyring-bin");
+        fs::create_dir_all(&bin).unwrap();
+        let secret_tool = bin.join("secret-tool");
+        fs::write(
+            &secret_tool,
+            "#!/bin/sh\n[ \"$*\" = 'lookup service kivi username prod' ] && echo keyring-token\n",
+        )
+        .unwrap();
+        fs::set_permissions(&secret_tool, fs::Permissions::from_mode(0o755)).unwrap();
+        let path = env::var_os("PATH").unwrap_or_default();
+        let mut dirs = vec![bin.to_owned()];
+        dirs.extend(env::split_paths(&path));
+        env::set_var("PATH", env::join_paths(dirs).unwrap());
+
+        let entry = |user: &str| {
+            TokenSource::Keyring(KeyringEntry {
+                service: "kivi".to_owned(),
+                user: user.to_owned(),
+            })
+        };
+        assert_eq!(Ok("keyring-token".to_owned()), entry("prod").resolve());
+        assert_eq!(
+            Err("keyring entry kivi/dev failed: exit status: 1".to_owned()),
+            entry("dev").resolve()
+        );
+        fs::remove_dir_all(&bin).unwrap();
+    }
+
+    #[test]
+    fn test_token_is_read_with_first_request() {
+        let file = temp_path("consul-token");
+        fs::write(&file, "file-token\n").unwrap();
+        let server = StubServer::start(&[(404, "")]);
+        let config = ConsulCommandConfig {
+            url: server.url.to_owned(),
+            token_file: Some(file.to_owned()),
+            ..Default::default()
+        };
+        let read_res = ConsulRemote::new(&config, AgentBuilder::new()).read_path(read_cfg());
+        fs::remove_file(&file).unwrap();
+
+        assert!(matches!(read_res, Err(KVError::NoValueErr(_))));
+        let requests = server.requests();
+        assert_eq!(Some("file-token"), requests[0].header("x-consul-token"));
+    }
+
+    #[test]
+    fn test_failed_token_command_stops_the_request() {
+        let server = StubServer::start(&[]);
+        let config = ConsulCommandConfig {
+            url: server.url.to_owned(),
+            token_command: Some("exit 1".to_owned()),
+            ..Default::default()
+        };
+        let read_res = ConsulRemote::new(&config, AgentBuilder::new()).read_path(read_cfg());
+
+        let Err(KVError::CredentialsErr(reason)) = read_res else {
+            panic!("credentials error expected");
+        };
+        assert_eq!("token command 'exit 1' failed: exit status: 1", reason);
+    }
+}
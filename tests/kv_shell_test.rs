@@ -0,0 +1,70 @@
+mod common;
+
+#[cfg(test)]
+#[path = "../src/kv_shell.rs"]
+mod test {
+    use kivi_rs::kv_shell::{change_dir, complete_line, is_kept_in_history, resolve_path};
+
+    use crate::common::MemoryRemote;
+
+    fn replacements(remote: &MemoryRemote, cwd: &str, line: &str) -> (usize, Vec<String>) {
+        let (start, candidates) = complete_line(remote, cwd, line);
+        return (
+            start,
+            candidates
+                .into_iter()
+                .map(|candidate| candidate.replacement)
+                .collect(),
+        );
+    }
+
+    #[test]
+    fn test_cd_resolves_relative_absolute_and_parent_paths() {
+        assert_eq!("svc/meta/", change_dir("svc/", "meta"));
+        assert_eq!("svc/meta/webdav/", change_dir("svc/", "./meta/webdav/"));
+        assert_eq!("svc/", change_dir("svc/meta/", ".."));
+        assert_eq!("", change_dir("svc/", "../.."));
+        assert_eq!("other/", change_dir("svc/meta/", "/other"));
+        assert_eq!("", change_dir("svc/meta/", "/"));
+        assert_eq!("svc/top", resolve_path("svc/meta/", "../top"));
+    }
+
+    #[test]
+    fn test_first_word_completes_commands() {
+        let remote = MemoryRemote::default();
+        assert_eq!(
+            (0, vec!["cd ".to_owned(), "cat ".to_owned()]),
+            replacements(&remote, "", "c")
+        );
+    }
+
+    #[test]
+    fn test_paths_complete_against_current_prefix() {
+        let remote = MemoryRemote::with_values(&[
+            ("svc/meta/top", "1"),
+            ("svc/meta/webdav/config", "2"),
+            ("svc/other", "3"),
+        ]);
+        assert_eq!(
+            (4, vec!["top".to_owned()]),
+            replacements(&remote, "svc/meta/", "cat t")
+        );
+        assert_eq!(
+            (4, vec!["meta/webdav/".to_owned()]),
+            replacements(&remote, "svc/", "cat meta/w")
+        );
+        assert_eq!(
+            (4, vec!["../other".to_owned()]),
+            replacements(&remote, "svc/meta/", "cat ../o")
+        );
+        assert_eq!((3, vec![]), replacements(&remote, "svc/", "ls missing/"));
+    }
+
+    #[test]
+    fn test_put_lines_are_left_out_of_history() {
+        assert!(is_kept_in_history("put-like svc/top"));
+        assert!(is_kept_in_history("ls svc/"));
+        assert!(!is_kept_in_history("put svc/token s3cret"));
+        assert!(!is_kept_in_history("   put svc/token 's3cret value'"));
+    }
+}
@@ -0,0 +1,126 @@
+mod common;
+
+#[cfg(test)]
+#[path = "../src/kv_template.rs"]
+mod test {
+    use std::env;
+    use std::fs;
+
+    use kivi_rs::cli_def::RenderCmdConfig;
+    use kivi_rs::dispatcher::watch_template;
+    use kivi_rs::kv_commons::KVError;
+    use kivi_rs::kv_template::Template;
+
+    use crate::common::MemoryRemote;
+
+    fn parse_error(source: &str) -> String {
+        return match Template::parse(source) {
+            Err(KVError::TemplateErr(reason)) => reason,
+            other => panic!("template error expected, got {other:?}"),
+        };
+    }
+
+    fn render(source: &str, remote: &MemoryRemote) -> Result<String, KVError> {
+        return Template::parse(source)?
+            .render(remote)
+            .map(|rendered| rendered.text);
+    }
+
+    #[test]
+    fn test_parse_errors_name_the_problem() {
+        assert_eq!(
+            "'{{' is not closed",
+            parse_error("port: {{ key \"svc/port\"")
+        );
+        assert_eq!("'end' without 'range'", parse_error("{{ end }}"));
+        assert_eq!(
+            "'range' without 'end'",
+            parse_error("{{ range ls \"svc\" }}{{ .Key }}")
+        );
+        assert_eq!("empty action", parse_error("{{ }}"));
+        assert!(parse_error("{{ key \"svc/port }}").starts_with("string is not closed"));
+        assert_eq!("empty action", parse_error("{{ | key \"svc\" }}"));
+    }
+
+    #[test]
+    fn test_render_keys_and_defaults() {
+        let remote = MemoryRemote::with_values(&[("svc/port", "8080")]);
+        assert_eq!(
+            Ok("port=8080 host=localhost".to_owned()),
+            render(
+                "port={{ key \"svc/port\" }} host={{ keyOrDefault \"svc/host\" \"localhost\" }}",
+                &remote
+            )
+            .map_err(|err| err.to_string())
+        );
+        assert!(matches!(
+            render("{{ key \"svc/host\" }}", &remote),
+            Err(KVError::NoValueErr(_))
+        ));
+    }
+
+    #[test]
+    fn test_render_range_and_json() {
+        let remote = MemoryRemote::with_values(&[
+            ("svc/meta/a", "{\"listen\": {\"port\": 80}}"),
+            ("svc/meta/b", "{\"listen\": {\"port\": 81}}"),
+        ]);
+        let source = "{{ range ls \"svc/meta\" -}}\n{{ .Key }}={{ .Value | json \"listen.port\" }}\n{{ end }}";
+        assert_eq!(
+            "a=80\nb=81\n",
+            render(source, &remote)
+                .map_err(|err| err.to_string())
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_render_records_inputs() {
+        let remote = MemoryRemote::with_values(&[("svc/port", "1"), ("app/name", "x")]);
+        let template =
+            Template::parse("{{ key \"svc/port\" }}{{ range tree \"app\" }}{{ end }}").unwrap();
+        let rendered = template.render(&remote).unwrap();
+        assert_eq!(vec!["svc/port", "app/"], rendered.inputs);
+    }
+
+    #[test]
+    fn test_render_errors() {
+        let remote = MemoryRemote::with_values(&[("svc/port", "not json")]);
+        let render_error = |source: &str| match render(source, &remote) {
+            Err(KVError::TemplateErr(reason)) => reason,
+            other => panic!("template error expected, got {other:?}"),
+        };
+        assert_eq!(
+            "unknown function 'secret'",
+            render_error("{{ secret \"a\" }}")
+        );
+        assert!(
+            render_error("{{ key \"svc/port\" | json \"a\" }}").starts_with("value is not JSON")
+        );
+        assert_eq!(
+            ".Key and .Value can only be used in 'range'",
+            render_error("{{ .Key }}")
+        );
+    }
+
+    #[test]
+    fn test_watch_refuses_keys_without_common_prefix() {
+        let remote = MemoryRemote::with_values(&[("svc/port", "1"), ("app/name", "x")]);
+        let template = env::temp_dir().join(format!("kivi-watch-{}.tpl", std::process::id()));
+        fs::write(&template, "{{ key \"svc/port\" }} {{ key \"app/name\" }}").unwrap();
+        let render_cmd = RenderCmdConfig {
+            out_file: None,
+            watch: true,
+            interval: 1,
+            template: template.to_owned(),
+        };
+        let mut outputs = 0;
+        let result = watch_template(&remote, &render_cmd, &mut |_| {
+            outputs += 1;
+            return Ok(());
+        });
+        fs::remove_file(&template).unwrap();
+        assert!(matches!(result, Err(KVError::UnsupportedErr)));
+        assert_eq!(0, outputs);
+    }
+}
@@ -0,0 +1,80 @@
+#[cfg(test)]
+#[path = "../src/kv_tls.rs"]
+mod test {
+    use std::env;
+    use std::path::PathBuf;
+
+    use kivi_rs::kv_tls::{TlsEnv, TlsOptions};
+
+    /// Variables are unique per test, as tests run in parallel
+    fn tls_env(test: &str) -> TlsEnv {
+        let name = |suffix: &str| -> &'static str {
+            return format!("KIVI_TEST_{test}_{suffix}").leak();
+        };
+        return TlsEnv {
+            ca_cert: name("CACERT"),
+            client_cert: name("CERT"),
+            client_key: name("KEY"),
+            server_name: Some(name("SERVER_NAME")),
+            skip_verify: Some(name("SKIP_VERIFY")),
+        };
+    }
+
+    #[test]
+    fn test_command_line_wins_over_env() {
+        let tls_env = tls_env("CLI");
+        env::set_var(tls_env.ca_cert, "/env/ca.pem");
+        env::set_var(tls_env.server_name.unwrap(), "env.local");
+        let options = TlsOptions {
+            ca_cert: Some(PathBuf::from("/cli/ca.pem")),
+            ..Default::default()
+        }
+        .with_env(&tls_env);
+
+        assert_eq!(Some(PathBuf::from("/cli/ca.pem")), options.ca_cert);
+        assert_eq!(Some("env.local".to_owned()), options.tls_server_name);
+        assert_eq!(None, options.client_cert);
+    }
+
+    #[test]
+    fn test_empty_env_is_ignored() {
+        let tls_env = tls_env("EMPTY");
+        env::set_var(tls_env.client_cert, "");
+        env::set_var(tls_env.client_key, "");
+        assert_eq!(
+            TlsOptions::default(),
+            TlsOptions::default().with_env(&tls_env)
+        );
+    }
+
+    #[test]
+    fn test_skip_verify_accepts_only_true_values() {
+        let tls_env = tls_env("SKIP");
+        let skip_verify = tls_env.skip_verify.unwrap();
+        for (value, expected) in [("1", true), ("TRUE", true), ("0", false), ("yes", false)] {
+            env::set_var(skip_verify, value);
+            let options = TlsOptions::default().with_env(&tls_env);
+            assert_eq!(expected, options.insecure_skip_verify, "{value}");
+        }
+
+        env::set_var(skip_verify, "0");
+        let options = TlsOptions {
+            insecure_skip_verify: true,
+            ..Default::default()
+        };
+        assert!(options.with_env(&tls_env).insecure_skip_verify);
+    }
+
+    #[test]
+    fn test_backend_without_env_variable() {
+        let tls_env = TlsEnv {
+            server_name: None,
+            skip_verify: None,
+            ..tls_env("NONE")
+        };
+        assert_eq!(
+            TlsOptions::default(),
+            TlsOptions::default().with_env(&tls_env)
+        );
+    }
+}
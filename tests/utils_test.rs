@@ -1,7 +1,10 @@
 #[cfg(test)]
 #[path = "../src/utils.rs"]
 mod test {
-    use kivi_rs::utils::{decodeb_64_safe, identity_str};
+    use kivi_rs::utils::{
+        decode_b64_bytes, decode_hex, decodeb_64_safe, encode_b64, identity_str, prefix_range_end,
+        prefix_range_start,
+    };
 
     #[test]
     fn test_identity_str() {
@@ -26,4 +29,41 @@ mod test {
         let input = "SGVsbG8sIHdvcmxkIQ";
         assert_eq!("", decodeb_64_safe(input));
     }
+
+    #[test]
+    fn test_encode_b64_roundtrip() {
+        let input = "Hello, world!";
+        assert_eq!("SGVsbG8sIHdvcmxkIQ==", encode_b64(input.as_bytes()));
+        assert_eq!(input, decodeb_64_safe(&encode_b64(input.as_bytes())));
+    }
+
+    #[test]
+    fn test_decode_b64_bytes_keeps_binary_data() {
+        let input = [0x1f, 0x8b, 0x08, 0x00, 0xff];
+        assert_eq!("", decodeb_64_safe(&encode_b64(&input)));
+        assert_eq!(input.to_vec(), decode_b64_bytes(&encode_b64(&input)));
+    }
+
+    #[test]
+    fn test_prefix_range_end_with_max_bytes() {
+        assert_eq!(
+            vec![b'a', b'c'],
+            prefix_range_end(&[b'a', b'b', 0xff, 0xff])
+        );
+        assert_eq!(vec![0], prefix_range_end(&[0xff, 0xff]));
+    }
+
+    #[test]
+    fn test_root_prefix_range_covers_whole_keyspace() {
+        assert_eq!("AA==", encode_b64(&prefix_range_start(b"")));
+        assert_eq!("AA==", encode_b64(&prefix_range_end(b"")));
+    }
+
+    #[test]
+    fn test_decode_hex() {
+        assert_eq!(Some(vec![0x30, 0x82, 0xff]), decode_hex("3082FF"));
+        assert_eq!(None, decode_hex("308"));
+        assert_eq!(None, decode_hex("+f"));
+        assert_eq!(None, decode_hex("é0"));
+    }
 }
@@ -0,0 +1,274 @@
+mod common;
+
+#[cfg(test)]
+#[path = "../src/vault_remote.rs"]
+mod test {
+    use std::thread;
+    use std::time::Duration;
+
+    use kivi_rs::cli_def::ReadCmdConfig;
+    use kivi_rs::kv_commons::{KVError, KVRemoteSource};
+    use kivi_rs::vault_remote::{
+        VaultAuthConfig, VaultAuthMethod, VaultCommandConfig, VaultRemote,
+    };
+    use ureq::AgentBuilder;
+
+    use crate::common::StubServer;
+
+    const KV_V1_MOUNT: &str = r#"{"data":{"path":"kv/","type":"kv","options":{"version":"1"}}}"#;
+    const KV_V2_MOUNT: &str =
+        r#"{"data":{"path":"secret/","type":"kv","options":{"version":"2"}}}"#;
+
+    fn login_response(token: &str, lease_duration: u64) -> String {
+        return format!(
+            r#"{{"auth":{{"client_token":"{token}","lease_duration":{lease_duration},"renewable":true}}}}"#
+        );
+    }
+
+    fn login_config(url: &str, auth: VaultAuthConfig) -> VaultCommandConfig {
+        return VaultCommandConfig {
+            url: url.to_owned(),
+            auth,
+            ..Default::default()
+        };
+    }
+
+    fn config(url: &str) -> VaultCommandConfig {
+        return VaultCommandConfig {
+            url: url.to_owned(),
+            token: Some("supplied".to_owned()),
+            ..Default::default()
+        };
+    }
+
+    #[test]
+    fn test_versioned_write_to_kv_v1_is_refused() {
+        let server = StubServer::start(&[(200, KV_V1_MOUNT)]);
+        let config = config(&server.url);
+        let write_res = VaultRemote::new(&config, AgentBuilder::new()).write_value(
+            "kv/app",
+            br#"{"user":"app"}"#.to_vec(),
+            Some(0),
+        );
+        assert!(matches!(write_res, Err(KVError::UnsupportedErr)));
+
+        let requests = server.requests();
+        assert_eq!(1, requests.len());
+        assert_eq!("/v1/sys/internal/ui/mounts/kv/app", requests[0].path);
+    }
+
+    #[test]
+    fn test_versioned_write_sends_check_and_set() {
+        let server = StubServer::start(&[(200, KV_V2_MOUNT), (200, "{}")]);
+        let config = config(&server.url);
+        VaultRemote::new(&config, AgentBuilder::new())
+            .write_value("secret/app", br#"{"user":"app"}"#.to_vec(), Some(3))
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(
+            ("POST", "/v1/secret/data/app"),
+            (requests[1].method.as_str(), requests[1].path.as_str())
+        );
+        assert_eq!(
+            r#"{"options":{"cas":3},"data":{"user":"app"}}"#,
+            requests[1].body
+        );
+        assert_eq!(Some("supplied"), requests[1].header("x-vault-token"));
+    }
+
+    #[test]
+    fn test_stale_check_and_set_is_a_conflict() {
+        let server = StubServer::start(&[
+            (200, KV_V2_MOUNT),
+            (
+                400,
+                r#"{"errors":["check-and-set parameter did not match the current version"]}"#,
+            ),
+        ]);
+        let config = config(&server.url);
+        let write_res = VaultRemote::new(&config, AgentBuilder::new()).write_value(
+            "secret/app",
+            br#"{"user":"app"}"#.to_vec(),
+            Some(3),
+        );
+        assert!(matches!(write_res, Err(KVError::ConflictErr)));
+    }
+
+    #[test]
+    fn test_version_is_read_with_query_parameter() {
+        let secret = r#"{"data":{"data":{"user":"app"},"metadata":{"version":2}}}"#;
+        let server = StubServer::start(&[(200, KV_V2_MOUNT), (200, secret)]);
+        let config = config(&server.url);
+        let value = VaultRemote::new(&config, AgentBuilder::new())
+            .read_path(ReadCmdConfig {
+                is_encoded: false,
+                is_hex: false,
+                out_file: None,
+                version: Some(2),
+                path: "secret/app".to_owned(),
+            })
+            .unwrap();
+        assert_eq!(Some(2), value.version);
+
+        let requests = server.requests();
+        assert_eq!("/v1/secret/data/app?version=2", requests[1].path);
+    }
+
+    #[test]
+    fn test_undelete_and_destroy_send_versions() {
+        let server =
+            StubServer::start(&[(200, KV_V2_MOUNT), (204, ""), (200, KV_V2_MOUNT), (204, "")]);
+        let config = config(&server.url);
+        let vault = VaultRemote::new(&config, AgentBuilder::new());
+        vault.undelete_versions("secret/app", &[1, 2]).unwrap();
+        vault.destroy_versions("secret/app", &[3]).unwrap();
+
+        let requests = server.requests();
+        let writes: Vec<(&str, &str, &str)> = [&requests[1], &requests[3]]
+            .iter()
+            .map(|request| {
+                (
+                    request.method.as_str(),
+                    request.path.as_str(),
+                    request.body.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("POST", "/v1/secret/undelete/app", r#"{"versions":[1,2]}"#),
+                ("POST", "/v1/secret/destroy/app", r#"{"versions":[3]}"#),
+            ],
+            writes
+        );
+    }
+
+    #[test]
+    fn test_undelete_on_kv_v1_is_refused() {
+        let server = StubServer::start(&[(200, KV_V1_MOUNT)]);
+        let config = config(&server.url);
+        let undelete_res =
+            VaultRemote::new(&config, AgentBuilder::new()).undelete_versions("kv/app", &[1]);
+        assert!(matches!(undelete_res, Err(KVError::UnsupportedErr)));
+    }
+
+    #[test]
+    fn test_approle_login_token_is_used_for_requests() {
+        let login = login_response("approle-token", 3600);
+        let server = StubServer::start(&[(200, &login), (200, KV_V2_MOUNT)]);
+        let config = login_config(
+            &server.url,
+            VaultAuthConfig {
+                method: VaultAuthMethod::Approle,
+                role_id: Some("role".to_owned()),
+                secret_id: Some("secret".to_owned()),
+                ..Default::default()
+            },
+        );
+        VaultRemote::new(&config, AgentBuilder::new())
+            .detect_mount("secret/app")
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!("/v1/auth/approle/login", requests[0].path);
+        assert_eq!(
+            r#"{"role_id":"role","secret_id":"secret"}"#,
+            requests[0].body
+        );
+        assert_eq!(None, requests[0].header("x-vault-token"));
+        assert_eq!(Some("approle-token"), requests[1].header("x-vault-token"));
+    }
+
+    #[test]
+    fn test_userpass_login_at_custom_path() {
+        let login = login_response("userpass-token", 3600);
+        let server = StubServer::start(&[(200, &login), (200, KV_V2_MOUNT)]);
+        let config = login_config(
+            &server.url,
+            VaultAuthConfig {
+                method: VaultAuthMethod::Userpass,
+                auth_path: Some("/team-users/".to_owned()),
+                username: Some("alice".to_owned()),
+                password: Some("pw".to_owned()),
+                ..Default::default()
+            },
+        );
+        VaultRemote::new(&config, AgentBuilder::new())
+            .detect_mount("secret/app")
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!("/v1/auth/team-users/login/alice", requests[0].path);
+        assert_eq!(r#"{"password":"pw"}"#, requests[0].body);
+        assert_eq!(Some("userpass-token"), requests[1].header("x-vault-token"));
+    }
+
+    #[test]
+    fn test_userpass_login_needs_password() {
+        let server = StubServer::start(&[]);
+        let config = login_config(
+            &server.url,
+            VaultAuthConfig {
+                method: VaultAuthMethod::Userpass,
+                username: Some("alice".to_owned()),
+                ..Default::default()
+            },
+        );
+        let login_res = VaultRemote::new(&config, AgentBuilder::new()).login();
+        assert!(matches!(login_res, Err(KVError::AuthenticationErr(_))));
+    }
+
+    #[test]
+    fn test_expiring_login_token_is_renewed() {
+        let login = login_response("approle-token", 1);
+        let renewed = login_response("renewed-token", 3600);
+        let server = StubServer::start(&[
+            (200, &login),
+            (200, KV_V2_MOUNT),
+            (200, &renewed),
+            (200, KV_V2_MOUNT),
+        ]);
+        let config = login_config(
+            &server.url,
+            VaultAuthConfig {
+                method: VaultAuthMethod::Approle,
+                role_id: Some("role".to_owned()),
+                ..Default::default()
+            },
+        );
+        let vault = VaultRemote::new(&config, AgentBuilder::new());
+        vault.detect_mount("secret/app").unwrap();
+        // two thirds of the lease pass
+        thread::sleep(Duration::from_millis(800));
+        vault.detect_mount("secret/app").unwrap();
+
+        let requests = server.requests();
+        assert_eq!("/v1/auth/token/renew-self", requests[2].path);
+        assert_eq!(Some("approle-token"), requests[2].header("x-vault-token"));
+        assert_eq!(Some("renewed-token"), requests[3].header("x-vault-token"));
+    }
+
+    #[test]
+    fn test_supplied_token_is_never_renewed() {
+        let server = StubServer::start(&[(200, KV_V2_MOUNT), (200, KV_V2_MOUNT)]);
+        let config = config(&server.url);
+        let vault = VaultRemote::new(&config, AgentBuilder::new());
+        vault.detect_mount("secret/app").unwrap();
+        vault.detect_mount("secret/app").unwrap();
+
+        let calls: Vec<(String, Option<String>)> = server
+            .requests()
+            .into_iter()
+            .map(|request| {
+                let token = request.header("x-vault-token").map(str::to_owned);
+                (request.path, token)
+            })
+            .collect();
+        let mount_call = (
+            "/v1/sys/internal/ui/mounts/secret/app".to_owned(),
+            Some("supplied".to_owned()),
+        );
+        assert_eq!(vec![mount_call.clone(), mount_call], calls);
+    }
+}